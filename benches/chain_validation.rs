@@ -0,0 +1,40 @@
+use criterion::{criterion_group, criterion_main, Criterion};
+use rust_blockchain::model::{Address, Block, Blockchain, Transaction, BLOCK_SUBSIDY};
+
+const CHAIN_LENGTH: u64 = 2000;
+
+fn create_blocks() -> Vec<Block> {
+    let blockchain = Blockchain::new(0);
+
+    for index in 1..=CHAIN_LENGTH {
+        let last_block = blockchain.get_last_block();
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: Address::default(),
+            amount: BLOCK_SUBSIDY,
+        };
+
+        let mut block = Block::new(index, 0, last_block.hash, vec![coinbase]);
+        block.timestamp = last_block.timestamp + 1;
+        block.hash = block.calculate_hash();
+
+        blockchain.add_block(block).unwrap();
+    }
+
+    blockchain.get_all_blocks()
+}
+
+fn verify_hashes(c: &mut Criterion) {
+    let blocks = create_blocks();
+
+    c.bench_function("verify hashes sequentially", |b| {
+        b.iter(|| Blockchain::verify_hashes(&blocks, false).unwrap())
+    });
+
+    c.bench_function("verify hashes in parallel", |b| {
+        b.iter(|| Blockchain::verify_hashes(&blocks, true).unwrap())
+    });
+}
+
+criterion_group!(benches, verify_hashes);
+criterion_main!(benches);
@@ -1,95 +1,1472 @@
-use actix_web::{web, App, HttpResponse, HttpServer, Responder};
+use std::{
+    fmt,
+    fs::File,
+    io::BufReader,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use actix_cors::Cors;
+use actix_web::{
+    body::{BoxBody, MessageBody},
+    dev::{ServerHandle, ServiceRequest, ServiceResponse},
+    http::{
+        header::{ContentType, HeaderName, HeaderValue},
+        Method, StatusCode,
+    },
+    middleware::{from_fn, Condition, Next},
+    rt, web, App, HttpMessage, HttpRequest, HttpResponse, HttpServer, Responder, ResponseError,
+};
+use actix_ws::Message;
 use anyhow::Result;
-use log::info;
+use log::{debug, info, warn};
+use rustls::{Certificate, PrivateKey, ServerConfig};
+use rustls_pemfile::Item;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
+use tokio::sync::broadcast::{self, error::RecvError};
 
 use crate::{
-    model::{Block, Blockchain, Transaction, TransactionPool},
-    util::{execution::Runnable, Context},
+    miner::{Miner, MinerError},
+    model::{
+        difficulty_to_target, serde_number, target_to_compact, AccountBalanceMapError, Address,
+        AddressError, Amount, Block, BlockHash, Blockchain, BlockchainError, PeerAddressError,
+        PeerAddresses, PeerStatus, PeerStatuses, Placement, PoolError, RejectedBlocks, Transaction,
+        TransactionHash, TransactionPool, TransactionVec, BLOCK_VERSION,
+    },
+    peer::{NodeVersion, Peer},
+    util::{
+        execution::Runnable,
+        request_id::{generate_request_id, is_valid_request_id, DEFAULT_REQUEST_ID_HEADER},
+        termination::{is_shutdown_requested, ShutdownFlag},
+        Context, Metrics, RateLimiter,
+    },
 };
 
+mod blocks_cache;
+mod rpc;
+
+use blocks_cache::BlocksCache;
+
+const SHUTDOWN_POLLING_MS: u64 = 100;
+
+const DEFAULT_RECENT_BLOCKS: usize = 20;
+const MAX_RECENT_BLOCKS: usize = 100;
+
+// Number of blocks buffered for slow WebSocket clients before they start missing blocks
+const NEW_BLOCKS_CAPACITY: usize = 64;
+
+// Time given to in-flight requests before the workers are stopped on shutdown
+const SHUTDOWN_TIMEOUT_SECS: u64 = 1;
+
+#[derive(Error, Debug)]
+pub enum ApiError {
+    #[error("No certificate found in `{0}`")]
+    CertificateNotFound(String),
+
+    #[error("No private key found in `{0}`")]
+    PrivateKeyNotFound(String),
+}
+
 struct ApiState {
     blockchain: Blockchain,
     pool: TransactionPool,
+    rejected_blocks: RejectedBlocks,
+    peer_statuses: PeerStatuses,
+    metrics: Metrics,
+    peer_addresses: PeerAddresses,
+    numbers_as_strings: bool,
+    stall_threshold_ms: u64,
+    finality_depth: u64,
+    json_rpc_enabled: bool,
+    blocks_cache: BlocksCache,
+    new_blocks: broadcast::Sender<Block>,
+    miner: Arc<Miner>,
+    peer: Option<Arc<Peer>>,
+    block_rate_limiter: RateLimiter,
+    request_id_header: String,
+}
+
+// Failed requests answer with the name of the error and its message, so clients can tell why
+// the request failed, like {"error": "InvalidIndex", "message": "Invalid index"}
+#[derive(Debug)]
+struct RequestError {
+    status: StatusCode,
+    error: String,
+    message: String,
+}
+
+#[derive(Serialize)]
+struct ErrorBody<'a> {
+    error: &'a str,
+    message: &'a str,
+}
+
+impl RequestError {
+    fn new(status: StatusCode, error: &str, message: impl ToString) -> RequestError {
+        RequestError {
+            status,
+            error: error.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl ResponseError for RequestError {
+    fn status_code(&self) -> StatusCode {
+        self.status
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        HttpResponse::build(self.status).json(ErrorBody {
+            error: &self.error,
+            message: &self.message,
+        })
+    }
+}
+
+impl<E: Into<anyhow::Error>> From<E> for RequestError {
+    fn from(error: E) -> RequestError {
+        let error = error.into();
+        let (status, name) = describe_error(&error);
+
+        RequestError::new(status, &name, error)
+    }
+}
+
+// Name of an enum variant, without its fields
+fn variant_name(error: &impl fmt::Debug) -> String {
+    let debug = format!("{:?}", error);
+
+    debug
+        .split(['(', ' ', '{'])
+        .next()
+        .unwrap_or_default()
+        .to_string()
+}
+
+fn describe_error(error: &anyhow::Error) -> (StatusCode, String) {
+    if let Some(error) = error.downcast_ref::<BlockchainError>() {
+        return (StatusCode::BAD_REQUEST, variant_name(error));
+    }
+
+    if let Some(error) = error.downcast_ref::<AccountBalanceMapError>() {
+        let status = match error {
+            AccountBalanceMapError::BalanceStore(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        return (status, variant_name(error));
+    }
+
+    if let Some(error) = error.downcast_ref::<PoolError>() {
+        return (StatusCode::BAD_REQUEST, variant_name(error));
+    }
+
+    if let Some(error) = error.downcast_ref::<AddressError>() {
+        return (StatusCode::BAD_REQUEST, variant_name(error));
+    }
+
+    if let Some(error) = error.downcast_ref::<PeerAddressError>() {
+        let status = match error {
+            PeerAddressError::DuplicatePeer => StatusCode::CONFLICT,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        return (status, variant_name(error));
+    }
+
+    if let Some(error) = error.downcast_ref::<MinerError>() {
+        let status = match error {
+            MinerError::BlockNotMined(_) | MinerError::StaleTip(_) => StatusCode::CONFLICT,
+            MinerError::ShutdownRequested(_) => StatusCode::SERVICE_UNAVAILABLE,
+            _ => StatusCode::BAD_REQUEST,
+        };
+        return (status, variant_name(error));
+    }
+
+    // anything else is a failure of the node, like a store that cannot be read
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        String::from("InternalError"),
+    )
+}
+
+#[derive(Serialize)]
+struct Status {
+    height: u64,
+    difficulty: u32,
+    peer_count: usize,
+    mempool_size: usize,
+    stalled: bool,
+    // hashes per second over the last mined blocks, 0 until a block is mined
+    hashrate: f64,
+}
+
+#[derive(Serialize)]
+struct PeerInfo {
+    address: String,
+    #[serde(flatten)]
+    status: PeerStatus,
+}
+
+#[derive(Deserialize)]
+struct NewPeer {
+    address: String,
+}
+
+#[derive(Deserialize)]
+struct BlocksQuery {
+    from: Option<usize>,
+    limit: Option<usize>,
+}
+
+#[derive(Deserialize)]
+struct RecentBlocksQuery {
+    n: Option<usize>,
+}
+
+#[derive(Serialize)]
+struct BlockSummary {
+    height: u64,
+    short_hash: String,
+    timestamp: i64,
+    transaction_count: usize,
+    miner: Option<Address>,
+}
+
+impl BlockSummary {
+    fn new(block: &Block) -> BlockSummary {
+        let hash = format!("{:064x}", block.hash);
+
+        BlockSummary {
+            height: block.index,
+            short_hash: hash[..16].to_string(),
+            timestamp: block.timestamp,
+            transaction_count: block.transactions.len(),
+            miner: block
+                .transactions
+                .first()
+                .map(|coinbase| coinbase.recipient.clone()),
+        }
+    }
+}
+
+// Fields of a block without its hash
+#[derive(Deserialize)]
+struct CandidateBlock {
+    #[serde(
+        default = "default_block_version",
+        deserialize_with = "serde_number::deserialize"
+    )]
+    version: u32,
+    #[serde(deserialize_with = "serde_number::deserialize")]
+    index: u64,
+    #[serde(deserialize_with = "serde_number::deserialize")]
+    timestamp: i64,
+    // the difficulty expected for the index when missing
+    #[serde(default, deserialize_with = "serde_number::deserialize_option")]
+    difficulty: Option<u32>,
+    #[serde(deserialize_with = "serde_number::deserialize")]
+    nonce: u64,
+    previous_hash: BlockHash,
+    transactions: TransactionVec,
+}
+
+fn default_block_version() -> u32 {
+    BLOCK_VERSION
+}
+
+#[derive(Serialize)]
+struct BlockHashResult {
+    hash: BlockHash,
+}
+
+#[derive(Serialize)]
+struct AccountBalance {
+    address: Address,
+    balance: Amount,
+    spendable: Amount,
+}
+
+// Answer to a submitted transaction, with the hash to look it up once confirmed
+#[derive(Serialize)]
+struct SubmittedTransaction {
+    hash: TransactionHash,
+}
+
+#[derive(Serialize)]
+struct ConfirmedTransaction {
+    block_index: u64,
+    // blocks on top of the containing one, counting it
+    confirmations: u64,
+    // deep enough in the chain to be considered irreversible
+    #[serde(rename = "final")]
+    is_final: bool,
+    transaction: Transaction,
+}
+
+// Outcome of each transaction of a batch, in the order they were submitted
+#[derive(Serialize)]
+struct BatchItemResult {
+    accepted: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+// Tells the sender of a block whether it was already known, so it stops sending it
+#[derive(Serialize)]
+struct BlockAcknowledgement {
+    known: bool,
+}
+
+#[derive(Serialize)]
+struct ExplorerBlock {
+    block: Block,
+    // zero padded, without the 0x prefix
+    hash: String,
+    leading_zeros: u32,
+    time_since_previous_ms: Option<i64>,
+    coinbase_reward: Option<Amount>,
+    transaction_count: usize,
+}
+
+#[derive(Serialize)]
+struct Difficulty {
+    difficulty: u32,
+    target: BlockHash,
+    // compact form of the target, as hex like in Bitcoin tools
+    bits: String,
+}
+
+#[derive(Serialize)]
+struct ChainVerification {
+    valid: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    bad_block_index: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reason: Option<String>,
+}
+
+#[derive(Serialize)]
+struct PendingTransactions {
+    count: usize,
+    transactions: TransactionVec,
 }
 
 pub struct Api {
+    bind_address: IpAddr,
     port: u16,
     blockchain: Blockchain,
     pool: TransactionPool,
+    rejected_blocks: RejectedBlocks,
+    peer_statuses: PeerStatuses,
+    metrics: Metrics,
+    peer_addresses: PeerAddresses,
+    miner: Arc<Miner>,
+    // peers are sent accepted blocks right away when set
+    peer: Option<Arc<Peer>>,
+    numbers_as_strings: bool,
+    stall_threshold_ms: u64,
+    finality_depth: u64,
+    json_rpc_enabled: bool,
+    blocks_cache_ms: u64,
+    tls_cert_path: String,
+    tls_key_path: String,
+    cors_allowed_origins: Vec<String>,
+    base_path: String,
+    max_blocks_per_minute: u32,
+    request_id_header: String,
+    shutdown: ShutdownFlag,
 }
 
 impl Runnable for Api {
     fn run(&self) -> Result<()> {
-        let api_blockchain = self.blockchain.clone();
-        let api_pool = self.pool.clone();
+        let api_state = ApiState {
+            blockchain: self.blockchain.clone(),
+            pool: self.pool.clone(),
+            rejected_blocks: self.rejected_blocks.clone(),
+            peer_statuses: self.peer_statuses.clone(),
+            metrics: self.metrics.clone(),
+            peer_addresses: self.peer_addresses.clone(),
+            numbers_as_strings: self.numbers_as_strings,
+            stall_threshold_ms: self.stall_threshold_ms,
+            finality_depth: self.finality_depth,
+            json_rpc_enabled: self.json_rpc_enabled,
+            blocks_cache: BlocksCache::new(Duration::from_millis(self.blocks_cache_ms)),
+            new_blocks: forward_new_blocks(&self.blockchain),
+            miner: self.miner.clone(),
+            peer: self.peer.clone(),
+            block_rate_limiter: RateLimiter::new(self.max_blocks_per_minute),
+            request_id_header: self.request_id_header.clone(),
+        };
+        let api_shutdown = self.shutdown.clone();
+
+        // the config refuses to start with only one of the paths
+        let tls_config = match self.tls_cert_path.is_empty() && self.tls_key_path.is_empty() {
+            true => None,
+            false => Some(load_tls_config(&self.tls_cert_path, &self.tls_key_path)?),
+        };
 
-        start_server(self.port, api_blockchain, api_pool)
+        let cors_allowed_origins = self.cors_allowed_origins.clone();
+
+        start_server(
+            self.bind_address,
+            self.port,
+            self.base_path.clone(),
+            api_state,
+            tls_config,
+            cors_allowed_origins,
+            api_shutdown,
+        )
     }
 }
 
 impl Api {
     pub fn new(context: &Context) -> Api {
         Api {
+            bind_address: context.config.bind_address,
             port: context.config.port,
             blockchain: context.blockchain.clone(),
             pool: context.pool.clone(),
+            rejected_blocks: context.rejected_blocks.clone(),
+            peer_statuses: context.peer_statuses.clone(),
+            metrics: context.metrics.clone(),
+            peer_addresses: context.peer_addresses.clone(),
+            miner: Arc::new(Miner::new(context)),
+            peer: None,
+            numbers_as_strings: context.config.api_numbers_as_strings,
+            stall_threshold_ms: context.config.stall_threshold_ms,
+            finality_depth: context.config.finality_depth,
+            json_rpc_enabled: context.config.json_rpc_enabled,
+            blocks_cache_ms: context.config.blocks_cache_ms,
+            tls_cert_path: context.config.tls_cert_path.clone(),
+            tls_key_path: context.config.tls_key_path.clone(),
+            cors_allowed_origins: context.config.cors_allowed_origins.clone(),
+            base_path: context.config.api_base_path.clone(),
+            max_blocks_per_minute: context.config.max_blocks_per_minute,
+            request_id_header: context.config.request_id_header.clone(),
+            shutdown: context.shutdown.clone(),
         }
     }
+
+    // Shares the peer system, so it knows which peers already got the blocks posted to the api
+    pub fn with_peer(mut self, peer: Arc<Peer>) -> Api {
+        self.peer = Some(peer);
+
+        self
+    }
+
+    // Mines the blocks requested through the api with the background miner, one round at a time
+    pub fn with_miner(mut self, miner: Arc<Miner>) -> Api {
+        self.miner = miner;
+
+        self
+    }
+}
+
+// The certificate chain and the private key are read from PEM files, the key can be
+// either PKCS#8, PKCS#1 (RSA) or SEC1 (EC)
+fn load_tls_config(cert_path: &str, key_path: &str) -> Result<ServerConfig> {
+    let mut cert_reader = BufReader::new(File::open(cert_path)?);
+    let certificates: Vec<Certificate> = rustls_pemfile::certs(&mut cert_reader)?
+        .into_iter()
+        .map(Certificate)
+        .collect();
+
+    if certificates.is_empty() {
+        return Err(ApiError::CertificateNotFound(cert_path.to_string()).into());
+    }
+
+    let mut key_reader = BufReader::new(File::open(key_path)?);
+    let private_key = rustls_pemfile::read_all(&mut key_reader)?
+        .into_iter()
+        .find_map(|item| match item {
+            Item::PKCS8Key(key) | Item::RSAKey(key) | Item::ECKey(key) => Some(PrivateKey(key)),
+            _ => None,
+        })
+        .ok_or_else(|| ApiError::PrivateKeyNotFound(key_path.to_string()))?;
+
+    let config = ServerConfig::builder()
+        .with_safe_defaults()
+        .with_no_client_auth()
+        .with_single_cert(certificates, private_key)?;
+
+    Ok(config)
+}
+
+// Cross-origin requests are only allowed from the given origins, or from any origin with "*"
+fn create_cors(allowed_origins: &[String]) -> Cors {
+    let cors = Cors::default().allow_any_method().allow_any_header();
+
+    if allowed_origins.iter().any(|origin| origin == "*") {
+        return cors.allow_any_origin();
+    }
+
+    allowed_origins
+        .iter()
+        .fold(cors, |cors, origin| cors.allowed_origin(origin))
+}
+
+// JavaScript clients lose precision on numbers beyond 2^53, so they can be sent as strings instead
+fn numbers_to_strings(value: Value) -> Value {
+    match value {
+        Value::Number(number) => Value::String(number.to_string()),
+        Value::Array(values) => Value::Array(values.into_iter().map(numbers_to_strings).collect()),
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| (key, numbers_to_strings(value)))
+                .collect(),
+        ),
+        value => value,
+    }
+}
+
+fn json_response<T: Serialize>(state: &ApiState, body: &T) -> HttpResponse {
+    if !state.numbers_as_strings {
+        return HttpResponse::Ok().json(body);
+    }
+
+    match serde_json::to_value(body) {
+        Ok(value) => HttpResponse::Ok().json(numbers_to_strings(value)),
+        Err(error) => HttpResponse::InternalServerError().body(error.to_string()),
+    }
+}
+
+fn to_json_bytes<T: Serialize>(state: &ApiState, body: &T) -> serde_json::Result<Vec<u8>> {
+    match state.numbers_as_strings {
+        true => serde_json::to_vec(&numbers_to_strings(serde_json::to_value(body)?)),
+        false => serde_json::to_vec(body),
+    }
+}
+
+async fn get_status(state: web::Data<ApiState>) -> impl Responder {
+    let height = state.blockchain.get_last_block_index();
+    let stall_threshold = Duration::from_millis(state.stall_threshold_ms);
+    let stalled = state.stall_threshold_ms > 0 && state.blockchain.is_stalled(stall_threshold);
+
+    let status = Status {
+        height,
+        difficulty: state.blockchain.expected_difficulty(height + 1),
+        peer_count: state.peer_addresses.len(),
+        mempool_size: state.pool.len(),
+        stalled,
+        hashrate: state.metrics.hashrate.get(),
+    };
+
+    json_response(&state, &status)
+}
+
+// Lets peers check they validate blocks alike before syncing
+async fn get_version(state: web::Data<ApiState>) -> impl Responder {
+    json_response(&state, &NodeVersion::current())
+}
+
+// Difficulty required for the next block
+async fn get_difficulty(state: web::Data<ApiState>) -> impl Responder {
+    let next_index = state.blockchain.get_last_block_index() + 1;
+    let difficulty = state.blockchain.expected_difficulty(next_index);
+    let target = difficulty_to_target(difficulty);
+
+    let body = Difficulty {
+        difficulty,
+        target,
+        bits: format!("{:08x}", target_to_compact(target)),
+    };
+
+    json_response(&state, &body)
+}
+
+async fn get_peers(state: web::Data<ApiState>) -> impl Responder {
+    let peers: Vec<PeerInfo> = state
+        .peer_addresses
+        .get_all()
+        .into_iter()
+        .map(|address| PeerInfo {
+            status: state.peer_statuses.get(&address),
+            address,
+        })
+        .collect();
+
+    json_response(&state, &peers)
+}
+
+async fn add_peer(
+    state: web::Data<ApiState>,
+    peer_json: web::Json<NewPeer>,
+) -> Result<HttpResponse, RequestError> {
+    state.peer_addresses.add(&peer_json.address)?;
+    info!("Added new peer {}", peer_json.address);
+
+    Ok(HttpResponse::Ok().finish())
 }
 
-async fn get_blocks(state: web::Data<ApiState>) -> impl Responder {
+// Mining is CPU bound, so it runs on the blocking thread pool
+async fn mine_block(state: web::Data<ApiState>) -> Result<HttpResponse, RequestError> {
+    let miner = state.miner.clone();
+
+    let block = web::block(move || miner.mine_pending())
+        .await
+        .map_err(|error| {
+            RequestError::new(StatusCode::INTERNAL_SERVER_ERROR, "MiningFailed", error)
+        })??;
+
+    Ok(json_response(&state, &block))
+}
+
+async fn get_blocks(
+    state: web::Data<ApiState>,
+    query: web::Query<BlocksQuery>,
+) -> Result<HttpResponse, RequestError> {
     let blockchain = &state.blockchain;
-    let blocks = blockchain.get_all_blocks();
 
-    HttpResponse::Ok().json(&blocks)
+    // only the whole chain is cached, as that is what explorers keep asking for
+    if query.from.is_none() && query.limit.is_none() && state.blocks_cache.is_enabled() {
+        let last_block = blockchain.get_last_block();
+        if let Some(bytes) = state.blocks_cache.get(last_block.index, last_block.hash) {
+            return Ok(json_bytes_response(bytes));
+        }
+
+        // the blocks are cloned under the lock and serialized after releasing it
+        let blocks = blockchain.get_blocks_range(0, usize::MAX);
+        // the genesis block is always present
+        let last_block = blocks.last().unwrap();
+
+        let bytes = to_json_bytes(&state, &blocks)
+            .map(web::Bytes::from)
+            .map_err(|error| {
+                RequestError::new(StatusCode::INTERNAL_SERVER_ERROR, "InternalError", error)
+            })?;
+        state
+            .blocks_cache
+            .insert(last_block.index, last_block.hash, bytes.clone());
+
+        return Ok(json_bytes_response(bytes));
+    }
+
+    let from = query.from.unwrap_or(0);
+    let limit = query.limit.unwrap_or(usize::MAX);
+    let blocks = blockchain.get_blocks_range(from, limit);
+
+    Ok(json_response(&state, &blocks))
+}
+
+fn json_bytes_response(bytes: web::Bytes) -> HttpResponse {
+    HttpResponse::Ok()
+        .content_type(ContentType::json())
+        .body(bytes)
+}
+
+async fn get_recent_blocks(
+    state: web::Data<ApiState>,
+    query: web::Query<RecentBlocksQuery>,
+) -> impl Responder {
+    let n = query
+        .n
+        .unwrap_or(DEFAULT_RECENT_BLOCKS)
+        .min(MAX_RECENT_BLOCKS);
+
+    let summaries: Vec<BlockSummary> = state.blockchain.with_blocks(|blocks| {
+        let len = blocks.len();
+        let recent_blocks = blocks.get_range(len.saturating_sub(n), n);
+
+        recent_blocks.iter().rev().map(BlockSummary::new).collect()
+    });
+
+    json_response(&state, &summaries)
+}
+
+async fn get_block_by_index(state: web::Data<ApiState>, index: web::Path<u64>) -> HttpResponse {
+    match state.blockchain.get_block_by_index(index.into_inner()) {
+        Some(block) => json_response(&state, &block),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+// The block with the data explorers would otherwise derive from several requests
+async fn get_explorer_block(state: web::Data<ApiState>, index: web::Path<u64>) -> HttpResponse {
+    let index = index.into_inner();
+    let block = match state.blockchain.get_block_by_index(index) {
+        Some(block) => block,
+        None => return HttpResponse::NotFound().finish(),
+    };
+
+    // the genesis block has no parent, and allocates the initial balances instead of a reward
+    let previous_block = index
+        .checked_sub(1)
+        .and_then(|previous_index| state.blockchain.get_block_by_index(previous_index));
+    let coinbase_reward = match index {
+        0 => None,
+        // a split reward is paid by several coinbase outputs
+        _ => block
+            .transactions
+            .iter()
+            .take_while(|transaction| transaction.sender == Address::default())
+            .map(|coinbase| coinbase.amount)
+            .reduce(|total, amount| total.saturating_add(amount)),
+    };
+
+    let mut hash = [0u8; 32];
+    block.hash.to_big_endian(&mut hash);
+
+    let explorer_block = ExplorerBlock {
+        hash: hex::encode(hash),
+        leading_zeros: block.hash.leading_zeros(),
+        time_since_previous_ms: previous_block.map(|previous| block.timestamp - previous.timestamp),
+        coinbase_reward,
+        transaction_count: block.transactions.len(),
+        block,
+    };
+
+    json_response(&state, &explorer_block)
+}
+
+// Hex encoded canonical bytes, for third parties to verify the hash of the block
+async fn get_raw_block(state: web::Data<ApiState>, index: web::Path<u64>) -> HttpResponse {
+    match state.blockchain.get_block_by_index(index.into_inner()) {
+        Some(block) => HttpResponse::Ok()
+            .content_type("text/plain")
+            .body(hex::encode(block.to_hashable_bytes())),
+        None => HttpResponse::NotFound().finish(),
+    }
+}
+
+// Replaying the whole chain is CPU bound, so it runs on the blocking thread pool
+async fn verify_chain(state: web::Data<ApiState>) -> Result<HttpResponse, RequestError> {
+    let blockchain = state.blockchain.clone();
+
+    let result = web::block(move || blockchain.validate_chain())
+        .await
+        .map_err(|error| {
+            RequestError::new(
+                StatusCode::INTERNAL_SERVER_ERROR,
+                "VerificationFailed",
+                error,
+            )
+        })?;
+
+    let verification = match result {
+        Ok(()) => ChainVerification {
+            valid: true,
+            bad_block_index: None,
+            reason: None,
+        },
+
+        Err(error) => {
+            warn!("Chain verification failed: {}", error);
+
+            ChainVerification {
+                valid: false,
+                bad_block_index: Some(error.index),
+                reason: Some(error.reason),
+            }
+        }
+    };
+
+    Ok(json_response(&state, &verification))
+}
+
+async fn get_block_by_hash(
+    state: web::Data<ApiState>,
+    hash: web::Path<String>,
+) -> Result<HttpResponse, RequestError> {
+    let hash = BlockHash::from_str(&hash)
+        .map_err(|error| RequestError::new(StatusCode::BAD_REQUEST, "InvalidBlockHash", error))?;
+
+    match state.blockchain.get_block_by_hash(&hash) {
+        Some(block) => Ok(json_response(&state, &block)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+// Blocks following a checkpoint of the main chain, 404 when the checkpoint is not in it
+async fn get_blocks_after_hash(
+    state: web::Data<ApiState>,
+    hash: web::Path<String>,
+) -> Result<HttpResponse, RequestError> {
+    let hash = BlockHash::from_str(&hash)
+        .map_err(|error| RequestError::new(StatusCode::BAD_REQUEST, "InvalidBlockHash", error))?;
+
+    match state.blockchain.get_blocks_after_hash(&hash) {
+        Some(blocks) => Ok(json_response(&state, &blocks)),
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
 }
 
-async fn add_block(state: web::Data<ApiState>, block_json: web::Json<Block>) -> HttpResponse {
+// Peers answering that they already knew the block do not broadcast it again, so it never loops.
+// The response does not wait for the peers, which get the id of the request
+fn broadcast_block(state: &ApiState, block: Block, request: &HttpRequest) {
+    let request_id = match request.extensions().get::<RequestId>() {
+        Some(RequestId(request_id)) => request_id.clone(),
+        None => generate_request_id(),
+    };
+
+    if let Some(peer) = state.peer.clone() {
+        thread::spawn(move || peer.broadcast_block(&block, &request_id));
+    }
+}
+
+fn is_duplicate_block(error: &anyhow::Error) -> bool {
+    matches!(
+        error.downcast_ref::<BlockchainError>(),
+        Some(BlockchainError::DuplicateBlock)
+    )
+}
+
+async fn add_block(
+    request: HttpRequest,
+    state: web::Data<ApiState>,
+    block_json: web::Json<Block>,
+) -> Result<HttpResponse, RequestError> {
     let mut block = block_json.into_inner();
 
     block.hash = block.calculate_hash();
 
     let blockchain = &state.blockchain;
-    let result = blockchain.add_block(block.clone());
+    let result = blockchain.receive_block(block.clone());
 
     match result {
-        Ok(_) => {
+        Ok(Placement::MainChain) => {
             info!("Received new block {}", block.index);
-            HttpResponse::Ok().finish()
+            broadcast_block(&state, block, &request);
+            Ok(HttpResponse::Ok().json(BlockAcknowledgement { known: false }))
+        }
+
+        Ok(Placement::SideBranch) => {
+            info!("Received side branch block {}", block.index);
+            Ok(HttpResponse::Ok().json(BlockAcknowledgement { known: false }))
+        }
+
+        Ok(Placement::Orphan) => {
+            info!("Received orphan block {}", block.index);
+            Ok(HttpResponse::Ok().json(BlockAcknowledgement { known: false }))
         }
 
-        Err(error) => HttpResponse::BadRequest().body(error.to_string()),
+        // peers can send blocks they received from us, which is not an error
+        Err(error) if is_duplicate_block(&error) => {
+            debug!("Ignored already known block {}", block.index);
+            Ok(HttpResponse::Ok().json(BlockAcknowledgement { known: true }))
+        }
+
+        Err(error) => {
+            let source = match request.peer_addr() {
+                Some(address) => address.ip().to_string(),
+                None => String::from("unknown"),
+            };
+            state
+                .rejected_blocks
+                .record(&block, &error.to_string(), &source);
+
+            Err(error.into())
+        }
+    }
+}
+
+async fn calculate_block_hash(
+    state: web::Data<ApiState>,
+    candidate_json: web::Json<CandidateBlock>,
+) -> impl Responder {
+    let candidate = candidate_json.into_inner();
+
+    let block = Block {
+        version: candidate.version,
+        index: candidate.index,
+        timestamp: candidate.timestamp,
+        difficulty: candidate
+            .difficulty
+            .unwrap_or_else(|| state.blockchain.expected_difficulty(candidate.index)),
+        nonce: candidate.nonce,
+        previous_hash: candidate.previous_hash,
+        hash: BlockHash::default(),
+        transactions: candidate.transactions,
+    };
+
+    let result = BlockHashResult {
+        hash: block.calculate_hash(),
+    };
+
+    json_response(&state, &result)
+}
+
+async fn get_rejected_blocks(state: web::Data<ApiState>) -> impl Responder {
+    let rejected_blocks = state.rejected_blocks.get_all();
+
+    json_response(&state, &rejected_blocks)
+}
+
+// Best-effort check against the confirmed balance of the sender. Transactions still waiting in the
+// pool are not taken into account, so the chain remains the final authority when the block is mined
+fn can_afford(state: &ApiState, transaction: &Transaction) -> Result<bool> {
+    Ok(state
+        .blockchain
+        .get_spendable_balance(&transaction.sender)?
+        >= transaction.amount)
+}
+
+fn submit_transaction(
+    state: &ApiState,
+    transaction: Transaction,
+) -> Result<SubmittedTransaction, RequestError> {
+    if !can_afford(state, &transaction)? {
+        return Err(AccountBalanceMapError::InsufficientFunds.into());
     }
+
+    if !state.blockchain.accepts_recipient(&transaction.recipient)? {
+        return Err(BlockchainError::UnknownRecipient.into());
+    }
+
+    let hash = transaction.hash();
+    let spendable = state
+        .blockchain
+        .get_spendable_balance(&transaction.sender)?;
+    let allowance = state.blockchain.get_spending_allowance(&transaction.sender);
+
+    state
+        .pool
+        .add_transaction_spending(transaction, spendable, allowance)?;
+    state.metrics.transactions_received.inc();
+
+    Ok(SubmittedTransaction { hash })
 }
 
 async fn add_transaction(
     state: web::Data<ApiState>,
     transaction_json: web::Json<Transaction>,
+) -> Result<HttpResponse, RequestError> {
+    let submitted_transaction = submit_transaction(&state, transaction_json.into_inner())?;
+
+    Ok(json_response(&state, &submitted_transaction))
+}
+
+// Adds a transaction of a batch, or names the reason it was refused for
+fn submit_batch_item(state: &ApiState, transaction: Transaction) -> Result<(), String> {
+    let error_name = |error: anyhow::Error| describe_error(&error).1;
+
+    if !can_afford(state, &transaction).map_err(error_name)? {
+        return Err("InsufficientFunds".to_string());
+    }
+
+    let accepted = state
+        .blockchain
+        .accepts_recipient(&transaction.recipient)
+        .map_err(error_name)?;
+    if !accepted {
+        return Err("UnknownRecipient".to_string());
+    }
+
+    let spendable = state
+        .blockchain
+        .get_spendable_balance(&transaction.sender)
+        .map_err(error_name)?;
+    let allowance = state.blockchain.get_spending_allowance(&transaction.sender);
+
+    state
+        .pool
+        .add_transaction_spending(transaction, spendable, allowance)
+        .map_err(|error| format!("{:?}", error))
+}
+
+// Items are parsed one by one, so a malformed transaction does not reject the whole batch
+async fn add_transaction_batch(
+    state: web::Data<ApiState>,
+    batch_json: web::Json<Vec<Value>>,
 ) -> impl Responder {
-    let transaction = transaction_json.into_inner();
-    let pool = &state.pool;
-    pool.add_transaction(transaction);
+    let results: Vec<BatchItemResult> = batch_json
+        .into_inner()
+        .into_iter()
+        .map(|value| {
+            let result = match serde_json::from_value::<Transaction>(value) {
+                Ok(transaction) => submit_batch_item(&state, transaction),
+                Err(_) => Err("InvalidTransaction".to_string()),
+            };
+
+            match result {
+                Ok(_) => {
+                    state.metrics.transactions_received.inc();
+                    BatchItemResult {
+                        accepted: true,
+                        error: None,
+                    }
+                }
+
+                Err(error) => BatchItemResult {
+                    accepted: false,
+                    error: Some(error),
+                },
+            }
+        })
+        .collect();
+
+    json_response(&state, &results)
+}
+
+// Confirmed balance, and the part of it that is not locked by immature coinbase rewards
+fn read_account_balance(state: &ApiState, address: &str) -> Result<AccountBalance, RequestError> {
+    let address = Address::from_str(address)?;
+
+    Ok(AccountBalance {
+        balance: state.blockchain.get_balance(&address)?,
+        spendable: state.blockchain.get_spendable_balance(&address)?,
+        address,
+    })
+}
+
+async fn get_balance(
+    state: web::Data<ApiState>,
+    address: web::Path<String>,
+) -> Result<HttpResponse, RequestError> {
+    let account_balance = read_account_balance(&state, &address)?;
+
+    Ok(json_response(&state, &account_balance))
+}
+
+// Lets new nodes start from the current balances instead of replaying every block
+async fn get_snapshot(state: web::Data<ApiState>) -> Result<HttpResponse, RequestError> {
+    let snapshot = state.blockchain.snapshot()?;
+
+    Ok(json_response(&state, &snapshot))
+}
+
+async fn get_transaction(
+    state: web::Data<ApiState>,
+    hash: web::Path<String>,
+) -> Result<HttpResponse, RequestError> {
+    let hash = TransactionHash::from_str(&hash).map_err(|error| {
+        RequestError::new(StatusCode::BAD_REQUEST, "InvalidTransactionHash", error)
+    })?;
+
+    match state.blockchain.find_transaction(&hash) {
+        Some((block_index, transaction)) => {
+            let last_index = state.blockchain.get_last_block_index();
+            let confirmations = last_index.saturating_sub(block_index) + 1;
+
+            let confirmed_transaction = ConfirmedTransaction {
+                block_index,
+                confirmations,
+                is_final: confirmations >= state.finality_depth,
+                transaction,
+            };
+
+            Ok(json_response(&state, &confirmed_transaction))
+        }
+
+        None => Ok(HttpResponse::NotFound().finish()),
+    }
+}
+
+async fn get_metrics(state: web::Data<ApiState>) -> impl Responder {
+    let metrics = &state.metrics;
+    metrics
+        .blockchain_height
+        .set(state.blockchain.get_last_block_index() as i64);
+    metrics.mempool_size.set(state.pool.len() as i64);
 
     HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(metrics.encode())
+}
+
+// Relays the blocks added to the blockchain to a channel that every WebSocket client can subscribe to
+fn forward_new_blocks(blockchain: &Blockchain) -> broadcast::Sender<Block> {
+    let (sender, _) = broadcast::channel(NEW_BLOCKS_CAPACITY);
+    let receiver = blockchain.subscribe();
+    let thread_sender = sender.clone();
+
+    thread::spawn(move || {
+        for block in receiver {
+            // sending only fails when there are no clients connected
+            let _ = thread_sender.send(block);
+        }
+    });
+
+    sender
+}
+
+// Pushes every new block to the client as JSON, until either side closes the connection
+async fn push_new_blocks(
+    req: HttpRequest,
+    body: web::Payload,
+    state: web::Data<ApiState>,
+) -> actix_web::Result<HttpResponse> {
+    let (response, mut session, mut messages) = actix_ws::handle(&req, body)?;
+    let mut new_blocks = state.new_blocks.subscribe();
+
+    rt::spawn(async move {
+        loop {
+            tokio::select! {
+                block = new_blocks.recv() => match block {
+                    Ok(block) => {
+                        let json = serde_json::to_string(&block).unwrap();
+
+                        if session.text(json).await.is_err() {
+                            return;
+                        }
+                    }
+
+                    Err(RecvError::Lagged(skipped)) => {
+                        warn!("WebSocket client is too slow, skipped {} blocks", skipped);
+                    }
+
+                    Err(RecvError::Closed) => break,
+                },
+
+                message = messages.recv() => match message {
+                    Some(Ok(Message::Ping(bytes))) => {
+                        if session.pong(&bytes).await.is_err() {
+                            return;
+                        }
+                    }
+
+                    Some(Ok(Message::Close(_))) | Some(Err(_)) | None => break,
+
+                    Some(Ok(_)) => {}
+                },
+            }
+        }
+
+        let _ = session.close(None).await;
+    });
+
+    Ok(response)
+}
+
+async fn stop_server_on_shutdown(server_handle: ServerHandle, shutdown: ShutdownFlag) {
+    while !is_shutdown_requested(&shutdown) {
+        rt::time::sleep(Duration::from_millis(SHUTDOWN_POLLING_MS)).await;
+    }
+
+    info!("Shutdown requested, stopping api server");
+    server_handle.stop(true).await;
+}
+
+async fn get_pending_transactions(state: web::Data<ApiState>) -> impl Responder {
+    let transactions = state.pool.peek();
+
+    let pending_transactions = PendingTransactions {
+        count: transactions.len(),
+        transactions,
+    };
+
+    json_response(&state, &pending_transactions)
+}
+
+// Id of the request being handled, for the handlers to pass it on to the peers
+#[derive(Clone)]
+struct RequestId(String);
+
+// Tags each request with the id sent by the client, or a new one, so a request can be followed
+// across the logs of several nodes. The id is logged with the outcome and echoed in the response
+async fn tag_request_id(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let header = match request.app_data::<web::Data<ApiState>>() {
+        Some(state) => state.request_id_header.clone(),
+        None => DEFAULT_REQUEST_ID_HEADER.to_string(),
+    };
+
+    let request_id = request
+        .headers()
+        .get(header.as_str())
+        .and_then(|value| value.to_str().ok())
+        .filter(|value| is_valid_request_id(value))
+        .map(str::to_string)
+        .unwrap_or_else(generate_request_id);
+    request
+        .extensions_mut()
+        .insert(RequestId(request_id.clone()));
+
+    let method = request.method().clone();
+    let path = request.path().to_string();
+    let start = Instant::now();
+
+    let mut response = next.call(request).await?.map_into_boxed_body();
+
+    info!(
+        "{} {} {} in {}ms [{}]",
+        method,
+        path,
+        response.status().as_u16(),
+        start.elapsed().as_millis(),
+        request_id
+    );
+
+    if let (Ok(name), Ok(value)) = (
+        HeaderName::from_str(&header),
+        HeaderValue::from_str(&request_id),
+    ) {
+        response.headers_mut().insert(name, value);
+    }
+
+    Ok(response)
+}
+
+// Peers posting blocks faster than the configured rate are told to slow down
+async fn limit_block_rate(
+    request: ServiceRequest,
+    next: Next<impl MessageBody + 'static>,
+) -> Result<ServiceResponse<BoxBody>, actix_web::Error> {
+    let limiter = request.app_data::<RateLimiter>().cloned();
+    let sender = request.peer_addr().map(|address| address.ip());
+    let is_post = request.method() == Method::POST;
+
+    if let (true, Some(limiter), Some(sender)) = (is_post, limiter, sender) {
+        if !limiter.try_acquire(sender) {
+            debug!("Rate limited blocks from {}", sender);
+
+            let error = RequestError::new(
+                StatusCode::TOO_MANY_REQUESTS,
+                "TooManyBlocks",
+                "Too many blocks, try again later",
+            );
+            return Ok(request.error_response(error));
+        }
+    }
+
+    Ok(next.call(request).await?.map_into_boxed_body())
 }
 
 #[actix_web::main]
-async fn start_server(port: u16, blockchain: Blockchain, pool: TransactionPool) -> Result<()> {
-    let url = format!("localhost:{}", port);
+async fn start_server(
+    bind_address: IpAddr,
+    port: u16,
+    api_base_path: String,
+    api_state: ApiState,
+    tls_config: Option<ServerConfig>,
+    cors_allowed_origins: Vec<String>,
+    shutdown: ShutdownFlag,
+) -> Result<()> {
+    let url = SocketAddr::new(bind_address, port);
+
+    let api_state = web::Data::new(api_state);
+
+    if !api_base_path.is_empty() {
+        info!("Serving the api under {}", api_base_path);
+    }
+
+    // signals are handled by the termination module, which requests the shutdown of all threads
+    let server = HttpServer::new(move || {
+        // without allowed origins browsers keep enforcing the same-origin policy
+        let cors_enabled = !cors_allowed_origins.is_empty();
+
+        let routes = web::scope(&api_base_path)
+            .route("/status", web::get().to(get_status))
+            .route("/metrics", web::get().to(get_metrics))
+            .route("/version", web::get().to(get_version))
+            .route("/difficulty", web::get().to(get_difficulty))
+            .route("/peers", web::get().to(get_peers))
+            .route("/peers", web::post().to(add_peer))
+            .service(
+                web::resource("/blocks")
+                    .wrap(from_fn(limit_block_rate))
+                    .route(web::get().to(get_blocks))
+                    .route(web::post().to(add_block)),
+            )
+            .route("/blocks/recent", web::get().to(get_recent_blocks))
+            .route("/blocks/after/{hash}", web::get().to(get_blocks_after_hash))
+            .route("/blocks/{index}", web::get().to(get_block_by_index))
+            .route("/blocks/{index}/raw", web::get().to(get_raw_block))
+            .route("/blocks/hash/{hash}", web::get().to(get_block_by_hash))
+            .route("/explorer/block/{index}", web::get().to(get_explorer_block))
+            .route("/balances/{address}", web::get().to(get_balance))
+            .route("/mine", web::post().to(mine_block))
+            .route("/snapshot", web::get().to(get_snapshot))
+            .route("/chain/verify", web::get().to(verify_chain))
+            .route("/transactions", web::post().to(add_transaction))
+            .route("/transactions/batch", web::post().to(add_transaction_batch))
+            .route("/util/hash", web::post().to(calculate_block_hash))
+            .route(
+                "/transactions/pending",
+                web::get().to(get_pending_transactions),
+            )
+            .route("/transactions/{hash}", web::get().to(get_transaction))
+            .route("/admin/rejected-blocks", web::get().to(get_rejected_blocks))
+            .route("/ws", web::get().to(push_new_blocks));
 
-    let api_state = web::Data::new(ApiState { blockchain, pool });
+        let routes = match api_state.json_rpc_enabled {
+            true => routes.route("/rpc", web::post().to(rpc::handle_rpc)),
+            false => routes,
+        };
 
-    HttpServer::new(move || {
         App::new()
+            .wrap(Condition::new(
+                cors_enabled,
+                create_cors(&cors_allowed_origins),
+            ))
+            .wrap(from_fn(tag_request_id))
             .app_data(api_state.clone())
-            .route("/blocks", web::get().to(get_blocks))
-            .route("/blocks", web::post().to(add_block))
-            .route("/transactions", web::post().to(add_transaction))
+            .app_data(api_state.block_rate_limiter.clone())
+            .service(routes)
     })
-    .bind(url)
-    .unwrap()
-    .run()
-    .await?;
+    .disable_signals()
+    .shutdown_timeout(SHUTDOWN_TIMEOUT_SECS);
+
+    let server = match tls_config {
+        Some(tls_config) => {
+            info!("Serving the api over HTTPS");
+            server.bind_rustls_021(url, tls_config)?
+        }
+        None => server.bind(url)?,
+    }
+    .run();
+
+    rt::spawn(stop_server_on_shutdown(server.handle(), shutdown));
+    server.await?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Mutex;
+
+    use crate::{
+        model::{test_person_util::person1, Address, BlockHash},
+        util::sync::lock_recover,
+    };
+
+    use super::*;
+
+    fn fixture_path(name: &str) -> String {
+        format!("{}/tests/fixtures/{}", env!("CARGO_MANIFEST_DIR"), name)
+    }
+
+    #[actix_web::test]
+    async fn limit_block_rate_until_the_bucket_refills() {
+        let now = Arc::new(Mutex::new(Instant::now()));
+        let clock_now = now.clone();
+        let limiter = RateLimiter::new(60).with_clock(Arc::new(move || *lock_recover(&clock_now)));
+
+        let app = actix_web::test::init_service(
+            App::new().app_data(limiter).service(
+                web::resource("/blocks")
+                    .wrap(from_fn(limit_block_rate))
+                    .route(web::get().to(HttpResponse::Ok))
+                    .route(web::post().to(HttpResponse::Ok)),
+            ),
+        )
+        .await;
+
+        let sender: SocketAddr = "10.0.0.1:8000".parse().unwrap();
+        let post_block = || async {
+            let request = actix_web::test::TestRequest::post()
+                .uri("/blocks")
+                .peer_addr(sender)
+                .to_request();
+            actix_web::test::call_service(&app, request).await.status()
+        };
+
+        // the bucket holds a minute worth of blocks, the next ones are refused
+        for _ in 0..60 {
+            assert_eq!(post_block().await, StatusCode::OK);
+        }
+        assert_eq!(post_block().await, StatusCode::TOO_MANY_REQUESTS);
+
+        // a sender waiting for the bucket to refill is accepted again, one block per second
+        for _ in 0..3 {
+            *lock_recover(&now) += Duration::from_secs(1);
+            assert_eq!(post_block().await, StatusCode::OK);
+            assert_eq!(post_block().await, StatusCode::TOO_MANY_REQUESTS);
+        }
+
+        // reading blocks is not limited
+        let request = actix_web::test::TestRequest::get()
+            .uri("/blocks")
+            .peer_addr(sender)
+            .to_request();
+        let response = actix_web::test::call_service(&app, request).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn load_self_signed_tls_config() {
+        let result = load_tls_config(&fixture_path("cert.pem"), &fixture_path("key.pem"));
+
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn reject_tls_files_without_pem_items() {
+        let cert_path = fixture_path("cert.pem");
+        let key_path = fixture_path("key.pem");
+
+        let err = load_tls_config(&cert_path, &cert_path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("No private key found in `{}`", cert_path)
+        );
+
+        let err = load_tls_config(&key_path, &key_path).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!("No certificate found in `{}`", key_path)
+        );
+    }
+
+    #[test]
+    fn large_numbers_round_trip_as_strings() {
+        let transaction = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: u64::MAX,
+        };
+        let mut block = Block::new(u64::MAX, u64::MAX, BlockHash::MAX, vec![transaction]);
+        block.hash = BlockHash::MAX - 1;
+
+        let value = numbers_to_strings(serde_json::to_value(&block).unwrap());
+        assert_eq!(value["index"], Value::String(u64::MAX.to_string()));
+        assert_eq!(
+            value["transactions"][0]["amount"],
+            Value::String(u64::MAX.to_string())
+        );
+
+        let json = serde_json::to_string(&value).unwrap();
+        let parsed_block: Block = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(parsed_block.index, block.index);
+        assert_eq!(parsed_block.nonce, block.nonce);
+        assert_eq!(parsed_block.timestamp, block.timestamp);
+        assert_eq!(parsed_block.previous_hash, block.previous_hash);
+        assert_eq!(parsed_block.hash, block.hash);
+        assert_eq!(parsed_block.transactions[0].amount, u64::MAX);
+    }
+
+    #[test]
+    fn candidate_block_numbers_can_be_strings() {
+        let mut block = Block::new(1, 2, BlockHash::from(3), vec![]);
+        block.difficulty = 4;
+
+        let value = numbers_to_strings(serde_json::to_value(&block).unwrap());
+        let candidate: CandidateBlock = serde_json::from_value(value).unwrap();
+
+        assert_eq!(candidate.version, block.version);
+        assert_eq!(candidate.index, block.index);
+        assert_eq!(candidate.timestamp, block.timestamp);
+        assert_eq!(candidate.difficulty, Some(block.difficulty));
+        assert_eq!(candidate.nonce, block.nonce);
+    }
+
+    #[test]
+    fn describe_errors_by_variant_name() {
+        let error = RequestError::from(BlockchainError::UnsupportedBlockVersion(3));
+        assert_eq!(error.status, StatusCode::BAD_REQUEST);
+        assert_eq!(error.error, "UnsupportedBlockVersion");
+
+        let error = RequestError::from(PeerAddressError::DuplicatePeer);
+        assert_eq!(error.status, StatusCode::CONFLICT);
+        assert_eq!(error.error, "DuplicatePeer");
+
+        let error = RequestError::from(anyhow::anyhow!("Unexpected"));
+        assert_eq!(error.status, StatusCode::INTERNAL_SERVER_ERROR);
+        assert_eq!(error.error, "InternalError");
+        assert_eq!(error.message, "Unexpected");
+    }
+}
@@ -0,0 +1,121 @@
+use std::{
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use actix_web::web::Bytes;
+
+use crate::{model::BlockHash, util::sync::lock_recover};
+
+struct CachedBlocks {
+    height: u64,
+    // a reorganization can replace the last block without changing the height
+    last_hash: BlockHash,
+    bytes: Bytes,
+    cached_at: Instant,
+}
+
+// Serialized response of the whole chain, reused by requests made at the same height so
+// bursts of explorers do not serialize it every time. A zero age disables the cache
+pub struct BlocksCache {
+    max_age: Duration,
+    cached: Mutex<Option<CachedBlocks>>,
+}
+
+impl BlocksCache {
+    pub fn new(max_age: Duration) -> BlocksCache {
+        BlocksCache {
+            max_age,
+            cached: Mutex::new(None),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        !self.max_age.is_zero()
+    }
+
+    pub fn get(&self, height: u64, last_hash: BlockHash) -> Option<Bytes> {
+        self.get_at(height, last_hash, Instant::now())
+    }
+
+    // Bytes are serialized by the caller, outside of the lock, so other requests are not blocked
+    pub fn insert(&self, height: u64, last_hash: BlockHash, bytes: Bytes) {
+        self.insert_at(height, last_hash, Instant::now(), bytes)
+    }
+
+    fn get_at(&self, height: u64, last_hash: BlockHash, now: Instant) -> Option<Bytes> {
+        let cached = lock_recover(&self.cached);
+        let entry = cached.as_ref()?;
+
+        let is_fresh = now.saturating_duration_since(entry.cached_at) < self.max_age;
+        match is_fresh && entry.height == height && entry.last_hash == last_hash {
+            true => Some(entry.bytes.clone()),
+            false => None,
+        }
+    }
+
+    fn insert_at(&self, height: u64, last_hash: BlockHash, now: Instant, bytes: Bytes) {
+        let mut cached = lock_recover(&self.cached);
+
+        // a slower request must not replace the bytes of a later block
+        if cached.as_ref().is_some_and(|entry| entry.height > height) {
+            return;
+        }
+
+        *cached = Some(CachedBlocks {
+            height,
+            last_hash,
+            bytes,
+            cached_at: now,
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reuse_bytes_until_the_chain_changes() {
+        let cache = BlocksCache::new(Duration::from_secs(60));
+        let now = Instant::now();
+
+        assert_eq!(cache.get_at(1, BlockHash::from(1), now), None);
+
+        cache.insert_at(1, BlockHash::from(1), now, Bytes::from("a"));
+        assert_eq!(
+            cache.get_at(1, BlockHash::from(1), now),
+            Some(Bytes::from("a"))
+        );
+
+        assert_eq!(cache.get_at(2, BlockHash::from(2), now), None);
+
+        // a reorganization replaces the last block at the same height
+        assert_eq!(cache.get_at(1, BlockHash::from(3), now), None);
+    }
+
+    #[test]
+    fn expire_cached_bytes() {
+        let cache = BlocksCache::new(Duration::from_secs(1));
+        let now = Instant::now();
+
+        cache.insert_at(1, BlockHash::from(1), now, Bytes::from("a"));
+
+        let later = now + Duration::from_secs(1);
+        assert_eq!(cache.get_at(1, BlockHash::from(1), later), None);
+    }
+
+    #[test]
+    fn keep_the_bytes_of_the_highest_block() {
+        let cache = BlocksCache::new(Duration::from_secs(60));
+        let now = Instant::now();
+
+        cache.insert_at(2, BlockHash::from(2), now, Bytes::from("b"));
+        cache.insert_at(1, BlockHash::from(1), now, Bytes::from("a"));
+
+        assert_eq!(
+            cache.get_at(2, BlockHash::from(2), now),
+            Some(Bytes::from("b"))
+        );
+    }
+}
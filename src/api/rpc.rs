@@ -0,0 +1,185 @@
+use actix_web::{web, HttpResponse};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use crate::model::Transaction;
+
+use super::{numbers_to_strings, read_account_balance, submit_transaction, ApiState, RequestError};
+
+// Error codes defined by the JSON-RPC 2.0 specification
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+const INTERNAL_ERROR: i64 = -32603;
+
+// Codes from -32000 to -32099 are left to the server, which reports the errors of the REST api
+const SERVER_ERROR: i64 = -32000;
+
+#[derive(Deserialize)]
+struct RpcRequest {
+    jsonrpc: String,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Serialize, Debug, PartialEq)]
+struct RpcError {
+    code: i64,
+    message: String,
+    // name of the error of the REST api, like "InsufficientFunds"
+    #[serde(skip_serializing_if = "Option::is_none")]
+    data: Option<String>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl ToString) -> RpcError {
+        RpcError {
+            code,
+            message: message.to_string(),
+            data: None,
+        }
+    }
+}
+
+impl From<RequestError> for RpcError {
+    fn from(error: RequestError) -> RpcError {
+        RpcError {
+            code: SERVER_ERROR,
+            message: error.message,
+            data: Some(error.error),
+        }
+    }
+}
+
+// Parameters are given either by position, like [0], or by name, like {"index": 0}
+fn read_param<T: DeserializeOwned>(
+    params: &Value,
+    position: usize,
+    name: &str,
+) -> Result<T, RpcError> {
+    let value = match params {
+        Value::Array(values) => values.get(position),
+        Value::Object(map) => map.get(name),
+        _ => None,
+    };
+
+    value
+        .and_then(|value| serde_json::from_value(value.clone()).ok())
+        .ok_or_else(|| RpcError::new(INVALID_PARAMS, format!("Invalid parameter `{}`", name)))
+}
+
+fn to_result<T: Serialize>(state: &ApiState, body: &T) -> Result<Value, RpcError> {
+    let value = serde_json::to_value(body).map_err(|error| RpcError::new(INTERNAL_ERROR, error))?;
+
+    match state.numbers_as_strings {
+        true => Ok(numbers_to_strings(value)),
+        false => Ok(value),
+    }
+}
+
+fn call_method(state: &ApiState, method: &str, params: &Value) -> Result<Value, RpcError> {
+    match method {
+        "getLastBlock" => to_result(state, &state.blockchain.get_last_block()),
+
+        // unknown blocks are a null result, as they are not an error of the call
+        "getBlockByIndex" => {
+            let index: u64 = read_param(params, 0, "index")?;
+            to_result(state, &state.blockchain.get_block_by_index(index))
+        }
+
+        "sendTransaction" => {
+            let transaction: Transaction = read_param(params, 0, "transaction")?;
+            to_result(state, &submit_transaction(state, transaction)?)
+        }
+
+        "getBalance" => {
+            let address: String = read_param(params, 0, "address")?;
+            to_result(state, &read_account_balance(state, &address)?)
+        }
+
+        _ => Err(RpcError::new(
+            METHOD_NOT_FOUND,
+            format!("Method `{}` not found", method),
+        )),
+    }
+}
+
+fn create_response(id: Value, outcome: Result<Value, RpcError>) -> Value {
+    match outcome {
+        Ok(result) => json!({ "jsonrpc": "2.0", "result": result, "id": id }),
+        Err(error) => json!({ "jsonrpc": "2.0", "error": error, "id": id }),
+    }
+}
+
+fn handle_request(state: &ApiState, request: Value) -> Value {
+    let id = request.get("id").cloned().unwrap_or(Value::Null);
+
+    let outcome = match serde_json::from_value::<RpcRequest>(request) {
+        Ok(request) if request.jsonrpc == "2.0" => {
+            call_method(state, &request.method, &request.params)
+        }
+        _ => Err(RpcError::new(INVALID_REQUEST, "Invalid request")),
+    };
+
+    create_response(id, outcome)
+}
+
+// Calls always answer 200, with the outcome in the body as the specification expects
+pub async fn handle_rpc(state: web::Data<ApiState>, body: web::Bytes) -> HttpResponse {
+    let response = match serde_json::from_slice::<Value>(&body) {
+        Ok(request) => handle_request(&state, request),
+        Err(error) => create_response(Value::Null, Err(RpcError::new(PARSE_ERROR, error))),
+    };
+
+    HttpResponse::Ok().json(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_params_by_position_or_name() {
+        let index: u64 = read_param(&json!([3]), 0, "index").unwrap();
+        assert_eq!(index, 3);
+
+        let index: u64 = read_param(&json!({ "index": 4 }), 0, "index").unwrap();
+        assert_eq!(index, 4);
+
+        let err = read_param::<u64>(&json!(["three"]), 0, "index").unwrap_err();
+        assert_eq!(err.code, INVALID_PARAMS);
+
+        let err = read_param::<u64>(&Value::Null, 0, "index").unwrap_err();
+        assert_eq!(err.code, INVALID_PARAMS);
+    }
+
+    #[test]
+    fn create_responses_following_the_specification() {
+        let response = create_response(json!(1), Ok(Value::Null));
+        assert_eq!(
+            response,
+            json!({ "jsonrpc": "2.0", "result": null, "id": 1 })
+        );
+
+        let error = RpcError::from(RequestError::new(
+            actix_web::http::StatusCode::BAD_REQUEST,
+            "InsufficientFunds",
+            "Insufficient funds",
+        ));
+        let response = create_response(json!("a"), Err(error));
+        assert_eq!(
+            response,
+            json!({
+                "jsonrpc": "2.0",
+                "error": {
+                    "code": SERVER_ERROR,
+                    "message": "Insufficient funds",
+                    "data": "InsufficientFunds"
+                },
+                "id": "a"
+            })
+        );
+    }
+}
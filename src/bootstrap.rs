@@ -0,0 +1,236 @@
+use std::time::Duration;
+
+use anyhow::Result;
+use isahc::{config::Configurable, HttpClient, ReadResponseExt};
+use serde::de::DeserializeOwned;
+use thiserror::Error;
+
+use crate::model::{Block, Blockchain, ChainSnapshot};
+
+#[derive(Error, PartialEq, Debug)]
+pub enum BootstrapError {
+    #[error("Bootstrap source is unreachable")]
+    Unreachable,
+
+    #[error("Bootstrap source responded with status `{0}`")]
+    BadStatus(u16),
+
+    #[error("Bootstrap source responded with an invalid body")]
+    InvalidBody,
+}
+
+// A stalled source would hang the startup, so connecting and any pause in the download are
+// bounded by the peer timeout. The whole download is not, as a long chain takes a while
+fn create_client(timeout_ms: u64) -> HttpClient {
+    let timeout = Duration::from_millis(timeout_ms);
+
+    HttpClient::builder()
+        .connect_timeout(timeout)
+        // pauses are counted in whole seconds, shorter ones would disable the check
+        .low_speed_timeout(1, timeout.max(Duration::from_secs(1)))
+        .build()
+        .unwrap()
+}
+
+fn download<T: DeserializeOwned>(client: &HttpClient, url: &str) -> Result<T, BootstrapError> {
+    let mut response = client.get(url).map_err(|_| BootstrapError::Unreachable)?;
+
+    let status = response.status().as_u16();
+    if status != 200 {
+        return Err(BootstrapError::BadStatus(status));
+    }
+
+    let raw_body = response.text().map_err(|_| BootstrapError::Unreachable)?;
+
+    serde_json::from_str(&raw_body).map_err(|_| BootstrapError::InvalidBody)
+}
+
+// Initializes the blockchain from the full list of blocks of a trusted source,
+// like the /blocks endpoint of another node, validating every block on the way
+pub fn bootstrap_from(url: &str, timeout_ms: u64, blockchain: &Blockchain) -> Result<()> {
+    info!("Bootstrapping blockchain from {}", url);
+
+    let client = create_client(timeout_ms);
+    let blocks: Vec<Block> = download(&client, url)?;
+    blockchain.import_blocks(&blocks)?;
+
+    info!(
+        "Bootstrapped blockchain up to block {}",
+        blockchain.get_last_block().index
+    );
+
+    Ok(())
+}
+
+// Initializes the blockchain from the snapshot of a trusted node, given by its base url, and
+// the blocks it added since. Only those are validated, the balances of the snapshot are trusted
+pub fn bootstrap_from_snapshot(url: &str, timeout_ms: u64, blockchain: &Blockchain) -> Result<()> {
+    info!("Bootstrapping blockchain from the snapshot of {}", url);
+
+    let client = create_client(timeout_ms);
+    let url = url.trim_end_matches('/');
+    let snapshot: ChainSnapshot = download(&client, &format!("{}/snapshot", url))?;
+    let tip_index = snapshot.tip_index;
+    let trailing_blocks: Vec<Block> = download(
+        &client,
+        &format!("{}/blocks/after/{:#x}", url, snapshot.tip_hash),
+    )?;
+
+    blockchain.import_snapshot(snapshot, &trailing_blocks)?;
+
+    info!(
+        "Bootstrapped blockchain from the snapshot at block {} up to block {}",
+        tip_index,
+        blockchain.get_last_block().index
+    );
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::TcpListener,
+        thread,
+        time::Instant,
+    };
+
+    use crate::model::{test_person_util::person1, Address, Transaction, BLOCK_SUBSIDY};
+
+    use super::*;
+
+    const TIMEOUT_MS: u64 = 5000;
+
+    // Answers one request with each of the given bodies, in order, and returns the base url
+    fn serve(bodies: Vec<String>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            for body in bodies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = [0; 1024];
+                let _ = stream.read(&mut request).unwrap();
+
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        url
+    }
+
+    // Serves a single request with the given body and returns the url to request it
+    fn serve_once(body: String) -> String {
+        format!("{}/blocks", serve(vec![body]))
+    }
+
+    fn create_source_blockchain() -> Blockchain {
+        let blockchain = Blockchain::new(0);
+
+        for _ in 0..3 {
+            let last_block = blockchain.get_last_block();
+            let coinbase = Transaction {
+                sender: Address::default(),
+                recipient: person1(),
+                amount: BLOCK_SUBSIDY,
+            };
+            let mut block = Block::new(last_block.index + 1, 0, last_block.hash, vec![coinbase]);
+            block.timestamp = last_block.timestamp + 1;
+            block.hash = block.calculate_hash();
+
+            blockchain.add_block(block).unwrap();
+        }
+
+        blockchain
+    }
+
+    #[test]
+    fn test_bootstrap_from_served_blocks() {
+        let source = create_source_blockchain();
+        let body = serde_json::to_string(&source.get_all_blocks()).unwrap();
+        let url = serve_once(body);
+
+        let blockchain = Blockchain::new(0);
+        bootstrap_from(&url, TIMEOUT_MS, &blockchain).unwrap();
+
+        assert_eq!(blockchain.get_all_blocks().len(), 4);
+        assert_eq!(
+            blockchain.get_last_block().hash,
+            source.get_last_block().hash
+        );
+    }
+
+    #[test]
+    fn test_bootstrap_gives_up_on_a_stalled_source() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/blocks", listener.local_addr().unwrap());
+
+        // accepts the connection but never answers
+        thread::spawn(move || {
+            let (_stream, _) = listener.accept().unwrap();
+            thread::sleep(Duration::from_secs(30));
+        });
+
+        let blockchain = Blockchain::new(0);
+        let started_at = Instant::now();
+        let result = bootstrap_from(&url, 1000, &blockchain);
+
+        let error = result.unwrap_err().downcast::<BootstrapError>().unwrap();
+        assert_eq!(error, BootstrapError::Unreachable);
+        assert!(started_at.elapsed() < Duration::from_secs(10));
+    }
+
+    #[test]
+    fn test_bootstrap_rejects_tampered_blocks() {
+        let source = create_source_blockchain();
+        let mut blocks = source.get_all_blocks();
+        blocks[2].transactions[0].amount += 1;
+
+        let body = serde_json::to_string(&blocks).unwrap();
+        let url = serve_once(body);
+
+        let blockchain = Blockchain::new(0);
+        let result = bootstrap_from(&url, TIMEOUT_MS, &blockchain);
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_bootstrap_from_snapshot_and_trailing_blocks() {
+        let source = create_source_blockchain();
+        let snapshot = source.snapshot().unwrap();
+
+        let last_block = source.get_last_block();
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let mut block = Block::new(last_block.index + 1, 0, last_block.hash, vec![coinbase]);
+        block.timestamp = last_block.timestamp + 1;
+        block.hash = block.calculate_hash();
+        source.add_block(block.clone()).unwrap();
+
+        let url = serve(vec![
+            serde_json::to_string(&snapshot).unwrap(),
+            serde_json::to_string(&vec![block]).unwrap(),
+        ]);
+
+        let blockchain = Blockchain::new(0);
+        bootstrap_from_snapshot(&url, TIMEOUT_MS, &blockchain).unwrap();
+
+        // the chain starts at the snapshot tip
+        assert_eq!(blockchain.get_all_blocks(), source.get_all_blocks()[3..]);
+        assert_eq!(blockchain.len(), source.len());
+        assert_eq!(
+            blockchain.get_balance(&person1()).unwrap(),
+            source.get_balance(&person1()).unwrap()
+        );
+    }
+}
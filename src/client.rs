@@ -0,0 +1,140 @@
+use isahc::{HttpClient, ReadResponseExt, Request, Response};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use thiserror::Error;
+
+use crate::model::{serde_number, Address, Amount, Block, Transaction, TransactionHash};
+
+#[derive(Error, Debug)]
+pub enum ClientError {
+    #[error("Request to the node failed: {0}")]
+    Request(#[from] isahc::Error),
+
+    #[error("Node responded with status `{0}`: {1}")]
+    BadStatus(u16, String),
+
+    #[error("Node responded with an invalid body: {0}")]
+    InvalidBody(#[from] serde_json::Error),
+}
+
+#[derive(Deserialize)]
+struct ErrorBody {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct SubmittedTransaction {
+    hash: TransactionHash,
+}
+
+#[derive(Deserialize)]
+struct AccountBalance {
+    #[serde(deserialize_with = "serde_number::deserialize")]
+    balance: Amount,
+}
+
+#[derive(Deserialize)]
+struct Status {
+    #[serde(deserialize_with = "serde_number::deserialize")]
+    height: u64,
+}
+
+// Typed access to the REST API of a node, for other Rust programs
+pub struct NodeClient {
+    base_url: String,
+    client: HttpClient,
+}
+
+impl NodeClient {
+    pub fn new(base_url: &str) -> Result<NodeClient, ClientError> {
+        Ok(NodeClient {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client: HttpClient::new()?,
+        })
+    }
+
+    pub fn get_blocks(&self) -> Result<Vec<Block>, ClientError> {
+        let response = self.client.get(self.url("/blocks"))?;
+
+        NodeClient::parse_json(response)
+    }
+
+    // Looks the block up by the height of the node instead of downloading the whole chain
+    pub fn get_last_block(&self) -> Result<Block, ClientError> {
+        let response = self.client.get(self.url("/status"))?;
+        let status: Status = NodeClient::parse_json(response)?;
+
+        let response = self
+            .client
+            .get(self.url(&format!("/blocks/{}", status.height)))?;
+
+        NodeClient::parse_json(response)
+    }
+
+    // Returns the hash the transaction can be looked up by once it is confirmed
+    pub fn submit_transaction(
+        &self,
+        transaction: &Transaction,
+    ) -> Result<TransactionHash, ClientError> {
+        let response = self.post_json("/transactions", transaction)?;
+        let submitted: SubmittedTransaction = NodeClient::parse_json(response)?;
+
+        Ok(submitted.hash)
+    }
+
+    pub fn submit_block(&self, block: &Block) -> Result<(), ClientError> {
+        let response = self.post_json("/blocks", block)?;
+
+        NodeClient::check_status(response).map(|_| ())
+    }
+
+    pub fn get_balance(&self, address: &Address) -> Result<Amount, ClientError> {
+        let response = self
+            .client
+            .get(self.url(&format!("/balances/{}", address)))?;
+        let account_balance: AccountBalance = NodeClient::parse_json(response)?;
+
+        Ok(account_balance.balance)
+    }
+
+    fn url(&self, path: &str) -> String {
+        format!("{}{}", self.base_url, path)
+    }
+
+    fn post_json<T: Serialize>(
+        &self,
+        path: &str,
+        body: &T,
+    ) -> Result<Response<isahc::Body>, ClientError> {
+        let request = Request::post(self.url(path))
+            .header("Content-Type", "application/json")
+            .body(serde_json::to_vec(body)?)
+            .map_err(isahc::Error::from)?;
+
+        Ok(self.client.send(request)?)
+    }
+
+    fn check_status(
+        mut response: Response<isahc::Body>,
+    ) -> Result<Response<isahc::Body>, ClientError> {
+        let status = response.status().as_u16();
+
+        if status != 200 {
+            let body = response.text().unwrap_or_default();
+            // the node describes failures as json, older nodes answer with plain text
+            let message = match serde_json::from_str::<ErrorBody>(&body) {
+                Ok(error) => error.message,
+                Err(_) => body,
+            };
+            return Err(ClientError::BadStatus(status, message));
+        }
+
+        Ok(response)
+    }
+
+    fn parse_json<T: DeserializeOwned>(response: Response<isahc::Body>) -> Result<T, ClientError> {
+        let mut response = NodeClient::check_status(response)?;
+        let raw_body = response.text().map_err(isahc::Error::from)?;
+
+        Ok(serde_json::from_str(&raw_body)?)
+    }
+}
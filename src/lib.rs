@@ -0,0 +1,13 @@
+#[macro_use]
+extern crate log;
+
+pub mod api;
+pub mod bootstrap;
+#[cfg(feature = "client")]
+pub mod client;
+pub mod miner;
+pub mod model;
+pub mod peer;
+pub mod util;
+pub mod wallet;
+pub mod watchdog;
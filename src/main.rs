@@ -1,41 +1,207 @@
-use util::initialize_logger;
+use std::{sync::Arc, time::Duration};
 
-use crate::{
+use log::{error, info, warn};
+use rust_blockchain::{
     api::Api,
+    bootstrap,
     miner::Miner,
-    model::{Blockchain, TransactionPool},
+    model::{
+        difficulty_for_block_time, AuditLog, Blockchain, GenesisConfig, PeerAddresses,
+        PeerStatuses, RejectedBlocks, TransactionPool,
+    },
     peer::Peer,
-    util::{execution, termination, Config, Context},
+    util::{
+        execution, initialize_logger,
+        termination::{self, ShutdownFlag},
+        Config, Context, Metrics,
+    },
+    wallet::Wallet,
+    watchdog::Watchdog,
 };
 
-#[macro_use]
-extern crate log;
+fn keygen() {
+    let wallet = Wallet::generate();
 
-mod api;
-mod miner;
-mod model;
-mod peer;
-mod util;
+    println!("Private key: {}", wallet.private_key());
+    println!("Public key:  {}", wallet.public_key());
+    println!("Address:     {}", wallet.address());
+}
 
 fn main() {
+    if std::env::args().nth(1).as_deref() == Some("keygen") {
+        keygen();
+        return;
+    }
+
+    // the files can set LOG_LEVEL, so they are loaded before the logger
+    let loaded_files = Config::load_files();
+
     initialize_logger();
 
     info!("Starting up");
 
-    termination::set_ctrlc_handler();
+    let shutdown = ShutdownFlag::default();
+    termination::set_ctrlc_handler(shutdown.clone());
+
+    let config = loaded_files.and_then(|warnings| {
+        for warning in warnings {
+            warn!("{}", warning);
+        }
+
+        Config::try_read_env()
+    });
+
+    let mut config = match config {
+        Ok(config) => config,
+        Err(error) => {
+            error!("Could not read the configuration: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    if let Err(error) = config.validate() {
+        warn!("{}", error);
+    }
+
+    // for a single node in development, as peers must agree on the difficulty
+    if config.auto_difficulty {
+        let hashrate = Miner::benchmark_hashrate();
+        config.difficulty = difficulty_for_block_time(hashrate, config.target_block_time_ms);
+
+        info!(
+            "Benchmarked {:.0} hashes/s, using difficulty {} for a block every {}ms",
+            hashrate, config.difficulty, config.target_block_time_ms
+        );
+    }
+
+    info!("Effective configuration:\n{}", config.summary());
 
-    let config = Config::read();
-    let difficulty = config.difficulty;
+    let genesis = match config.genesis_file.as_str() {
+        "" => GenesisConfig::default(),
+        path => match GenesisConfig::from_file(path) {
+            Ok(genesis) => genesis,
+            Err(error) => {
+                error!("Could not read the genesis file: {}", error);
+                std::process::exit(1);
+            }
+        },
+    };
+
+    let store = match config.storage_backend.create_store() {
+        Ok(store) => store,
+        Err(error) => {
+            error!("Could not create the block store: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let balance_store = match config.balance_backend.create_store() {
+        Ok(balance_store) => balance_store,
+        Err(error) => {
+            error!("Could not create the balance store: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let audit_log = match config.audit_log_path.as_str() {
+        "" => AuditLog::default(),
+        path => match AuditLog::open(path) {
+            Ok(audit_log) => audit_log,
+            Err(error) => {
+                error!("Could not open the audit log: {}", error);
+                std::process::exit(1);
+            }
+        },
+    };
+
+    let blockchain = match Blockchain::from_genesis(config.difficulty, &genesis)
+        .with_store(store)
+        .and_then(|blockchain| blockchain.with_balance_store(balance_store))
+    {
+        Ok(blockchain) => blockchain
+            .with_difficulty_ramp(config.difficulty_ramp_blocks)
+            .with_halving_interval(config.halving_interval)
+            .with_coinbase_maturity(config.coinbase_maturity)
+            .with_require_coinbase_recipient(config.require_coinbase_recipient)
+            .with_require_existing_recipient(config.require_existing_recipient)
+            .with_reward_split(config.reward_split.clone())
+            .with_spending_policy(config.spending_policy.clone())
+            .with_parallel_validation(config.parallel_validation)
+            .with_audit_log(audit_log),
+        Err(error) => {
+            error!("Could not initialize the blockchain: {}", error);
+            std::process::exit(1);
+        }
+    };
+
+    let chain_file = config.chain_file.clone();
+    if !chain_file.is_empty() {
+        match blockchain.load_from_file(&chain_file) {
+            Ok(true) => info!(
+                "Loaded the chain from {} up to block {}",
+                chain_file,
+                blockchain.get_last_block().index
+            ),
+            Ok(false) => info!("No chain saved in {} yet, starting a new one", chain_file),
+            Err(error) => {
+                error!("Could not load the chain from {}: {}", chain_file, error);
+                std::process::exit(1);
+            }
+        }
+    }
+
+    if !config.bootstrap_from.is_empty() {
+        if let Err(error) =
+            bootstrap::bootstrap_from(&config.bootstrap_from, config.peer_timeout_ms, &blockchain)
+        {
+            error!("Could not bootstrap the blockchain: {}", error);
+            std::process::exit(1);
+        }
+    } else if !config.bootstrap_snapshot_from.is_empty() {
+        if let Err(error) = bootstrap::bootstrap_from_snapshot(
+            &config.bootstrap_snapshot_from,
+            config.peer_timeout_ms,
+            &blockchain,
+        ) {
+            error!("Could not bootstrap the blockchain: {}", error);
+            std::process::exit(1);
+        }
+    }
+
+    let rejected_blocks = RejectedBlocks::new(config.rejected_blocks_capacity);
+
+    let peer_addresses = PeerAddresses::new(config.peers.clone());
+
+    let pool = TransactionPool::new()
+        .with_ttl(Duration::from_millis(config.mempool_ttl_ms))
+        .with_pending_funds_check(config.mempool_pending_funds_check);
 
     let context = Context {
         config,
-        blockchain: Blockchain::new(difficulty),
-        pool: TransactionPool::new(),
+        blockchain: blockchain.clone(),
+        pool,
+        rejected_blocks,
+        peer_addresses,
+        peer_statuses: PeerStatuses::default(),
+        metrics: Metrics::new(),
+        shutdown,
     };
 
-    let miner = Miner::new(&context);
-    let api = Api::new(&context);
-    let peer = Peer::new(&context);
+    let miner = Arc::new(Miner::new(&context));
+    let peer = Arc::new(Peer::new(&context));
+    let api = Api::new(&context)
+        .with_peer(peer.clone())
+        .with_miner(miner.clone());
+    let watchdog = Watchdog::new(&context);
+
+    execution::run_in_parallel(vec![miner.as_ref(), &api, peer.as_ref(), &watchdog]);
+
+    if !chain_file.is_empty() {
+        match blockchain.save_to_file(&chain_file) {
+            Ok(()) => info!("Saved the chain to {}", chain_file),
+            Err(error) => error!("Could not save the chain to {}: {}", chain_file, error),
+        }
+    }
 
-    execution::run_in_parallel(vec![&miner, &api, &peer]);
+    info!("Shutdown complete");
 }
@@ -1,31 +1,72 @@
+use std::{
+    collections::VecDeque,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
 use anyhow::Result;
 use thiserror::Error;
 
 use crate::{
     model::{
-        Address, Block, BlockHash, Blockchain, Transaction, TransactionPool, TransactionVec,
-        BLOCK_SUBSIDY,
+        difficulty_to_target, Address, Block, BlockHash, Blockchain, RewardSplit, Transaction,
+        TransactionPool, TransactionVec, BLOCK_SUBSIDY,
     },
     util::{
-        execution::{sleep_millis, Runnable},
-        Context,
+        execution::Runnable,
+        sync::lock_recover,
+        termination::{is_shutdown_requested, sleep_unless_shutdown, ShutdownFlag},
+        Context, Metrics,
     },
 };
 
+// Number of mining rounds the hashrate is averaged over
+const HASHRATE_WINDOW: usize = 10;
+
+// Time spent hashing to estimate the hashrate of the machine
+const BENCHMARK_DURATION: Duration = Duration::from_millis(500);
+
+// A throttled miner works for this long before pausing, so the pauses stay short
+const THROTTLE_SLICE: Duration = Duration::from_millis(10);
+
+// Nonces tried between checks of the shutdown flag, so the check stays out of the hashing
+const SHUTDOWN_CHECK_NONCES: u64 = 1000;
+
 #[derive(Error, Debug)]
 pub enum MinerError {
     #[error("No valid block was mined at index `{0}`")]
     BlockNotMined(u64),
+
+    #[error("Not mining to the default zero address")]
+    DefaultMinerAddress,
+
+    #[error("The block mined at index `{0}` was built on a replaced tip")]
+    StaleTip(u64),
+
+    #[error("Mining of the block at index `{0}` stopped for a shutdown")]
+    ShutdownRequested(u64),
 }
 
 pub struct Miner {
     miner_address: Address,
+    // recipients of the block reward instead of the miner address, when configured
+    reward_split: RewardSplit,
+    allow_default_miner: bool,
     max_blocks: u64,
     max_nonce: u64,
+    // share of a core used while mining, from 1 to 100
+    throttle_pct: u8,
     transaction_waiting_ms: u64,
     blockchain: Blockchain,
     pool: TransactionPool,
-    target: BlockHash,
+    metrics: Metrics,
+    // attempted nonces and time spent for each of the last mining rounds
+    hashrate_samples: Mutex<VecDeque<(u64, Duration)>>,
+    // held for a whole round, so blocks mined through the api do not race the background miner
+    mining: Mutex<()>,
+    // work done since the last throttling pause, carried over rounds so short ones are throttled
+    throttle_worked: Mutex<Duration>,
+    shutdown: ShutdownFlag,
 }
 
 impl Runnable for Miner {
@@ -35,34 +76,66 @@ impl Runnable for Miner {
 }
 
 impl Miner {
-    fn create_target(difficulty: u32) -> BlockHash {
-        BlockHash::MAX >> difficulty
-    }
-
     pub fn new(context: &Context) -> Miner {
-        let target = Self::create_target(context.config.difficulty);
-
         Miner {
             miner_address: context.config.miner_address.clone(),
+            reward_split: context.config.reward_split.clone(),
+            allow_default_miner: context.config.allow_default_miner,
             max_blocks: context.config.max_blocks,
             max_nonce: context.config.max_nonce,
+            throttle_pct: context.config.miner_throttle_pct.clamp(1, 100),
             transaction_waiting_ms: context.config.transaction_waiting_ms,
             blockchain: context.blockchain.clone(),
             pool: context.pool.clone(),
-            target,
+            metrics: context.metrics.clone(),
+            hashrate_samples: Mutex::default(),
+            mining: Mutex::default(),
+            throttle_worked: Mutex::default(),
+            shutdown: context.shutdown.clone(),
         }
     }
 
+    // Hashes of a block like the ones being mined per second, on a single thread like the miner
+    pub fn benchmark_hashrate() -> f64 {
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: Address::default(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let mut block = Block::new(1, 0, BlockHash::default(), vec![coinbase]);
+
+        let start = Instant::now();
+        let mut hashes: u64 = 0;
+
+        while start.elapsed() < BENCHMARK_DURATION {
+            block.nonce = hashes;
+            block.hash = block.calculate_hash();
+            hashes += 1;
+        }
+
+        hashes as f64 / start.elapsed().as_secs_f64()
+    }
+
     fn must_stop_mining(&self, block_counter: u64) -> bool {
         self.max_blocks > 0 && block_counter >= self.max_blocks
     }
 
-    fn create_coinbase_transaction(&self) -> Transaction {
-        Transaction {
-            sender: Address::default(),
-            recipient: self.miner_address.clone(),
-            amount: BLOCK_SUBSIDY,
-        }
+    // One output for the miner address, or one per share when the reward is split
+    fn create_coinbase_transactions(&self, height: u64) -> TransactionVec {
+        let subsidy = self.blockchain.block_subsidy(height);
+        let outputs = match self.reward_split.is_empty() {
+            true => vec![(self.miner_address.clone(), subsidy)],
+            false => self.reward_split.split(subsidy),
+        };
+
+        outputs
+            .into_iter()
+            .map(|(recipient, amount)| Transaction {
+                sender: Address::default(),
+                recipient,
+                amount,
+            })
+            .collect()
     }
 
     fn create_next_block(
@@ -71,34 +144,203 @@ impl Miner {
         transactions: TransactionVec,
         nonce: u64,
     ) -> Block {
-        let index = (last_block.index + 1) as u64;
+        let index = last_block.index + 1;
         let previous_hash = last_block.hash;
 
-        Block::new(index, nonce, previous_hash, transactions)
+        let mut block = Block::new(index, nonce, previous_hash, transactions);
+        block.difficulty = self.blockchain.expected_difficulty(index);
+
+        // timestamps must strictly increase, even when blocks are mined within the same millisecond
+        if block.timestamp <= last_block.timestamp {
+            block.timestamp = last_block.timestamp + 1;
+        }
+
+        block.hash = block.calculate_hash();
+
+        block
     }
 
     fn mine_block(&self, last_block: &Block, transactions: &TransactionVec) -> Option<Block> {
-        let coinbase = self.create_coinbase_transaction();
-        let mut block_transactions = transactions.clone();
-        block_transactions.insert(0, coinbase);
+        let mut block_transactions = self.create_coinbase_transactions(last_block.index + 1);
+        block_transactions.extend(transactions.iter().cloned());
+
+        let difficulty = self.blockchain.expected_difficulty(last_block.index + 1);
+        let target = difficulty_to_target(difficulty);
+
+        let start = Instant::now();
+        let mut slice_start = start;
+        let mut worked = *lock_recover(&self.throttle_worked);
+        let mut attempts = 0;
+        let mut mined_block = None;
 
         for nonce in 0..self.max_nonce {
+            if nonce % SHUTDOWN_CHECK_NONCES == 0 && is_shutdown_requested(&self.shutdown) {
+                break;
+            }
+
+            self.throttle(&mut worked, &mut slice_start);
+
+            attempts += 1;
             let next_block = self.create_next_block(last_block, block_transactions.clone(), nonce);
 
-            if next_block.hash < self.target {
-                return Some(next_block);
+            if next_block.hash < target {
+                mined_block = Some(next_block);
+                break;
+            }
+        }
+
+        *lock_recover(&self.throttle_worked) = worked + slice_start.elapsed();
+        self.record_hashrate(attempts, start.elapsed());
+
+        mined_block
+    }
+
+    // Pauses after each slice of work, for as long as needed to keep the cpu usage to the
+    // configured share. At 50% the miner works for a slice, then sleeps for as long
+    fn throttle(&self, worked: &mut Duration, slice_start: &mut Instant) {
+        if self.throttle_pct >= 100 {
+            return;
+        }
+
+        let slice = *worked + slice_start.elapsed();
+        if slice < THROTTLE_SLICE {
+            return;
+        }
+
+        let pause = self.throttle_pause(slice);
+        sleep_unless_shutdown(&self.shutdown, pause.as_millis() as u64);
+
+        *worked = Duration::ZERO;
+        *slice_start = Instant::now();
+    }
+
+    fn throttle_pause(&self, worked: Duration) -> Duration {
+        let idle_ratio = (100 - self.throttle_pct) as f64 / self.throttle_pct as f64;
+
+        worked.mul_f64(idle_ratio)
+    }
+
+    fn record_hashrate(&self, attempts: u64, elapsed: Duration) {
+        let mut samples = lock_recover(&self.hashrate_samples);
+
+        samples.push_back((attempts, elapsed));
+        if samples.len() > HASHRATE_WINDOW {
+            samples.pop_front();
+        }
+
+        let total_attempts: u64 = samples.iter().map(|(attempts, _)| attempts).sum();
+        let total_elapsed: Duration = samples.iter().map(|(_, elapsed)| *elapsed).sum();
+
+        if !total_elapsed.is_zero() {
+            let hashrate = total_attempts as f64 / total_elapsed.as_secs_f64();
+            self.metrics.hashrate.set(hashrate);
+        }
+    }
+
+    fn is_default_miner_refused(&self) -> bool {
+        self.miner_address == Address::default() && !self.allow_default_miner
+    }
+
+    // Transactions of a block that was not added go back to the pool, unless a block received in
+    // the meantime already included them
+    fn requeue_unmined(&self, last_block: &Block, transactions: TransactionVec) {
+        let (confirmed, unmined): (TransactionVec, TransactionVec) =
+            transactions.into_iter().partition(|transaction| {
+                self.blockchain
+                    .is_confirmed_after(&transaction.hash(), last_block.index)
+            });
+
+        self.pool.release(&confirmed);
+        self.pool.requeue(unmined);
+    }
+
+    // Transactions of a block refused on the tip it was mined on are checked one by one against
+    // that tip, so only the ones a block can still include go back to the pool
+    fn requeue_valid(&self, transactions: TransactionVec) {
+        let (valid, invalid) = self.blockchain.partition_valid_transfers(transactions);
+
+        for (transaction, error) in invalid {
+            warn!(
+                "Dropped transaction {:#x} from the pool: {}",
+                transaction.hash(),
+                error
+            );
+            self.pool.release(&[transaction]);
+        }
+
+        self.pool.requeue(valid);
+    }
+
+    // Mines the transactions on top of the current last block and adds the result to the blockchain
+    fn mine_transactions(&self, transactions: TransactionVec) -> Result<Block> {
+        let _mining = lock_recover(&self.mining);
+        let last_block = self.blockchain.get_last_block();
+        let index = last_block.index + 1;
+
+        let block = match self.mine_block(&last_block, &transactions) {
+            Some(block) => block,
+            None if is_shutdown_requested(&self.shutdown) => {
+                self.requeue_unmined(&last_block, transactions);
+
+                return Err(MinerError::ShutdownRequested(index).into());
+            }
+            None => {
+                error!("No valid block was found for index {}", index);
+                self.requeue_unmined(&last_block, transactions);
+
+                return Err(MinerError::BlockNotMined(index).into());
+            }
+        };
+
+        info!("Valid block found for index {}", index);
+        if let Err(error) = self.blockchain.add_block(block.clone()) {
+            warn!("Mined block {} was not added: {}", index, error);
+
+            // a peer block may have been added while mining
+            if self.blockchain.get_last_block().hash != last_block.hash {
+                self.requeue_unmined(&last_block, transactions);
+                return Err(MinerError::StaleTip(index).into());
             }
+
+            self.requeue_valid(transactions);
+
+            return Err(error);
+        }
+
+        self.pool.release(&transactions);
+        self.metrics.blocks_mined.inc();
+        info!("Mining at {:.0} hashes/s", self.metrics.hashrate.get());
+
+        Ok(block)
+    }
+
+    // Mines a single block with the transactions currently in the pool, even if there are none
+    pub fn mine_pending(&self) -> Result<Block> {
+        if self.is_default_miner_refused() {
+            return Err(MinerError::DefaultMinerAddress.into());
         }
 
-        None
+        self.mine_transactions(self.pool.pop())
     }
 
     pub fn start(&self) -> Result<()> {
+        if self.is_default_miner_refused() {
+            error!("Not mining to the default zero address, set ALLOW_DEFAULT_MINER to allow it");
+
+            return Ok(());
+        }
+
         info!("Start mining with dificulty {}", self.blockchain.difficulty);
 
         let mut block_counter = 0;
 
         loop {
+            if is_shutdown_requested(&self.shutdown) {
+                info!("Shutdown requested, stopping mining");
+
+                return Ok(());
+            }
+
             if self.must_stop_mining(block_counter) {
                 info!("Block limit reached, stopping mining");
 
@@ -108,27 +350,18 @@ impl Miner {
             let transactions = self.pool.pop();
 
             if transactions.is_empty() {
-                sleep_millis(self.transaction_waiting_ms);
+                sleep_unless_shutdown(&self.shutdown, self.transaction_waiting_ms);
 
                 continue;
             }
 
-            let last_block = self.blockchain.get_last_block();
-            let mining_result = self.mine_block(&last_block, &transactions.clone());
-
-            match mining_result {
-                Some(block) => {
-                    info!("Valid block found for index {}", block.index);
-                    self.blockchain.add_block(block.clone())?;
-                    block_counter += 1;
-                }
-
-                None => {
-                    let index = last_block.index + 1;
-                    error!("No valid block was found for index {}", index);
-
-                    return Err(MinerError::BlockNotMined(index).into());
-                }
+            match self.mine_transactions(transactions) {
+                Ok(_) => block_counter += 1,
+                Err(error) => match error.downcast_ref() {
+                    Some(MinerError::BlockNotMined(_)) => return Err(error),
+                    // the transactions that can still be mined are back in the pool
+                    _ => continue,
+                },
             }
         }
     }
@@ -136,7 +369,21 @@ impl Miner {
 
 #[cfg(test)]
 mod tests {
-    use crate::model::test_person_util::{person1, person2};
+    use std::{
+        collections::BTreeMap,
+        str::FromStr,
+        sync::{atomic::Ordering, Arc},
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        model::{
+            test_person_util::{person1, person2, person3},
+            GenesisConfig,
+        },
+        util::execution::sleep_millis,
+    };
 
     use super::*;
 
@@ -147,22 +394,32 @@ mod tests {
     }
 
     fn create_miner(difficulty: u32, max_nonce: u64) -> Miner {
+        create_throttled_miner(difficulty, max_nonce, 100)
+    }
+
+    fn create_throttled_miner(difficulty: u32, max_nonce: u64, throttle_pct: u8) -> Miner {
         let miner_address = miner_address();
         let max_blocks = 1;
         let transaction_waiting_ms = 1;
-        let target = Miner::create_target(difficulty);
-
-        let blockchain = Blockchain::new(difficulty);
+        // the mock transactions spend the block rewards right away
+        let blockchain = Blockchain::new(difficulty).with_coinbase_maturity(0);
         let pool = TransactionPool::new();
 
         Miner {
             miner_address,
+            reward_split: RewardSplit::default(),
+            allow_default_miner: false,
             max_blocks,
             max_nonce,
+            throttle_pct,
             transaction_waiting_ms,
             blockchain,
             pool,
-            target,
+            metrics: Metrics::new(),
+            hashrate_samples: Mutex::default(),
+            mining: Mutex::default(),
+            throttle_worked: Mutex::default(),
+            shutdown: ShutdownFlag::default(),
         }
     }
 
@@ -174,7 +431,7 @@ mod tests {
     }
 
     fn create_empty_block() -> Block {
-        return Block::new(0, 0, BlockHash::default(), Vec::new());
+        Block::new(0, 0, BlockHash::default(), Vec::new())
     }
 
     #[test]
@@ -186,26 +443,13 @@ mod tests {
 
         assert_eq!(next_block.index, block.index + 1);
         assert_eq!(next_block.previous_hash, block.hash);
-    }
-
-    #[test]
-    fn test_create_target_valid_difficulty() {
-        for difficulty in 0..MAX_DIFFICULTY {
-            let target = Miner::create_target(difficulty);
-            assert_eq!(target.leading_zeros(), difficulty);
-        }
-    }
-
-    #[test]
-    fn test_create_target_overflowing_difficulty() {
-        let target = Miner::create_target(MAX_DIFFICULTY + 1);
-        assert_eq!(target.leading_zeros(), MAX_DIFFICULTY);
+        assert!(next_block.timestamp > block.timestamp);
     }
 
     fn assert_mined_block_is_valid(mined_block: &Block, previous_block: &Block, difficulty: u32) {
         assert_eq!(mined_block.index, previous_block.index + 1);
         assert_eq!(mined_block.previous_hash, previous_block.hash);
-        assert!(mined_block.hash.leading_zeros() >= difficulty as u32);
+        assert!(mined_block.hash.leading_zeros() >= difficulty);
     }
 
     #[test]
@@ -222,6 +466,21 @@ mod tests {
         assert_mined_block_is_valid(&mined_block, &last_block, difficulty);
     }
 
+    #[test]
+    fn test_mine_block_reports_hashrate() {
+        let difficulty = 8;
+        let max_nonce = 1_000_000;
+
+        let miner = create_miner(difficulty, max_nonce);
+        assert_eq!(miner.metrics.hashrate.get(), 0.0);
+
+        let last_block = create_empty_block();
+        let result = miner.mine_block(&last_block, &Vec::new());
+        assert!(result.is_some());
+
+        assert!(miner.metrics.hashrate.get() > 0.0);
+    }
+
     #[test]
     fn test_mine_block_not_found() {
         let difficulty = MAX_DIFFICULTY;
@@ -233,6 +492,49 @@ mod tests {
         assert!(result.is_none());
     }
 
+    #[test]
+    fn test_benchmark_hashrate() {
+        assert!(Miner::benchmark_hashrate() > 0.0);
+    }
+
+    #[test]
+    fn test_throttle_pause_keeps_cpu_share() {
+        let slice = Duration::from_millis(10);
+
+        let miner = create_throttled_miner(1, 1, 25);
+        assert_eq!(miner.throttle_pause(slice), slice * 3);
+
+        let miner = create_throttled_miner(1, 1, 50);
+        assert_eq!(miner.throttle_pause(slice), slice);
+    }
+
+    #[test]
+    fn test_throttle_carries_work_over_short_rounds() {
+        let last_block = create_empty_block();
+        let miner = create_throttled_miner(MAX_DIFFICULTY, 1, 25);
+
+        miner.mine_block(&last_block, &Vec::new());
+        let carried = *lock_recover(&miner.throttle_worked);
+        assert!(carried > Duration::ZERO);
+
+        // a round shorter than a slice still pauses once enough work has piled up
+        *lock_recover(&miner.throttle_worked) = THROTTLE_SLICE;
+        let start = Instant::now();
+        miner.mine_block(&last_block, &Vec::new());
+
+        assert!(start.elapsed() >= THROTTLE_SLICE * 3);
+        assert!(*lock_recover(&miner.throttle_worked) < THROTTLE_SLICE);
+    }
+
+    #[test]
+    fn test_throttled_mining_tries_every_nonce() {
+        let miner = create_throttled_miner(MAX_DIFFICULTY, 1_000, 25);
+        miner.mine_block(&create_empty_block(), &Vec::new());
+
+        let samples = lock_recover(&miner.hashrate_samples);
+        assert_eq!(samples[0].0, 1_000);
+    }
+
     fn add_mock_transaction(pool: &TransactionPool) {
         let transaction = Transaction {
             sender: miner_address(),
@@ -240,7 +542,7 @@ mod tests {
             amount: 3,
         };
 
-        pool.add_transaction(transaction.clone());
+        pool.add_transaction(transaction.clone()).unwrap();
     }
 
     #[test]
@@ -270,6 +572,34 @@ mod tests {
         assert!(transactions.is_empty());
     }
 
+    #[test]
+    fn test_run_splits_block_reward() {
+        let split = format!("{}:90,{}:10", miner_address(), person2());
+        let reward_split = RewardSplit::from_str(&split).unwrap();
+
+        let mut miner = create_miner(1, 1_000_000);
+        miner.reward_split = reward_split.clone();
+        miner.blockchain = Blockchain::new(1)
+            .with_coinbase_maturity(0)
+            .with_reward_split(reward_split);
+        add_mock_transaction(&miner.pool);
+
+        let result = miner.run();
+        assert!(result.is_ok());
+
+        let mined_block = miner.blockchain.get_last_block();
+        assert_eq!(mined_block.index, 1);
+        assert_eq!(mined_block.transactions.len(), 3);
+
+        let subsidy = miner.blockchain.block_subsidy(1);
+        assert_eq!(mined_block.transactions[0].amount, subsidy * 9 / 10);
+        assert_eq!(mined_block.transactions[1].amount, subsidy / 10);
+        assert_eq!(
+            miner.blockchain.get_balance(&person2()).unwrap(),
+            subsidy / 10 + 3
+        );
+    }
+
     #[test]
     #[should_panic(expected = "No valid block was mined at index `1`")]
     fn test_run_block_not_found() {
@@ -283,4 +613,163 @@ mod tests {
         // should return BlockNotMined error
         miner.run().unwrap();
     }
+
+    #[test]
+    fn test_run_drops_transactions_refused_by_the_chain() {
+        let genesis = GenesisConfig {
+            balances: BTreeMap::from([(person2(), 100)]),
+            ..GenesisConfig::default()
+        };
+        let mut miner = create_miner(1, 1_000_000);
+        miner.blockchain = Blockchain::from_genesis(1, &genesis).with_coinbase_maturity(0);
+
+        // each transfer is affordable on its own, but not both in the same block
+        for amount in [60, 50] {
+            let transaction = Transaction {
+                sender: person2(),
+                recipient: person3(),
+                amount,
+            };
+            miner.pool.add_transaction(transaction).unwrap();
+        }
+
+        let result = miner.run();
+        assert!(result.is_ok());
+
+        let mined_block = miner.blockchain.get_last_block();
+        assert_eq!(mined_block.index, 1);
+        assert_eq!(mined_block.transactions.len(), 2);
+        assert_eq!(mined_block.transactions[1].amount, 60);
+        assert!(miner.pool.is_empty());
+    }
+
+    #[test]
+    fn test_run_refuses_default_miner_address() {
+        let mut miner = create_miner(1, 1_000_000);
+        miner.miner_address = Address::default();
+        add_mock_transaction(&miner.pool);
+
+        let result = miner.run();
+        assert!(result.is_ok());
+        assert_eq!(miner.blockchain.get_all_blocks().len(), 1);
+        assert_eq!(miner.pool.len(), 1);
+    }
+
+    #[test]
+    fn test_run_stops_on_shutdown() {
+        let mut miner = create_default_miner();
+        miner.max_blocks = 0;
+
+        let shutdown = miner.shutdown.clone();
+        let handle = thread::spawn(move || {
+            sleep_millis(50);
+            shutdown.store(true, Ordering::SeqCst);
+        });
+
+        let start = Instant::now();
+        let result = miner.run();
+        handle.join().unwrap();
+
+        assert!(result.is_ok());
+        assert!(start.elapsed() < Duration::from_secs(1));
+    }
+
+    #[test]
+    fn test_throttled_mining_stops_on_shutdown() {
+        let miner = create_throttled_miner(MAX_DIFFICULTY, u64::MAX, 1);
+        add_mock_transaction(&miner.pool);
+
+        let shutdown = miner.shutdown.clone();
+        let handle = thread::spawn(move || {
+            sleep_millis(50);
+            shutdown.store(true, Ordering::SeqCst);
+        });
+
+        let start = Instant::now();
+        let result = miner.mine_pending();
+        handle.join().unwrap();
+
+        let error = result.unwrap_err().downcast::<MinerError>().unwrap();
+        assert!(matches!(error, MinerError::ShutdownRequested(1)));
+        assert!(start.elapsed() < Duration::from_secs(1));
+
+        // the transactions go back to the pool, to be flushed or mined after a restart
+        assert_eq!(miner.pool.len(), 1);
+    }
+
+    #[test]
+    fn test_subscriber_receives_mined_block() {
+        let difficulty = 1;
+        let max_nonce = 1_000_000;
+
+        let miner = create_miner(difficulty, max_nonce);
+        let receiver = miner.blockchain.subscribe();
+        add_mock_transaction(&miner.pool);
+
+        let handle = thread::spawn(move || miner.run());
+
+        let block = receiver.recv_timeout(Duration::from_secs(5)).unwrap();
+        assert_eq!(block.index, 1);
+
+        let result = handle.join().unwrap();
+        assert!(result.is_ok());
+    }
+
+    #[test]
+    fn test_mine_pending_includes_pool_transactions() {
+        let miner = create_miner(1, 1_000_000);
+        add_mock_transaction(&miner.pool);
+
+        let block = miner.mine_pending().unwrap();
+        assert_eq!(block.index, 1);
+        assert_eq!(block.transactions.len(), 2);
+        assert_eq!(miner.blockchain.get_last_block(), block);
+        assert!(miner.pool.is_empty());
+    }
+
+    #[test]
+    fn test_mine_pending_not_found() {
+        let miner = create_miner(MAX_DIFFICULTY, 1);
+        add_mock_transaction(&miner.pool);
+
+        let result = miner.mine_pending();
+        assert!(result.is_err());
+        assert_eq!(miner.blockchain.get_all_blocks().len(), 1);
+        assert_eq!(miner.pool.len(), 1);
+    }
+
+    #[test]
+    fn test_api_and_background_rounds_share_hashrate_window() {
+        let miner = Arc::new(create_miner(1, 1_000_000));
+        let background = miner.clone();
+
+        miner.mine_pending().unwrap();
+        add_mock_transaction(&miner.pool);
+        background.run().unwrap();
+
+        assert_eq!(lock_recover(&miner.hashrate_samples).len(), 2);
+    }
+
+    #[test]
+    fn test_stale_tip_requeues_transactions() {
+        let miner = create_miner(1, 1_000_000);
+        add_mock_transaction(&miner.pool);
+
+        // another block lands on the tip the miner is working on
+        let genesis = miner.blockchain.get_last_block();
+        let peer_block = miner.mine_block(&genesis, &Vec::new()).unwrap();
+        let transactions = miner.pool.pop();
+        miner.blockchain.add_block(peer_block).unwrap();
+
+        let block = miner.mine_block(&genesis, &transactions).unwrap();
+        assert!(miner.blockchain.add_block(block).is_err());
+        miner.requeue_unmined(&genesis, transactions);
+        assert_eq!(miner.pool.len(), 1);
+
+        // the background miner goes on mining on the new tip
+        let result = miner.run();
+        assert!(result.is_ok());
+        assert_eq!(miner.blockchain.get_last_block().index, 2);
+        assert!(miner.pool.is_empty());
+    }
 }
@@ -1,15 +1,45 @@
 mod account_balance_map;
 mod address;
+mod audit_log;
+mod balance_store;
+mod binary_format;
 mod block;
+mod block_store;
 mod blockchain;
+mod difficulty;
+mod genesis;
+mod peer_addresses;
+mod peer_statuses;
+mod rejected_blocks;
+mod reward_split;
+pub(crate) mod serde_number;
+mod snapshot;
+mod spending_policy;
 mod transaction;
 mod transaction_pool;
 
-pub use address::Address;
-pub use block::{Block, BlockHash};
-pub use blockchain::{Blockchain, BlockchainError, BLOCK_SUBSIDY};
-pub use transaction::Transaction;
-pub use transaction_pool::{TransactionPool, TransactionVec};
+pub use account_balance_map::{AccountBalanceMapError, Amount};
+pub use address::{Address, AddressError};
+pub use audit_log::{AuditEntry, AuditLog};
+pub use balance_store::{BalanceBackend, BalanceStore, SharedBalanceStore};
+pub use block::{Block, BlockHash, BLOCK_VERSION, HASH_ALGORITHM};
+pub use block_store::{BlockStore, BoxedBlockStore, StorageBackend, DEFAULT_HOT_BLOCKS};
+pub use blockchain::{
+    Blockchain, BlockchainError, ChainValidationError, Placement, BLOCK_SUBSIDY,
+    DEFAULT_COINBASE_MATURITY, DEFAULT_HALVING_INTERVAL,
+};
+pub use difficulty::{
+    compact_to_target, difficulty_for_block_time, difficulty_to_target, target_to_compact,
+};
+pub use genesis::GenesisConfig;
+pub use peer_addresses::{PeerAddressError, PeerAddresses};
+pub use peer_statuses::{PeerStatus, PeerStatuses};
+pub use rejected_blocks::{RejectedBlock, RejectedBlockVec, RejectedBlocks};
+pub use reward_split::{RewardSplit, RewardSplitError};
+pub use snapshot::ChainSnapshot;
+pub use spending_policy::{SpendingPolicy, SpendingPolicyError};
+pub use transaction::{Transaction, TransactionHash};
+pub use transaction_pool::{PoolError, TransactionPool, TransactionVec};
 
 #[cfg(test)]
 pub use address::test_person_util;
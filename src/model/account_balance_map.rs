@@ -1,8 +1,14 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, sync::Arc};
 
+use anyhow::Result;
+use serde::{Deserialize, Serialize, Serializer};
 use thiserror::Error;
 
-use super::address::Address;
+use super::{
+    address::Address,
+    audit_log::AuditEntry,
+    balance_store::{InMemoryBalanceStore, SharedBalanceStore},
+};
 
 pub type Amount = u64;
 
@@ -13,34 +19,223 @@ pub enum AccountBalanceMapError {
 
     #[error("Insufficient funds")]
     InsufficientFunds,
+
+    #[error("Balance overflow")]
+    BalanceOverflow,
+
+    #[error("Balance store failed: {0}")]
+    BalanceStore(String),
+}
+
+// Balances of the accounts, as serialized in snapshots
+#[derive(Serialize, Deserialize)]
+struct SerializedBalances {
+    balances: HashMap<Address, Amount>,
+    immature_coinbase: Vec<(u64, Address, Amount)>,
+}
+
+// The committed balances are kept in a store shared by the clones of the map, which only copy the
+// changes made since the last commit. Blocks are validated on a clone, that replaces the map and
+// is committed once the block is added
+#[derive(Debug, Clone, Deserialize)]
+#[serde(from = "SerializedBalances")]
+pub struct AccountBalanceMap {
+    store: SharedBalanceStore,
+    changes: HashMap<Address, Amount>,
+    // block index and changes recorded since auditing started, if it did
+    audit: Option<(u64, Vec<AuditEntry>)>,
+    // coinbase rewards with the block index from which they can be spent
+    immature_coinbase: Vec<(u64, Address, Amount)>,
+}
+
+impl Default for AccountBalanceMap {
+    fn default() -> Self {
+        AccountBalanceMap::with_store(Arc::<InMemoryBalanceStore>::default())
+    }
+}
+
+impl From<SerializedBalances> for AccountBalanceMap {
+    fn from(serialized: SerializedBalances) -> Self {
+        AccountBalanceMap {
+            changes: serialized.balances,
+            immature_coinbase: serialized.immature_coinbase,
+            ..AccountBalanceMap::default()
+        }
+    }
 }
 
-#[derive(Debug, Default, Clone)]
-pub struct AccountBalanceMap(HashMap<Address, Amount>);
+// Reading the store can fail, so the balances are serialized by hand
+impl Serialize for AccountBalanceMap {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let serialized = self.to_serialized().map_err(serde::ser::Error::custom)?;
+
+        serialized.serialize(serializer)
+    }
+}
+
+fn store_error(error: anyhow::Error) -> AccountBalanceMapError {
+    AccountBalanceMapError::BalanceStore(error.to_string())
+}
 
 impl AccountBalanceMap {
-    pub fn get_receipient_balance(&self, recipient: &Address) -> Amount {
-        match self.0.get(recipient) {
-            Some(amount) => *amount,
-            None => 0,
+    pub fn with_store(store: SharedBalanceStore) -> AccountBalanceMap {
+        AccountBalanceMap {
+            store,
+            changes: HashMap::new(),
+            audit: None,
+            immature_coinbase: Vec::new(),
         }
     }
 
-    pub fn get_sender_balance(&self, sender: &Address) -> Result<Amount, AccountBalanceMapError> {
-        match self.0.get(sender) {
-            Some(balance) => Ok(*balance),
-            None => Err(AccountBalanceMapError::SenderAccountDoesNotExist),
+    pub fn get_store(&self) -> SharedBalanceStore {
+        self.store.clone()
+    }
+
+    fn get_all_balances(&self) -> Result<HashMap<Address, Amount>, AccountBalanceMapError> {
+        let entries = self.store.entries().map_err(store_error)?;
+
+        let mut balances: HashMap<Address, Amount> = entries.into_iter().collect();
+        balances.extend(
+            self.changes
+                .iter()
+                .map(|(address, amount)| (address.clone(), *amount)),
+        );
+
+        Ok(balances)
+    }
+
+    fn to_serialized(&self) -> Result<SerializedBalances, AccountBalanceMapError> {
+        Ok(SerializedBalances {
+            balances: self.get_all_balances()?,
+            immature_coinbase: self.immature_coinbase.clone(),
+        })
+    }
+
+    // Writes the changes to the store, they are kept on top of it if the write fails
+    pub fn commit(&mut self) -> Result<()> {
+        self.store.write(&self.changes)?;
+        self.changes.clear();
+
+        Ok(())
+    }
+
+    // Replaces the content of the given store with the balances of the map, which then uses it
+    pub fn move_to(self, store: SharedBalanceStore) -> Result<AccountBalanceMap> {
+        let balances = self.get_all_balances()?;
+
+        store.clear()?;
+        store.write(&balances)?;
+
+        Ok(AccountBalanceMap {
+            store,
+            changes: HashMap::new(),
+            audit: self.audit,
+            immature_coinbase: self.immature_coinbase,
+        })
+    }
+
+    // Copy that no longer follows the commits made to the store, like the ones of later blocks
+    pub fn to_in_memory(&self) -> Result<AccountBalanceMap, AccountBalanceMapError> {
+        Ok(self.to_serialized()?.into())
+    }
+
+    // Records every following balance change as part of the given block
+    pub fn start_audit(&mut self, block_index: u64) {
+        self.audit = Some((block_index, Vec::new()));
+    }
+
+    pub fn take_audit_entries(&mut self) -> Vec<AuditEntry> {
+        match self.audit.take() {
+            Some((_, entries)) => entries,
+            None => Vec::new(),
         }
     }
 
+    fn audit_change(&mut self, address: &Address, delta: i128, balance: Amount) {
+        if let Some((block_index, entries)) = self.audit.as_mut() {
+            entries.push(AuditEntry {
+                block_index: *block_index,
+                address: address.clone(),
+                delta,
+                balance,
+            });
+        }
+    }
+
+    fn get_balance(&self, address: &Address) -> Result<Option<Amount>, AccountBalanceMapError> {
+        match self.changes.get(address) {
+            Some(amount) => Ok(Some(*amount)),
+            None => self.store.get(address).map_err(store_error),
+        }
+    }
+
+    pub fn get_receipient_balance(
+        &self,
+        recipient: &Address,
+    ) -> Result<Amount, AccountBalanceMapError> {
+        Ok(self.get_balance(recipient)?.unwrap_or(0))
+    }
+
+    // Accounts exist once they received funds, even if their balance went back to zero
+    pub fn has_account(&self, address: &Address) -> Result<bool, AccountBalanceMapError> {
+        Ok(self.get_balance(address)?.is_some())
+    }
+
+    // Keeps the amount out of the spendable balance of the address until the given block index
+    pub fn lock_until(&mut self, address: &Address, amount: Amount, spendable_from: u64) {
+        self.immature_coinbase
+            .push((spendable_from, address.clone(), amount));
+    }
+
+    // Forgets the locks that no longer apply from the given block index on
+    pub fn release_matured(&mut self, block_index: u64) {
+        self.immature_coinbase
+            .retain(|(spendable_from, _, _)| *spendable_from > block_index);
+    }
+
+    // Balance that can be spent in the block with the given index
+    pub fn get_spendable_balance(
+        &self,
+        address: &Address,
+        block_index: u64,
+    ) -> Result<Amount, AccountBalanceMapError> {
+        let immature: Amount = self
+            .immature_coinbase
+            .iter()
+            .filter(|(spendable_from, locked, _)| {
+                *spendable_from > block_index && locked == address
+            })
+            .map(|(_, _, amount)| amount)
+            .sum();
+
+        Ok(self
+            .get_receipient_balance(address)?
+            .saturating_sub(immature))
+    }
+
+    pub fn get_sender_balance(&self, sender: &Address) -> Result<Amount, AccountBalanceMapError> {
+        self.get_balance(sender)?
+            .ok_or(AccountBalanceMapError::SenderAccountDoesNotExist)
+    }
+
     pub fn update_balance(&mut self, address: &Address, new_balance: Amount) {
-        let balance = self.0.entry(address.clone()).or_insert(0);
-        *balance = new_balance;
+        self.changes.insert(address.clone(), new_balance);
     }
 
-    pub fn add_amount(&mut self, recipient: &Address, amount: Amount) {
-        let balance = self.get_receipient_balance(recipient);
-        self.update_balance(recipient, balance + amount);
+    pub fn add_amount(
+        &mut self,
+        recipient: &Address,
+        amount: Amount,
+    ) -> Result<(), AccountBalanceMapError> {
+        let balance = self
+            .get_receipient_balance(recipient)?
+            .checked_add(amount)
+            .ok_or(AccountBalanceMapError::BalanceOverflow)?;
+
+        self.update_balance(recipient, balance);
+        self.audit_change(recipient, amount as i128, balance);
+
+        Ok(())
     }
 
     pub fn transfer(
@@ -49,16 +244,243 @@ impl AccountBalanceMap {
         recipient: &Address,
         amount: Amount,
     ) -> Result<(), AccountBalanceMapError> {
-        let sender_balance = self.get_sender_balance(sender)?;
-        let recipient_balance = self.get_receipient_balance(recipient);
+        let sender_balance = self
+            .get_sender_balance(sender)?
+            .checked_sub(amount)
+            .ok_or(AccountBalanceMapError::InsufficientFunds)?;
 
-        if sender_balance < amount {
-            return Err(AccountBalanceMapError::InsufficientFunds);
-        }
+        // checked before any change, so a failed transfer leaves both balances untouched
+        let recipient_balance = self
+            .get_receipient_balance(recipient)?
+            .checked_add(amount)
+            .ok_or(AccountBalanceMapError::BalanceOverflow)?;
+
+        self.update_balance(sender, sender_balance);
+        self.audit_change(sender, -(amount as i128), sender_balance);
 
-        self.update_balance(sender, sender_balance - amount);
-        self.update_balance(recipient, recipient_balance + amount);
+        self.update_balance(recipient, recipient_balance);
+        self.audit_change(recipient, amount as i128, recipient_balance);
 
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::model::{
+        address::test_person_util::{person1, person2, person3},
+        balance_store::{BalanceBackend, BalanceStore},
+    };
+
+    use super::*;
+
+    type Outcome = Result<(), AccountBalanceMapError>;
+
+    // Outcomes of the same operations, with the balances and accounts they leave
+    fn run_sequence(backend: BalanceBackend) -> (Vec<Outcome>, Vec<Amount>, Vec<bool>) {
+        let mut account_balances = AccountBalanceMap::with_store(backend.create_store().unwrap());
+
+        let mut outcomes = vec![
+            account_balances.transfer(&person1(), &person2(), 10),
+            account_balances.add_amount(&person1(), 100),
+            account_balances.transfer(&person1(), &person2(), 30),
+        ];
+        account_balances.commit().unwrap();
+
+        outcomes.push(account_balances.transfer(&person2(), &person1(), 31));
+        outcomes.push(account_balances.add_amount(&person2(), u64::MAX));
+        outcomes.push(account_balances.transfer(&person2(), &person3(), 30));
+        outcomes.push(account_balances.transfer(&person1(), &person3(), 71));
+        account_balances.commit().unwrap();
+
+        let people = [person1(), person2(), person3()];
+        let balances = people
+            .iter()
+            .map(|person| account_balances.get_receipient_balance(person).unwrap())
+            .collect();
+        let accounts = people
+            .iter()
+            .map(|person| account_balances.has_account(person).unwrap())
+            .collect();
+
+        (outcomes, balances, accounts)
+    }
+
+    #[test]
+    fn backends_give_same_results() {
+        let (outcomes, balances, accounts) = run_sequence(BalanceBackend::Memory);
+
+        assert_eq!(
+            outcomes,
+            vec![
+                Err(AccountBalanceMapError::SenderAccountDoesNotExist),
+                Ok(()),
+                Ok(()),
+                Err(AccountBalanceMapError::InsufficientFunds),
+                Err(AccountBalanceMapError::BalanceOverflow),
+                Ok(()),
+                Err(AccountBalanceMapError::InsufficientFunds),
+            ]
+        );
+        assert_eq!(balances, vec![70, 0, 30]);
+        assert_eq!(accounts, vec![true, true, true]);
+
+        assert_eq!(
+            run_sequence(BalanceBackend::Sled),
+            (outcomes, balances, accounts)
+        );
+    }
+
+    #[test]
+    fn uncommitted_changes_stay_out_of_the_store() {
+        let store = BalanceBackend::Sled.create_store().unwrap();
+        let mut account_balances = AccountBalanceMap::with_store(store.clone());
+        account_balances.add_amount(&person1(), 100).unwrap();
+        account_balances.commit().unwrap();
+
+        let mut tentative = account_balances.clone();
+        tentative.transfer(&person1(), &person2(), 40).unwrap();
+
+        assert_eq!(store.get(&person1()).unwrap(), Some(100));
+        assert_eq!(
+            account_balances.get_receipient_balance(&person2()).unwrap(),
+            0
+        );
+        assert_eq!(tentative.get_receipient_balance(&person2()).unwrap(), 40);
+
+        tentative.commit().unwrap();
+        assert_eq!(store.get(&person1()).unwrap(), Some(60));
+        assert_eq!(store.get(&person2()).unwrap(), Some(40));
+    }
+
+    #[derive(Debug)]
+    struct UnreadableStore;
+
+    impl BalanceStore for UnreadableStore {
+        fn get(&self, _: &Address) -> Result<Option<Amount>> {
+            Err(anyhow::anyhow!("disk unavailable"))
+        }
+
+        fn entries(&self) -> Result<Vec<(Address, Amount)>> {
+            Err(anyhow::anyhow!("disk unavailable"))
+        }
+
+        fn write(&self, _: &HashMap<Address, Amount>) -> Result<()> {
+            Ok(())
+        }
+
+        fn clear(&self) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn store_read_failures_are_errors() {
+        let mut account_balances = AccountBalanceMap::with_store(Arc::new(UnreadableStore));
+        let store_error = || AccountBalanceMapError::BalanceStore(String::from("disk unavailable"));
+
+        let result = account_balances.transfer(&person1(), &person2(), 10);
+        assert_eq!(result, Err(store_error()));
+        assert_eq!(account_balances.to_in_memory().unwrap_err(), store_error());
+        assert!(serde_json::to_string(&account_balances).is_err());
+    }
+
+    #[test]
+    fn transfer_produces_debit_and_credit_entries() {
+        let mut account_balances = AccountBalanceMap::default();
+        account_balances.add_amount(&person1(), 100).unwrap();
+
+        account_balances.start_audit(3);
+        account_balances
+            .transfer(&person1(), &person2(), 30)
+            .unwrap();
+
+        let entries = account_balances.take_audit_entries();
+        assert_eq!(entries.len(), 2);
+
+        assert_eq!(entries[0].block_index, 3);
+        assert_eq!(entries[0].address, person1());
+        assert_eq!(entries[0].delta, -30);
+        assert_eq!(entries[0].balance, 70);
+
+        assert_eq!(entries[1].address, person2());
+        assert_eq!(entries[1].delta, 30);
+        assert_eq!(entries[1].balance, 30);
+    }
+
+    #[test]
+    fn changes_are_not_recorded_without_audit() {
+        let mut account_balances = AccountBalanceMap::default();
+        account_balances.add_amount(&person1(), 100).unwrap();
+
+        assert!(account_balances.take_audit_entries().is_empty());
+    }
+
+    #[test]
+    fn adding_past_max_balance_overflows() {
+        let mut account_balances = AccountBalanceMap::default();
+        account_balances.add_amount(&person1(), u64::MAX).unwrap();
+
+        let result = account_balances.add_amount(&person1(), 1);
+
+        assert_eq!(result, Err(AccountBalanceMapError::BalanceOverflow));
+        assert_eq!(
+            account_balances.get_receipient_balance(&person1()).unwrap(),
+            u64::MAX
+        );
+    }
+
+    #[test]
+    fn transfer_overflowing_recipient_leaves_balances_unchanged() {
+        let mut account_balances = AccountBalanceMap::default();
+        account_balances.add_amount(&person1(), 10).unwrap();
+        account_balances.add_amount(&person2(), u64::MAX).unwrap();
+
+        let result = account_balances.transfer(&person1(), &person2(), 10);
+
+        assert_eq!(result, Err(AccountBalanceMapError::BalanceOverflow));
+        assert_eq!(
+            account_balances.get_receipient_balance(&person1()).unwrap(),
+            10
+        );
+        assert_eq!(
+            account_balances.get_receipient_balance(&person2()).unwrap(),
+            u64::MAX
+        );
+    }
+
+    #[test]
+    fn locked_amount_is_spendable_once_mature() {
+        let mut account_balances = AccountBalanceMap::default();
+        account_balances.add_amount(&person1(), 100).unwrap();
+        account_balances.add_amount(&person1(), 50).unwrap();
+        account_balances.lock_until(&person1(), 50, 5);
+
+        assert_eq!(
+            account_balances
+                .get_spendable_balance(&person1(), 4)
+                .unwrap(),
+            100
+        );
+        assert_eq!(
+            account_balances
+                .get_spendable_balance(&person1(), 5)
+                .unwrap(),
+            150
+        );
+        assert_eq!(
+            account_balances
+                .get_spendable_balance(&person2(), 4)
+                .unwrap(),
+            0
+        );
+
+        account_balances.release_matured(5);
+        assert_eq!(
+            account_balances
+                .get_spendable_balance(&person1(), 0)
+                .unwrap(),
+            150
+        );
+    }
+}
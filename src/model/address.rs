@@ -15,7 +15,7 @@ pub enum AddressError {
     InvalidLength,
 }
 
-#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Default, Clone, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
 #[serde(try_from = "String", into = "String")]
 pub struct Address([Byte; LEN]);
 
@@ -62,6 +62,18 @@ impl From<Address> for String {
     }
 }
 
+impl From<[Byte; LEN]> for Address {
+    fn from(bytes: [Byte; LEN]) -> Self {
+        Address(bytes)
+    }
+}
+
+impl Address {
+    pub fn to_bytes(&self) -> [Byte; LEN] {
+        self.0
+    }
+}
+
 #[cfg(test)]
 pub mod test_person_util {
     use super::Address;
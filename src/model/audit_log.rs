@@ -0,0 +1,104 @@
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use crate::util::sync::lock_recover;
+
+use super::{account_balance_map::Amount, address::Address};
+
+// A single balance change, with a negative delta for debits
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct AuditEntry {
+    pub block_index: u64,
+    pub address: Address,
+    pub delta: i128,
+    pub balance: Amount,
+}
+
+// Appends every balance change of the accepted blocks to a JSONL file, disabled by default
+#[derive(Debug, Default, Clone)]
+pub struct AuditLog {
+    file: Option<Arc<Mutex<File>>>,
+}
+
+impl AuditLog {
+    pub fn open(path: &str) -> Result<AuditLog> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+        Ok(AuditLog {
+            file: Some(Arc::new(Mutex::new(file))),
+        })
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.file.is_some()
+    }
+
+    pub fn write(&self, entries: &[AuditEntry]) -> Result<()> {
+        let file = match &self.file {
+            Some(file) => file,
+            None => return Ok(()),
+        };
+
+        let mut lines = Vec::new();
+        for entry in entries {
+            serde_json::to_writer(&mut lines, entry)?;
+            lines.push(b'\n');
+        }
+
+        lock_recover(file).write_all(&lines)?;
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process};
+
+    use crate::model::address::test_person_util::person1;
+
+    use super::*;
+
+    #[test]
+    fn should_append_entries_as_json_lines() {
+        let path = std::env::temp_dir().join(format!("audit-log-{}.jsonl", process::id()));
+        let path = path.to_str().unwrap();
+        let _ = fs::remove_file(path);
+
+        let audit_log = AuditLog::open(path).unwrap();
+        let entry = AuditEntry {
+            block_index: 1,
+            address: person1(),
+            delta: -5,
+            balance: 95,
+        };
+        audit_log.write(&[entry.clone(), entry]).unwrap();
+
+        let contents = fs::read_to_string(path).unwrap();
+        fs::remove_file(path).unwrap();
+
+        let lines: Vec<&str> = contents.lines().collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(
+            lines[0],
+            format!(
+                r#"{{"block_index":1,"address":"{}","delta":-5,"balance":95}}"#,
+                person1()
+            )
+        );
+    }
+
+    #[test]
+    fn should_do_nothing_when_disabled() {
+        let audit_log = AuditLog::default();
+
+        assert!(!audit_log.is_enabled());
+        assert!(audit_log.write(&[]).is_ok());
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    collections::HashMap,
+    env,
+    fmt::Debug,
+    process,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, RwLock,
+    },
+};
+
+use anyhow::Result;
+use thiserror::Error;
+
+use crate::util::sync::{read_recover, write_recover};
+
+use super::{account_balance_map::Amount, address::Address};
+
+pub type SharedBalanceStore = Arc<dyn BalanceStore>;
+
+// Tells apart the databases of the sled stores of a process
+static SLED_STORE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Error, PartialEq, Debug)]
+pub enum BalanceStoreError {
+    #[error("Unknown balance backend `{0}`")]
+    UnknownBackend(String),
+
+    #[error("Corrupt balance entry for `{0}`")]
+    CorruptEntry(String),
+}
+
+// Storage of the balances of the accounts as of the last block. Changes are written in batches,
+// which are applied entirely or not at all
+pub trait BalanceStore: Debug + Send + Sync {
+    fn get(&self, address: &Address) -> Result<Option<Amount>>;
+    fn entries(&self) -> Result<Vec<(Address, Amount)>>;
+    fn write(&self, balances: &HashMap<Address, Amount>) -> Result<()>;
+    fn clear(&self) -> Result<()>;
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryBalanceStore {
+    balances: RwLock<HashMap<Address, Amount>>,
+}
+
+impl BalanceStore for InMemoryBalanceStore {
+    fn get(&self, address: &Address) -> Result<Option<Amount>> {
+        Ok(read_recover(&self.balances).get(address).copied())
+    }
+
+    fn entries(&self) -> Result<Vec<(Address, Amount)>> {
+        let entries = read_recover(&self.balances)
+            .iter()
+            .map(|(address, amount)| (address.clone(), *amount))
+            .collect();
+
+        Ok(entries)
+    }
+
+    fn write(&self, balances: &HashMap<Address, Amount>) -> Result<()> {
+        let mut stored = write_recover(&self.balances);
+        for (address, amount) in balances {
+            stored.insert(address.clone(), *amount);
+        }
+
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        write_recover(&self.balances).clear();
+
+        Ok(())
+    }
+}
+
+// Balances are kept on disk in a sled database, keyed by address, so they do not all live in RAM.
+// The database is temporary and removed with the store. Balances follow from the blocks, which
+// are replayed when the chain is loaded at startup, so a database left by a previous run is never
+// trusted: it would also need the tip it belongs to and the locked coinbase rewards
+#[derive(Debug)]
+pub struct SledBalanceStore {
+    db: sled::Db,
+}
+
+impl SledBalanceStore {
+    pub fn new() -> Result<SledBalanceStore> {
+        let path = env::temp_dir().join(format!(
+            "rust_blockchain_{}_{}.balances",
+            process::id(),
+            SLED_STORE_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        let db = sled::Config::new().path(path).temporary(true).open()?;
+
+        Ok(SledBalanceStore { db })
+    }
+
+    fn parse_amount(key: &str, value: &[u8]) -> Result<Amount> {
+        let bytes = value
+            .try_into()
+            .map_err(|_| BalanceStoreError::CorruptEntry(key.to_string()))?;
+
+        Ok(Amount::from_be_bytes(bytes))
+    }
+
+    fn parse_entry(key: &[u8], value: &[u8]) -> Result<(Address, Amount)> {
+        let key = std::str::from_utf8(key)
+            .map_err(|_| BalanceStoreError::CorruptEntry(String::from_utf8_lossy(key).into()))?;
+        let address =
+            Address::from_str(key).map_err(|_| BalanceStoreError::CorruptEntry(key.to_string()))?;
+
+        Ok((address, SledBalanceStore::parse_amount(key, value)?))
+    }
+}
+
+impl BalanceStore for SledBalanceStore {
+    fn get(&self, address: &Address) -> Result<Option<Amount>> {
+        let key = address.to_string();
+
+        match self.db.get(&key)? {
+            Some(value) => Ok(Some(SledBalanceStore::parse_amount(&key, &value)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn entries(&self) -> Result<Vec<(Address, Amount)>> {
+        self.db
+            .iter()
+            .map(|entry| {
+                let (key, value) = entry?;
+
+                SledBalanceStore::parse_entry(&key, &value)
+            })
+            .collect()
+    }
+
+    fn write(&self, balances: &HashMap<Address, Amount>) -> Result<()> {
+        let mut batch = sled::Batch::default();
+        for (address, amount) in balances {
+            batch.insert(address.to_string().as_bytes(), &amount.to_be_bytes());
+        }
+
+        self.db.apply_batch(batch)?;
+
+        Ok(())
+    }
+
+    fn clear(&self) -> Result<()> {
+        self.db.clear()?;
+
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BalanceBackend {
+    Memory,
+    Sled,
+}
+
+impl BalanceBackend {
+    pub fn create_store(&self) -> Result<SharedBalanceStore> {
+        let store: SharedBalanceStore = match self {
+            BalanceBackend::Memory => Arc::<InMemoryBalanceStore>::default(),
+            BalanceBackend::Sled => Arc::new(SledBalanceStore::new()?),
+        };
+
+        Ok(store)
+    }
+}
+
+impl FromStr for BalanceBackend {
+    type Err = BalanceStoreError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string.trim().to_lowercase().as_str() {
+            "memory" => Ok(BalanceBackend::Memory),
+            "sled" => Ok(BalanceBackend::Sled),
+            _ => Err(BalanceStoreError::UnknownBackend(string.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::address::test_person_util::{person1, person2};
+
+    use super::*;
+
+    fn exercise_store(store: SharedBalanceStore) {
+        assert_eq!(store.get(&person1()).unwrap(), None);
+        assert!(store.entries().unwrap().is_empty());
+
+        let balances = HashMap::from([(person1(), 10), (person2(), u64::MAX)]);
+        store.write(&balances).unwrap();
+        store.write(&HashMap::from([(person1(), 0)])).unwrap();
+
+        assert_eq!(store.get(&person1()).unwrap(), Some(0));
+        assert_eq!(store.get(&person2()).unwrap(), Some(u64::MAX));
+
+        let mut entries = store.entries().unwrap();
+        entries.sort_by_key(|(_, amount)| *amount);
+        assert_eq!(entries, vec![(person1(), 0), (person2(), u64::MAX)]);
+
+        store.clear().unwrap();
+        assert_eq!(store.get(&person2()).unwrap(), None);
+    }
+
+    #[test]
+    fn in_memory_store() {
+        exercise_store(BalanceBackend::Memory.create_store().unwrap());
+    }
+
+    #[test]
+    fn sled_store() {
+        exercise_store(BalanceBackend::Sled.create_store().unwrap());
+    }
+
+    #[test]
+    fn sled_store_reports_corrupt_entries() {
+        let store = SledBalanceStore::new().unwrap();
+        store
+            .db
+            .insert(person1().to_string(), &[1, 2, 3][..])
+            .unwrap();
+
+        let error = store.get(&person1()).unwrap_err();
+        assert_eq!(
+            error.downcast::<BalanceStoreError>().unwrap(),
+            BalanceStoreError::CorruptEntry(person1().to_string())
+        );
+
+        store.db.insert("not an address", &[0; 8][..]).unwrap();
+        assert!(store.entries().is_err());
+    }
+
+    #[test]
+    fn parse_balance_backend() {
+        assert_eq!(BalanceBackend::from_str("Sled"), Ok(BalanceBackend::Sled));
+        assert_eq!(
+            BalanceBackend::from_str("rocksdb"),
+            Err(BalanceStoreError::UnknownBackend(String::from("rocksdb")))
+        );
+    }
+}
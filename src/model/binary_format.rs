@@ -0,0 +1,139 @@
+use anyhow::Result;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    address::Address,
+    block::{Block, BlockHash},
+    transaction::Transaction,
+};
+
+type Bytes32 = [u8; 32];
+
+// The JSON representation writes hashes and addresses as hex strings and accepts numbers as
+// strings, which bincode can't decode, so blocks are converted to fixed size fields first
+
+#[derive(Serialize, Deserialize)]
+struct BinaryTransaction {
+    sender: Bytes32,
+    recipient: Bytes32,
+    amount: u64,
+}
+
+#[derive(Serialize, Deserialize)]
+struct BinaryBlock {
+    version: u32,
+    index: u64,
+    timestamp: i64,
+    difficulty: u32,
+    nonce: u64,
+    previous_hash: Bytes32,
+    hash: Bytes32,
+    transactions: Vec<BinaryTransaction>,
+}
+
+fn hash_to_bytes(hash: &BlockHash) -> Bytes32 {
+    let mut bytes = Bytes32::default();
+    hash.to_big_endian(&mut bytes);
+
+    bytes
+}
+
+impl From<&Transaction> for BinaryTransaction {
+    fn from(transaction: &Transaction) -> Self {
+        BinaryTransaction {
+            sender: transaction.sender.to_bytes(),
+            recipient: transaction.recipient.to_bytes(),
+            amount: transaction.amount,
+        }
+    }
+}
+
+impl From<BinaryTransaction> for Transaction {
+    fn from(transaction: BinaryTransaction) -> Self {
+        Transaction {
+            sender: Address::from(transaction.sender),
+            recipient: Address::from(transaction.recipient),
+            amount: transaction.amount,
+        }
+    }
+}
+
+impl From<&Block> for BinaryBlock {
+    fn from(block: &Block) -> Self {
+        BinaryBlock {
+            version: block.version,
+            index: block.index,
+            timestamp: block.timestamp,
+            difficulty: block.difficulty,
+            nonce: block.nonce,
+            previous_hash: hash_to_bytes(&block.previous_hash),
+            hash: hash_to_bytes(&block.hash),
+            transactions: block.transactions.iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl From<BinaryBlock> for Block {
+    fn from(block: BinaryBlock) -> Self {
+        Block {
+            version: block.version,
+            index: block.index,
+            timestamp: block.timestamp,
+            difficulty: block.difficulty,
+            nonce: block.nonce,
+            previous_hash: BlockHash::from_big_endian(&block.previous_hash),
+            hash: BlockHash::from_big_endian(&block.hash),
+            transactions: block.transactions.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+pub fn encode_blocks(blocks: &[Block]) -> Vec<u8> {
+    let binary_blocks: Vec<BinaryBlock> = blocks.iter().map(Into::into).collect();
+
+    bincode::serialize(&binary_blocks).unwrap()
+}
+
+pub fn decode_blocks(bytes: &[u8]) -> Result<Vec<Block>> {
+    let binary_blocks: Vec<BinaryBlock> = bincode::deserialize(bytes)?;
+
+    Ok(binary_blocks.into_iter().map(Into::into).collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::test_person_util::{person1, person2};
+
+    use super::*;
+
+    fn create_blocks() -> Vec<Block> {
+        let transaction = Transaction {
+            sender: person1(),
+            recipient: person2(),
+            amount: u64::MAX,
+        };
+        let mut block = Block::new(1, 42, BlockHash::MAX, vec![transaction]);
+        block.difficulty = 7;
+        block.hash = block.calculate_hash();
+
+        vec![Block::new(0, 0, BlockHash::default(), vec![]), block]
+    }
+
+    #[test]
+    fn blocks_round_trip() {
+        let blocks = create_blocks();
+
+        let bytes = encode_blocks(&blocks);
+        let decoded_blocks = decode_blocks(&bytes).unwrap();
+
+        assert_eq!(decoded_blocks, blocks);
+        assert_eq!(decoded_blocks[1].calculate_hash(), blocks[1].hash);
+    }
+
+    #[test]
+    fn reject_truncated_bytes() {
+        let bytes = encode_blocks(&create_blocks());
+
+        assert!(decode_blocks(&bytes[..bytes.len() - 1]).is_err());
+    }
+}
@@ -3,34 +3,117 @@ use crypto::{digest::Digest, sha2::Sha256};
 use ethereum_types::U256;
 use serde::{Deserialize, Serialize};
 
-use super::transaction::Transaction;
+use super::{serde_number, transaction::Transaction};
 
 pub type BlockHash = U256;
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+// Version of the hashing and validation rules, to be increased on consensus changes.
+// Version 2 added the difficulty to the hashed fields
+pub const BLOCK_VERSION: u32 = 2;
+
+// Oldest version still accepted, so chains started before the difficulty field stay valid
+pub const FIRST_BLOCK_VERSION: u32 = 1;
+
+// Name of the function block hashes are calculated with, which peers must share
+pub const HASH_ALGORITHM: &str = "sha256";
+
+fn default_version() -> u32 {
+    FIRST_BLOCK_VERSION
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Block {
+    // blocks sent before versioning was introduced follow the first version rules
+    #[serde(
+        default = "default_version",
+        deserialize_with = "serde_number::deserialize"
+    )]
+    pub version: u32,
+    #[serde(deserialize_with = "serde_number::deserialize")]
     pub index: u64,
+    #[serde(deserialize_with = "serde_number::deserialize")]
     pub timestamp: i64,
+    // number of leading zero bits the hash was mined to have
+    #[serde(default, deserialize_with = "serde_number::deserialize")]
+    pub difficulty: u32,
+    #[serde(deserialize_with = "serde_number::deserialize")]
     pub nonce: u64,
     pub previous_hash: BlockHash,
     pub hash: BlockHash,
     pub transactions: Vec<Transaction>,
 }
 
+// Fields of the first version, which had no difficulty, in the order they were hashed
+#[derive(Serialize)]
+struct FirstVersionBlock<'a> {
+    version: u32,
+    index: u64,
+    timestamp: i64,
+    nonce: u64,
+    previous_hash: &'a BlockHash,
+    hash: &'a BlockHash,
+    transactions: &'a [Transaction],
+}
+
+impl<'a> From<&'a Block> for FirstVersionBlock<'a> {
+    fn from(block: &'a Block) -> FirstVersionBlock<'a> {
+        FirstVersionBlock {
+            version: block.version,
+            index: block.index,
+            timestamp: block.timestamp,
+            nonce: block.nonce,
+            previous_hash: &block.previous_hash,
+            hash: &block.hash,
+            transactions: &block.transactions,
+        }
+    }
+}
+
+pub(super) fn sha256(bytes: &[u8]) -> U256 {
+    let mut byte_hash = <[u8; 32]>::default();
+    let mut hasher = Sha256::new();
+
+    hasher.input(bytes);
+    hasher.result(&mut byte_hash);
+
+    U256::from(byte_hash)
+}
+
 impl Block {
-    pub fn calculate_hash(&self) -> BlockHash {
+    // Serialization used for hashing, fields are always written in declaration order. Blocks of
+    // the first version leave the difficulty out, as it was not part of their hash
+    pub fn to_canonical_bytes(&self) -> Vec<u8> {
+        match self.version {
+            FIRST_BLOCK_VERSION => serde_json::to_vec(&FirstVersionBlock::from(self)).unwrap(),
+            _ => serde_json::to_vec(self).unwrap(),
+        }
+    }
+
+    pub fn from_canonical_bytes(bytes: &[u8]) -> Result<Block, serde_json::Error> {
+        serde_json::from_slice(bytes)
+    }
+
+    // Bytes the proof of work hash is calculated from, which leave the hash itself out
+    pub fn to_hashable_bytes(&self) -> Vec<u8> {
         let mut hashable_data = self.clone();
         hashable_data.hash = BlockHash::default();
 
-        let serialized = serde_json::to_string(&hashable_data).unwrap();
+        hashable_data.to_canonical_bytes()
+    }
 
-        let mut byte_hash = <[u8; 32]>::default();
-        let mut hasher = Sha256::new();
+    // Proof of work hash, which depends on the nonce found by the miner
+    pub fn calculate_hash(&self) -> BlockHash {
+        sha256(&self.to_hashable_bytes())
+    }
 
-        hasher.input_str(&serialized);
-        hasher.result(&mut byte_hash);
+    // Identifies the content of the block regardless of the nonce that satisfied the difficulty,
+    // so the same block mined with different nonces shares the same id but not the same hash
+    pub fn id(&self) -> BlockHash {
+        let mut identifiable_data = self.clone();
+        identifiable_data.hash = BlockHash::default();
+        identifiable_data.nonce = 0;
 
-        U256::from(byte_hash)
+        sha256(&identifiable_data.to_canonical_bytes())
     }
 
     pub fn new(
@@ -40,8 +123,10 @@ impl Block {
         transactions: Vec<Transaction>,
     ) -> Block {
         let mut block = Block {
+            version: BLOCK_VERSION,
             index,
             timestamp: Utc::now().timestamp_millis(),
+            difficulty: 0,
             nonce,
             previous_hash,
             hash: BlockHash::default(),
@@ -53,3 +138,90 @@ impl Block {
         block
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn canonical_bytes_round_trip() {
+        let block = Block::new(1, 42, BlockHash::from(7), vec![]);
+
+        let bytes = block.to_canonical_bytes();
+        let parsed_block = Block::from_canonical_bytes(&bytes).unwrap();
+
+        assert_eq!(parsed_block, block);
+        assert_eq!(parsed_block.calculate_hash(), block.hash);
+    }
+
+    #[test]
+    fn hash_is_calculated_from_hashable_bytes() {
+        let block = Block::new(1, 42, BlockHash::from(7), vec![]);
+
+        let bytes = block.to_hashable_bytes();
+        assert_eq!(sha256(&bytes), block.hash);
+
+        let parsed_block = Block::from_canonical_bytes(&bytes).unwrap();
+        assert_eq!(parsed_block.hash, BlockHash::default());
+    }
+
+    #[test]
+    fn id_does_not_depend_on_nonce() {
+        let block = Block::new(1, 0, BlockHash::default(), vec![]);
+
+        let mut other_block = block.clone();
+        other_block.nonce = 1;
+        other_block.hash = other_block.calculate_hash();
+
+        assert_ne!(block.hash, other_block.hash);
+        assert_eq!(block.id(), other_block.id());
+
+        let mut different_block = block.clone();
+        different_block.index = 2;
+
+        assert_ne!(block.id(), different_block.id());
+    }
+
+    #[test]
+    fn version_is_part_of_the_hash() {
+        let block = Block::new(1, 0, BlockHash::default(), vec![]);
+        assert_eq!(block.version, BLOCK_VERSION);
+
+        let mut other_block = block.clone();
+        other_block.version = BLOCK_VERSION + 1;
+
+        assert_ne!(other_block.calculate_hash(), block.hash);
+    }
+
+    #[test]
+    fn difficulty_is_part_of_the_hash() {
+        let block = Block::new(1, 0, BlockHash::default(), vec![]);
+        assert_eq!(block.difficulty, 0);
+
+        let mut other_block = block.clone();
+        other_block.difficulty = 1;
+
+        assert_ne!(other_block.calculate_hash(), block.hash);
+    }
+
+    #[test]
+    fn missing_version_defaults_to_first_version() {
+        let json = r#"{"index": 1, "timestamp": 0, "nonce": 0, "previous_hash": "0x0",
+            "hash": "0x0", "transactions": []}"#;
+        let block: Block = serde_json::from_str(json).unwrap();
+
+        assert_eq!(block.version, FIRST_BLOCK_VERSION);
+    }
+
+    #[test]
+    fn first_version_hash_leaves_the_difficulty_out() {
+        let json = r#"{"version":1,"index":1,"timestamp":0,"nonce":0,"previous_hash":"0x0","hash":"0x0","transactions":[]}"#;
+        let mut block: Block = serde_json::from_str(json).unwrap();
+
+        assert_eq!(block.to_hashable_bytes(), json.as_bytes());
+
+        let hash = block.calculate_hash();
+        block.version = BLOCK_VERSION;
+        assert_ne!(block.calculate_hash(), hash);
+    }
+}
@@ -0,0 +1,562 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    env,
+    fmt::{Debug, Display},
+    fs::{self, File, OpenOptions},
+    io::{Read, Seek, SeekFrom, Write},
+    path::PathBuf,
+    process,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+};
+
+use anyhow::Result;
+use rusqlite::{params, Connection, OptionalExtension};
+use thiserror::Error;
+
+use crate::util::sync::lock_recover;
+
+use super::{
+    block::{Block, BlockHash},
+    blockchain::BlockVec,
+};
+
+pub type BoxedBlockStore = Box<dyn BlockStore + Send + Sync>;
+
+pub const DEFAULT_HOT_BLOCKS: usize = 1000;
+
+// Tells apart the spill files of the hybrid stores of a process
+static SPILL_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+#[derive(Error, PartialEq, Debug)]
+pub enum BlockStoreError {
+    #[error("Unknown storage backend `{0}`")]
+    UnknownBackend(String),
+}
+
+// Storage of the blocks of the main chain, indexed by their position in the chain
+pub trait BlockStore: Debug {
+    fn get(&self, index: u64) -> Option<Block>;
+    fn last(&self) -> Option<Block>;
+    fn len(&self) -> usize;
+    fn append(&mut self, block: Block) -> Result<()>;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Up to `limit` blocks starting at index `from`
+    fn get_range(&self, from: usize, limit: usize) -> BlockVec {
+        let to = from.saturating_add(limit).min(self.len());
+
+        (from..to)
+            .filter_map(|index| self.get(index as u64))
+            .collect()
+    }
+
+    fn find_by_hash(&self, hash: &BlockHash) -> Option<Block> {
+        (0..self.len() as u64)
+            .filter_map(|index| self.get(index))
+            .find(|block| &block.hash == hash)
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    blocks: BlockVec,
+}
+
+impl BlockStore for InMemoryStore {
+    fn get(&self, index: u64) -> Option<Block> {
+        self.blocks.get(index as usize).cloned()
+    }
+
+    fn last(&self) -> Option<Block> {
+        self.blocks.last().cloned()
+    }
+
+    fn len(&self) -> usize {
+        self.blocks.len()
+    }
+
+    fn append(&mut self, block: Block) -> Result<()> {
+        self.blocks.push(block);
+
+        Ok(())
+    }
+
+    fn get_range(&self, from: usize, limit: usize) -> BlockVec {
+        self.blocks.iter().skip(from).take(limit).cloned().collect()
+    }
+
+    fn find_by_hash(&self, hash: &BlockHash) -> Option<Block> {
+        self.blocks
+            .iter()
+            .find(|block| &block.hash == hash)
+            .cloned()
+    }
+}
+
+// Blocks are kept serialized in an in-memory SQLite database, indexed by position and hash
+#[derive(Debug)]
+pub struct SqliteStore {
+    connection: Mutex<Connection>,
+}
+
+impl SqliteStore {
+    pub fn new() -> Result<SqliteStore> {
+        let connection = Connection::open_in_memory()?;
+
+        connection.execute_batch(
+            "CREATE TABLE blocks (
+                block_index INTEGER PRIMARY KEY,
+                hash TEXT NOT NULL,
+                data BLOB NOT NULL
+            );
+            CREATE INDEX blocks_hash ON blocks (hash);",
+        )?;
+
+        Ok(SqliteStore {
+            connection: Mutex::new(connection),
+        })
+    }
+
+    // Reads cannot fail in the store interface, so failures are logged and the blocks they
+    // concern are treated as missing
+    fn log_failure<T, E: Display>(result: Result<T, E>) -> Option<T> {
+        result
+            .map_err(|error| error!("Could not read the SQLite block store: {}", error))
+            .ok()
+    }
+
+    fn parse_block(data: Vec<u8>) -> Option<Block> {
+        SqliteStore::log_failure(Block::from_canonical_bytes(&data))
+    }
+
+    fn query_block(&self, sql: &str, params: impl rusqlite::Params) -> Option<Block> {
+        let connection = lock_recover(&self.connection);
+
+        let data = connection
+            .query_row(sql, params, |row| row.get(0))
+            .optional();
+
+        SqliteStore::log_failure(data)?.and_then(SqliteStore::parse_block)
+    }
+
+    fn query_blocks(
+        &self,
+        sql: &str,
+        params: impl rusqlite::Params,
+    ) -> rusqlite::Result<Vec<Vec<u8>>> {
+        let connection = lock_recover(&self.connection);
+        let mut statement = connection.prepare(sql)?;
+        let rows = statement.query_map(params, |row| row.get(0))?;
+
+        rows.collect()
+    }
+}
+
+impl BlockStore for SqliteStore {
+    fn get(&self, index: u64) -> Option<Block> {
+        let sql = "SELECT data FROM blocks WHERE block_index = ?1";
+
+        self.query_block(sql, params![index as i64])
+    }
+
+    fn last(&self) -> Option<Block> {
+        let sql = "SELECT data FROM blocks ORDER BY block_index DESC LIMIT 1";
+
+        self.query_block(sql, [])
+    }
+
+    fn len(&self) -> usize {
+        let connection = lock_recover(&self.connection);
+        let sql = "SELECT COUNT(*) FROM blocks";
+
+        let result = connection.query_row(sql, [], |row| row.get::<_, i64>(0));
+
+        SqliteStore::log_failure(result).unwrap_or(0) as usize
+    }
+
+    // Blocks are keyed by their position, which is their index unless they are wrapped by a
+    // CheckpointStore
+    fn append(&mut self, block: Block) -> Result<()> {
+        let connection = lock_recover(&self.connection);
+        let sql = "INSERT INTO blocks (block_index, hash, data)
+            VALUES ((SELECT COUNT(*) FROM blocks), ?1, ?2)";
+
+        connection.execute(
+            sql,
+            params![format!("{:x}", block.hash), block.to_canonical_bytes()],
+        )?;
+
+        Ok(())
+    }
+
+    fn get_range(&self, from: usize, limit: usize) -> BlockVec {
+        let sql = "SELECT data FROM blocks WHERE block_index >= ?1 ORDER BY block_index LIMIT ?2";
+        let from = from.min(i64::MAX as usize) as i64;
+        let limit = limit.min(i64::MAX as usize) as i64;
+
+        SqliteStore::log_failure(self.query_blocks(sql, params![from, limit]))
+            .unwrap_or_default()
+            .into_iter()
+            .filter_map(SqliteStore::parse_block)
+            .collect()
+    }
+
+    fn find_by_hash(&self, hash: &BlockHash) -> Option<Block> {
+        let sql = "SELECT data FROM blocks WHERE hash = ?1";
+
+        self.query_block(sql, params![format!("{:x}", hash)])
+    }
+}
+
+// Keeps the last blocks in memory and spills older ones to a file, so the memory used does not
+// grow with the chain. Only the hashes of the spilled blocks stay in memory, to find them
+#[derive(Debug)]
+pub struct HybridStore {
+    hot_blocks: usize,
+    hot: VecDeque<Block>,
+    // position and length in the spill file of each spilled block, by index
+    cold: Vec<(u64, usize)>,
+    indexes_by_hash: HashMap<BlockHash, u64>,
+    spill_file: Mutex<File>,
+    spill_path: PathBuf,
+}
+
+impl HybridStore {
+    // The spill file is temporary, like the other stores the chain is not kept across restarts
+    pub fn new(hot_blocks: usize) -> Result<HybridStore> {
+        let spill_path = env::temp_dir().join(format!(
+            "rust_blockchain_{}_{}.blocks",
+            process::id(),
+            SPILL_FILE_COUNTER.fetch_add(1, Ordering::SeqCst)
+        ));
+
+        let spill_file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(&spill_path)?;
+
+        // the open handle keeps the data, so the file cannot be left behind by a killed node.
+        // Where it cannot be removed while open, it is removed on drop
+        let _ = fs::remove_file(&spill_path);
+
+        Ok(HybridStore {
+            // the last block is always kept in memory
+            hot_blocks: hot_blocks.max(1),
+            hot: VecDeque::new(),
+            cold: Vec::new(),
+            indexes_by_hash: HashMap::new(),
+            spill_file: Mutex::new(spill_file),
+            spill_path,
+        })
+    }
+
+    fn spill(&mut self, block: &Block) -> Result<()> {
+        let data = block.to_canonical_bytes();
+        let mut spill_file = lock_recover(&self.spill_file);
+
+        let position = spill_file.seek(SeekFrom::End(0))?;
+        spill_file.write_all(&data)?;
+
+        self.cold.push((position, data.len()));
+
+        Ok(())
+    }
+
+    fn read_spilled(&self, index: usize) -> Option<Block> {
+        let (position, len) = *self.cold.get(index)?;
+        let mut data = vec![0; len];
+
+        let mut spill_file = lock_recover(&self.spill_file);
+        spill_file.seek(SeekFrom::Start(position)).ok()?;
+        spill_file.read_exact(&mut data).ok()?;
+
+        Block::from_canonical_bytes(&data).ok()
+    }
+}
+
+impl BlockStore for HybridStore {
+    fn get(&self, index: u64) -> Option<Block> {
+        let index = index as usize;
+
+        match index < self.cold.len() {
+            true => self.read_spilled(index),
+            false => self.hot.get(index - self.cold.len()).cloned(),
+        }
+    }
+
+    fn last(&self) -> Option<Block> {
+        self.hot.back().cloned()
+    }
+
+    fn len(&self) -> usize {
+        self.cold.len() + self.hot.len()
+    }
+
+    fn append(&mut self, block: Block) -> Result<()> {
+        if self.hot.len() == self.hot_blocks {
+            let oldest = self.hot.front().unwrap().clone();
+            self.spill(&oldest)?;
+            self.hot.pop_front();
+        }
+
+        self.indexes_by_hash.insert(block.hash, self.len() as u64);
+        self.hot.push_back(block);
+
+        Ok(())
+    }
+
+    fn find_by_hash(&self, hash: &BlockHash) -> Option<Block> {
+        let index = *self.indexes_by_hash.get(hash)?;
+
+        self.get(index)
+    }
+}
+
+impl Drop for HybridStore {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.spill_path);
+    }
+}
+
+// Main chain of a node bootstrapped from a snapshot, which starts at the checkpoint block of the
+// snapshot instead of holding every block since the genesis one. The wrapped store holds the
+// genesis block, then the checkpoint block and the ones after it, so the blocks in between are
+// missing. Ranges start at the checkpoint, so their blocks always follow each other
+#[derive(Debug)]
+pub struct CheckpointStore {
+    checkpoint_index: u64,
+    store: BoxedBlockStore,
+}
+
+impl CheckpointStore {
+    // The store must hold the genesis block then the checkpoint block
+    pub fn new(store: BoxedBlockStore, checkpoint_index: u64) -> CheckpointStore {
+        CheckpointStore {
+            checkpoint_index,
+            store,
+        }
+    }
+
+    // Position in the wrapped store of a block from the checkpoint on
+    fn position(&self, index: u64) -> Option<u64> {
+        index
+            .checked_sub(self.checkpoint_index)
+            .map(|offset| offset + 1)
+    }
+}
+
+impl BlockStore for CheckpointStore {
+    fn get(&self, index: u64) -> Option<Block> {
+        match index {
+            0 => self.store.get(0),
+            _ => self.store.get(self.position(index)?),
+        }
+    }
+
+    fn last(&self) -> Option<Block> {
+        self.store.last()
+    }
+
+    fn len(&self) -> usize {
+        self.checkpoint_index as usize + self.store.len() - 1
+    }
+
+    fn append(&mut self, block: Block) -> Result<()> {
+        self.store.append(block)
+    }
+
+    fn get_range(&self, from: usize, limit: usize) -> BlockVec {
+        let checkpoint_index = self.checkpoint_index as usize;
+        let to = from.saturating_add(limit);
+        let from = from.max(checkpoint_index);
+
+        self.store
+            .get_range(from - checkpoint_index + 1, to.saturating_sub(from))
+    }
+
+    fn find_by_hash(&self, hash: &BlockHash) -> Option<Block> {
+        self.store.find_by_hash(hash)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StorageBackend {
+    Memory,
+    Sqlite,
+    // number of the most recent blocks kept in memory
+    Hybrid(usize),
+}
+
+impl StorageBackend {
+    pub fn create_store(&self) -> Result<BoxedBlockStore> {
+        let store: BoxedBlockStore = match self {
+            StorageBackend::Memory => Box::<InMemoryStore>::default(),
+            StorageBackend::Sqlite => Box::new(SqliteStore::new()?),
+            StorageBackend::Hybrid(hot_blocks) => Box::new(HybridStore::new(*hot_blocks)?),
+        };
+
+        Ok(store)
+    }
+}
+
+impl FromStr for StorageBackend {
+    type Err = BlockStoreError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        match string.trim().to_lowercase().as_str() {
+            "memory" => Ok(StorageBackend::Memory),
+            "sqlite" => Ok(StorageBackend::Sqlite),
+            "hybrid" => Ok(StorageBackend::Hybrid(DEFAULT_HOT_BLOCKS)),
+            _ => Err(BlockStoreError::UnknownBackend(string.to_string())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_blocks(count: u64) -> BlockVec {
+        (0..count)
+            .map(|index| Block::new(index, 0, BlockHash::from(index), vec![]))
+            .collect()
+    }
+
+    fn exercise_store(mut store: BoxedBlockStore) {
+        assert!(store.is_empty());
+        assert!(store.last().is_none());
+
+        let blocks = create_blocks(5);
+        for block in blocks.iter() {
+            store.append(block.clone()).unwrap();
+        }
+
+        assert_eq!(store.len(), 5);
+        assert_eq!(store.get(2), Some(blocks[2].clone()));
+        assert_eq!(store.get(5), None);
+        assert_eq!(store.last(), Some(blocks[4].clone()));
+
+        assert_eq!(store.get_range(1, 2), blocks[1..3].to_vec());
+        assert_eq!(store.get_range(3, usize::MAX), blocks[3..].to_vec());
+        assert!(store.get_range(10, 2).is_empty());
+
+        assert_eq!(store.find_by_hash(&blocks[3].hash), Some(blocks[3].clone()));
+        assert_eq!(store.find_by_hash(&BlockHash::MAX), None);
+    }
+
+    #[test]
+    fn in_memory_store() {
+        exercise_store(StorageBackend::Memory.create_store().unwrap());
+    }
+
+    #[test]
+    fn sqlite_store() {
+        exercise_store(StorageBackend::Sqlite.create_store().unwrap());
+    }
+
+    #[test]
+    fn sqlite_store_skips_undecodable_blocks() {
+        let mut store = SqliteStore::new().unwrap();
+        let blocks = create_blocks(3);
+        for block in blocks.iter() {
+            store.append(block.clone()).unwrap();
+        }
+
+        lock_recover(&store.connection)
+            .execute("UPDATE blocks SET data = x'00' WHERE block_index = 1", [])
+            .unwrap();
+
+        assert_eq!(store.get(1), None);
+        assert_eq!(store.get(2), Some(blocks[2].clone()));
+        assert_eq!(
+            store.get_range(0, 3),
+            vec![blocks[0].clone(), blocks[2].clone()]
+        );
+    }
+
+    #[test]
+    fn hybrid_store() {
+        exercise_store(StorageBackend::Hybrid(2).create_store().unwrap());
+    }
+
+    #[test]
+    fn hybrid_store_reads_spilled_blocks() {
+        let mut store = HybridStore::new(3).unwrap();
+        assert!(!store.spill_path.exists());
+
+        let blocks = create_blocks(10);
+        for block in blocks.iter() {
+            store.append(block.clone()).unwrap();
+        }
+
+        assert_eq!(store.hot.len(), 3);
+        assert_eq!(store.cold.len(), 7);
+        assert_eq!(store.len(), 10);
+
+        assert_eq!(store.get(0), Some(blocks[0].clone()));
+        assert_eq!(store.get(6), Some(blocks[6].clone()));
+        assert_eq!(store.get(7), Some(blocks[7].clone()));
+        assert_eq!(store.find_by_hash(&blocks[1].hash), Some(blocks[1].clone()));
+        assert_eq!(store.get_range(5, 4), blocks[5..9].to_vec());
+    }
+
+    #[test]
+    fn checkpoint_store_starts_at_the_checkpoint() {
+        for backend in [
+            StorageBackend::Memory,
+            StorageBackend::Sqlite,
+            StorageBackend::Hybrid(2),
+        ] {
+            let blocks = create_blocks(8);
+            let mut store = backend.create_store().unwrap();
+            store.append(blocks[0].clone()).unwrap();
+            store.append(blocks[5].clone()).unwrap();
+
+            let mut store = CheckpointStore::new(store, 5);
+            for block in blocks[6..].iter() {
+                store.append(block.clone()).unwrap();
+            }
+
+            assert_eq!(store.len(), 8);
+            assert_eq!(store.get(0), Some(blocks[0].clone()));
+            assert_eq!(store.get(3), None);
+            assert_eq!(store.get(6), Some(blocks[6].clone()));
+            assert_eq!(store.get(8), None);
+            assert_eq!(store.last(), Some(blocks[7].clone()));
+
+            assert_eq!(store.get_range(0, usize::MAX), blocks[5..].to_vec());
+            assert_eq!(store.get_range(2, 5), blocks[5..7].to_vec());
+            assert_eq!(store.get_range(6, 1), blocks[6..7].to_vec());
+            assert!(store.get_range(1, 3).is_empty());
+
+            assert_eq!(store.find_by_hash(&blocks[6].hash), Some(blocks[6].clone()));
+            assert_eq!(store.find_by_hash(&blocks[3].hash), None);
+        }
+    }
+
+    #[test]
+    fn parse_storage_backend() {
+        assert_eq!(
+            StorageBackend::from_str("memory"),
+            Ok(StorageBackend::Memory)
+        );
+        assert_eq!(
+            StorageBackend::from_str("SQLite"),
+            Ok(StorageBackend::Sqlite)
+        );
+        assert_eq!(
+            StorageBackend::from_str("hybrid"),
+            Ok(StorageBackend::Hybrid(DEFAULT_HOT_BLOCKS))
+        );
+        assert!(StorageBackend::from_str("postgres").is_err());
+    }
+}
@@ -1,27 +1,74 @@
 use std::{
+    collections::HashMap,
+    ffi::OsString,
+    fs::{self, File},
+    io::Write,
     slice::Iter,
-    sync::{Arc, Mutex},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex, RwLock,
+    },
+    time::{Duration, Instant},
 };
 
 use anyhow::Result;
+use chrono::Utc;
+use rayon::prelude::*;
 use thiserror::Error;
 
+use crate::util::sync::{lock_recover, read_recover, write_recover};
+
 use super::{
-    account_balance_map::AccountBalanceMap,
-    block::{Block, BlockHash},
-    transaction::Transaction,
+    account_balance_map::{AccountBalanceMap, Amount},
+    address::Address,
+    audit_log::AuditLog,
+    balance_store::SharedBalanceStore,
+    binary_format::{decode_blocks, encode_blocks},
+    block::{Block, BlockHash, BLOCK_VERSION, FIRST_BLOCK_VERSION},
+    block_store::{BlockStore, BoxedBlockStore, CheckpointStore, InMemoryStore},
+    genesis::GenesisConfig,
+    reward_split::RewardSplit,
+    snapshot::ChainSnapshot,
+    spending_policy::SpendingPolicy,
+    transaction::{Transaction, TransactionHash},
+    transaction_pool::TransactionVec,
 };
 
 pub type BlockVec = Vec<Block>;
 
-type SyncedBlockVec = Arc<Mutex<BlockVec>>;
-type SyncedAccountBalanceVec = Arc<Mutex<AccountBalanceMap>>;
+type SyncedBlockStore = Arc<RwLock<BoxedBlockStore>>;
+type SyncedBlockMap = Arc<RwLock<HashMap<BlockHash, Block>>>;
+type SyncedOrphanBlockMap = Arc<Mutex<HashMap<BlockHash, OrphanBlock>>>;
+// Identical transfers share a hash, so each hash maps to every block confirming it, in order
+type SyncedTransactionIndex = Arc<RwLock<HashMap<TransactionHash, Vec<u64>>>>;
+type SyncedAccountBalanceVec = Arc<RwLock<AccountBalanceMap>>;
+type SyncedSubscriberVec = Arc<Mutex<Vec<Sender<Block>>>>;
+type SyncedInstant = Arc<Mutex<Instant>>;
+type SyncedCheckpoint = Arc<RwLock<Option<ChainSnapshot>>>;
 
 pub const BLOCK_SUBSIDY: u64 = 100;
 
+// Number of blocks after which the block subsidy is halved
+pub const DEFAULT_HALVING_INTERVAL: u64 = 210_000;
+
+// Number of blocks after which a coinbase reward can be spent, 0 makes it spendable right away
+pub const DEFAULT_COINBASE_MATURITY: u64 = 100;
+
+// Maximum time a block timestamp can be ahead of the local clock (2 hours)
+const MAX_FUTURE_BLOCK_TIME_MS: i64 = 2 * 60 * 60 * 1000;
+
+// Maximum number of side branch blocks kept, the lowest ones are evicted first
+const MAX_SIDE_BLOCKS: usize = 1000;
+
+// Maximum number of blocks waiting for their parent, the oldest ones are evicted first
+const MAX_ORPHAN_BLOCKS: usize = 100;
+
 #[derive(Error, PartialEq, Debug)]
 #[allow(clippy::enum_variant_names)]
 pub enum BlockchainError {
+    #[error("Unsupported block version `{0}`")]
+    UnsupportedBlockVersion(u32),
+
     #[error("Invalid index")]
     InvalidIndex,
 
@@ -31,79 +78,615 @@ pub enum BlockchainError {
     #[error("Invalid hash")]
     InvalidHash,
 
+    #[error("Timestamp is not later than the previous block")]
+    TimestampTooOld,
+
+    #[error("Timestamp is too far in the future")]
+    TimestampInFuture,
+
     #[error("Invalid difficulty")]
     InvalidDifficulty,
 
+    #[error("Difficulty does not match the expected one")]
+    UnexpectedDifficulty,
+
+    #[error("Invalid genesis block")]
+    InvalidGenesis,
+
+    #[error("Snapshot tip block does not match the snapshot")]
+    InvalidSnapshot,
+
+    #[error("The chain lacks the blocks before its snapshot checkpoint")]
+    PrunedChain,
+
+    #[error("Block is already known")]
+    DuplicateBlock,
+
     #[error("Coinbase transaction not found")]
     CoinbaseTransactionNotFound,
 
+    #[error("Only the first transaction can be a coinbase")]
+    MultipleCoinbase,
+
     #[error("Invalid coinbase amount")]
     InvalidCoinbaseAmount,
+
+    #[error("Coinbase outputs do not match the reward split")]
+    InvalidRewardSplit,
+
+    #[error("Coinbase recipient is the zero address")]
+    InvalidCoinbaseRecipient,
+
+    #[error("Recipient account does not exist")]
+    UnknownRecipient,
+
+    #[error("Spending limit exceeded")]
+    SpendingLimitExceeded,
+
+    #[error("Transaction amount must be greater than zero")]
+    ZeroAmount,
+
+    #[error("Sender and recipient must be different")]
+    SelfTransfer,
+
+    #[error("Coinbase reward is not mature yet")]
+    ImmatureCoinbase,
+}
+
+// First block of the main chain that does not pass validation
+#[derive(Error, PartialEq, Debug)]
+#[error("Invalid block at index `{index}`: {reason}")]
+pub struct ChainValidationError {
+    pub index: u64,
+    pub reason: String,
+}
+
+// Where a received block ended up
+#[derive(Debug, PartialEq)]
+pub enum Placement {
+    MainChain,
+    SideBranch,
+    // the parent is not known yet, the block is added once it arrives
+    Orphan,
+}
+
+#[derive(Debug, Clone)]
+struct OrphanBlock {
+    block: Block,
+    received_at: Instant,
 }
 
 #[derive(Debug, Clone)]
 pub struct Blockchain {
     pub difficulty: u32,
-    blocks: SyncedBlockVec,
+    // blocks over which the difficulty rises to its configured value on a new network
+    difficulty_ramp_blocks: u64,
+    halving_interval: u64,
+    coinbase_maturity: u64,
+    require_coinbase_recipient: bool,
+    // transfers can only be sent to accounts that already exist, for permissioned chains
+    require_existing_recipient: bool,
+    // recipients the coinbase outputs must pay, in order, when the reward is split
+    reward_split: RewardSplit,
+    // allocations of the configured genesis block, the only transactions it can have
+    genesis_transactions: TransactionVec,
+    blocks: SyncedBlockStore,
+    // snapshot the chain was bootstrapped from, whose balances replays start from
+    checkpoint: SyncedCheckpoint,
+    side_blocks: SyncedBlockMap,
+    orphan_blocks: SyncedOrphanBlockMap,
+    transaction_index: SyncedTransactionIndex,
     account_balances: SyncedAccountBalanceVec,
+    spending_policy: SpendingPolicy,
+    parallel_validation: bool,
+    audit_log: AuditLog,
+    subscribers: SyncedSubscriberVec,
+    last_block_added_at: SyncedInstant,
+}
+
+fn with_suffix(path: &str, suffix: &str) -> OsString {
+    OsString::from(format!("{}{}", path, suffix))
+}
+
+fn index_transactions(transaction_index: &mut HashMap<TransactionHash, Vec<u64>>, block: &Block) {
+    for transaction in block.transactions.iter() {
+        transaction_index
+            .entry(transaction.hash())
+            .or_default()
+            .push(block.index);
+    }
 }
 
 impl Blockchain {
-    fn create_genesis_block() -> Block {
-        let index = 0;
-        let nonce = 0;
-        let previous_hash = BlockHash::default();
-        let transactions = Vec::new();
+    pub fn new(difficulty: u32) -> Blockchain {
+        Blockchain::from_genesis(difficulty, &GenesisConfig::default())
+    }
 
-        let mut block = Block::new(index, nonce, previous_hash, transactions);
+    // Starts the chain from a genesis block built from the config, with its initial balances
+    pub fn from_genesis(difficulty: u32, genesis: &GenesisConfig) -> Blockchain {
+        let genesis_block = genesis.create_block();
 
-        block.timestamp = 0;
-        block.hash = block.calculate_hash();
+        let mut transaction_index = HashMap::new();
+        index_transactions(&mut transaction_index, &genesis_block);
 
-        block
-    }
+        let genesis_transactions = genesis_block.transactions.clone();
 
-    pub fn new(difficulty: u32) -> Blockchain {
-        let genesis_block = Blockchain::create_genesis_block();
+        let mut blocks = InMemoryStore::default();
+        blocks.append(genesis_block).unwrap();
 
-        let blocks = vec![genesis_block];
-        let synced_blocks = Arc::new(Mutex::new(blocks));
-        let synced_account_balances = SyncedAccountBalanceVec::default();
+        let synced_blocks: SyncedBlockStore = Arc::new(RwLock::new(Box::new(blocks)));
+        let synced_account_balances = Arc::new(RwLock::new(genesis.create_account_balances()));
 
         Blockchain {
             difficulty,
+            difficulty_ramp_blocks: 0,
+            halving_interval: DEFAULT_HALVING_INTERVAL,
+            coinbase_maturity: DEFAULT_COINBASE_MATURITY,
+            require_coinbase_recipient: false,
+            require_existing_recipient: false,
+            reward_split: RewardSplit::default(),
+            genesis_transactions,
             blocks: synced_blocks,
+            checkpoint: SyncedCheckpoint::default(),
+            side_blocks: SyncedBlockMap::default(),
+            orphan_blocks: SyncedOrphanBlockMap::default(),
+            transaction_index: Arc::new(RwLock::new(transaction_index)),
             account_balances: synced_account_balances,
+            spending_policy: SpendingPolicy::default(),
+            parallel_validation: true,
+            audit_log: AuditLog::default(),
+            subscribers: SyncedSubscriberVec::default(),
+            last_block_added_at: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+
+    // A ramp of 0 blocks mines every block at the configured difficulty
+    pub fn with_difficulty_ramp(mut self, difficulty_ramp_blocks: u64) -> Blockchain {
+        self.difficulty_ramp_blocks = difficulty_ramp_blocks;
+
+        self
+    }
+
+    pub fn with_halving_interval(mut self, halving_interval: u64) -> Blockchain {
+        self.halving_interval = halving_interval;
+
+        self
+    }
+
+    pub fn with_coinbase_maturity(mut self, coinbase_maturity: u64) -> Blockchain {
+        self.coinbase_maturity = coinbase_maturity;
+
+        self
+    }
+
+    // Rejects coinbases paying the zero address, which would burn the subsidy
+    pub fn with_require_coinbase_recipient(
+        mut self,
+        require_coinbase_recipient: bool,
+    ) -> Blockchain {
+        self.require_coinbase_recipient = require_coinbase_recipient;
+
+        self
+    }
+
+    pub fn with_require_existing_recipient(
+        mut self,
+        require_existing_recipient: bool,
+    ) -> Blockchain {
+        self.require_existing_recipient = require_existing_recipient;
+
+        self
+    }
+
+    pub fn with_reward_split(mut self, reward_split: RewardSplit) -> Blockchain {
+        self.reward_split = reward_split;
+
+        self
+    }
+
+    pub fn with_spending_policy(mut self, spending_policy: SpendingPolicy) -> Blockchain {
+        self.spending_policy = spending_policy;
+
+        self
+    }
+
+    pub fn with_parallel_validation(mut self, parallel_validation: bool) -> Blockchain {
+        self.parallel_validation = parallel_validation;
+
+        self
+    }
+
+    pub fn with_audit_log(mut self, audit_log: AuditLog) -> Blockchain {
+        self.audit_log = audit_log;
+
+        self
+    }
+
+    // Replaces the storage of the chain, the genesis block is added if the store is empty
+    pub fn with_store(mut self, mut store: BoxedBlockStore) -> Result<Blockchain> {
+        match store.get(0) {
+            Some(genesis_block) => self.validate_genesis(&genesis_block)?,
+            None => {
+                let genesis_block = self.get_block_by_index(0).unwrap();
+                store.append(genesis_block)?;
+            }
+        }
+
+        self.blocks = Arc::new(RwLock::new(store));
+
+        Ok(self)
+    }
+
+    // Keeps the balances in the given store, which starts with the current ones
+    pub fn with_balance_store(self, store: SharedBalanceStore) -> Result<Blockchain> {
+        let mut account_balances = write_recover(&self.account_balances);
+        *account_balances = account_balances.clone().move_to(store)?;
+        drop(account_balances);
+
+        Ok(self)
+    }
+
+    // Takes the balances of the snapshot instead of replaying the blocks up to its tip, which is
+    // only checked to match the snapshot. The chain then starts at the tip block, without the
+    // blocks before it, and only the trailing blocks, which follow the tip, are validated. The
+    // chain must only have its genesis block, like when bootstrapping a new node
+    pub fn import_snapshot(
+        &self,
+        snapshot: ChainSnapshot,
+        trailing_blocks: &[Block],
+    ) -> Result<()> {
+        let tip_block = &snapshot.tip_block;
+        let is_valid_tip = tip_block.index == snapshot.tip_index
+            && tip_block.hash == snapshot.tip_hash
+            && tip_block.hash == tip_block.calculate_hash();
+        if !is_valid_tip {
+            return Err(BlockchainError::InvalidSnapshot.into());
+        }
+
+        let mut store = write_recover(&self.blocks);
+        let genesis_hash = store.get(0).unwrap().hash;
+        if snapshot.genesis_hash != genesis_hash || store.len() != 1 {
+            return Err(BlockchainError::InvalidGenesis.into());
+        }
+
+        if snapshot.tip_index == 0 {
+            if tip_block.hash != genesis_hash {
+                return Err(BlockchainError::InvalidSnapshot.into());
+            }
+        } else {
+            store.append(tip_block.clone())?;
+            let placeholder: BoxedBlockStore = Box::<InMemoryStore>::default();
+            let genesis_store = std::mem::replace(&mut *store, placeholder);
+            *store = Box::new(CheckpointStore::new(genesis_store, snapshot.tip_index));
+        }
+
+        // same lock order as append_block
+        let mut account_balances = write_recover(&self.account_balances);
+        *account_balances = snapshot
+            .account_balances
+            .clone()
+            .move_to(account_balances.get_store())?;
+        // a snapshot at the genesis block leaves a full chain
+        if snapshot.tip_index > 0 {
+            index_transactions(&mut write_recover(&self.transaction_index), tip_block);
+            *write_recover(&self.checkpoint) = Some(snapshot);
+        }
+        drop(account_balances);
+        drop(store);
+
+        Blockchain::verify_hashes(trailing_blocks, self.parallel_validation)?;
+
+        for block in trailing_blocks {
+            self.append_block(block.clone(), false)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn snapshot(&self) -> Result<ChainSnapshot> {
+        // the blocks lock is held so the balances belong to the tip
+        let blocks = read_recover(&self.blocks);
+        let tip = blocks.last().unwrap();
+
+        Ok(ChainSnapshot {
+            genesis_hash: blocks.get(0).unwrap().hash,
+            tip_index: tip.index,
+            tip_hash: tip.hash,
+            account_balances: read_recover(&self.account_balances).to_in_memory()?,
+            tip_block: tip,
+        })
+    }
+
+    // Compact encoding of the main chain, for transfers and files where JSON is too verbose
+    pub fn export_binary(&self) -> Vec<u8> {
+        encode_blocks(&self.get_all_blocks())
+    }
+
+    // Rebuilds the chain from an export, validating its blocks like an import from a peer. The
+    // chain must only have its genesis block
+    pub fn import_binary(&self, bytes: &[u8]) -> Result<()> {
+        self.import_blocks(&decode_blocks(bytes)?)
+    }
+
+    // The binary export is written to a temporary file that is then renamed over the target, so
+    // a crash mid-write leaves the last saved chain intact. That one is kept as a `.bak` file
+    pub fn save_to_file(&self, path: &str) -> Result<()> {
+        // the saved blocks could not be imported back without the ones before the checkpoint
+        if read_recover(&self.checkpoint).is_some() {
+            return Err(BlockchainError::PrunedChain.into());
+        }
+
+        let temp_path = with_suffix(path, ".tmp");
+
+        let mut file = File::create(&temp_path)?;
+        file.write_all(&self.export_binary())?;
+        file.sync_all()?;
+
+        if fs::metadata(path).is_ok() {
+            fs::rename(path, with_suffix(path, ".bak"))?;
         }
+        fs::rename(&temp_path, path)?;
+
+        Ok(())
+    }
+
+    // Imports the chain saved by save_to_file, returning false when nothing was saved yet. Falls
+    // back to the backup of the previous save when the file is missing or corrupt, which is
+    // found before importing any block
+    pub fn load_from_file(&self, path: &str) -> Result<bool> {
+        let backup_path = with_suffix(path, ".bak");
+        if fs::metadata(path).is_err() && fs::metadata(&backup_path).is_err() {
+            return Ok(false);
+        }
+
+        let read = |path: &OsString| -> Result<BlockVec> { decode_blocks(&fs::read(path)?) };
+
+        let blocks = match read(&path.into()) {
+            Ok(blocks) => blocks,
+            Err(error) => {
+                warn!(
+                    "Could not load the chain from {}: {}, trying its backup",
+                    path, error
+                );
+                read(&backup_path).map_err(|_| error)?
+            }
+        };
+        self.import_blocks(&blocks)?;
+
+        Ok(true)
     }
 
     pub fn get_last_block(&self) -> Block {
-        let blocks = self.blocks.lock().unwrap();
+        let blocks = read_recover(&self.blocks);
+
+        // the genesis block is always present
+        blocks.last().unwrap()
+    }
+
+    pub fn get_last_block_index(&self) -> u64 {
+        self.get_last_block().index
+    }
+
+    pub fn len(&self) -> usize {
+        read_recover(&self.blocks).len()
+    }
+
+    // The genesis block is always present
+    pub fn is_empty(&self) -> bool {
+        false
+    }
+
+    // Runs the closure with read access to the block store, so it sees a consistent chain
+    pub fn with_blocks<R>(&self, f: impl FnOnce(&dyn BlockStore) -> R) -> R {
+        let blocks = read_recover(&self.blocks);
+
+        f(blocks.as_ref())
+    }
+
+    // Balance of the address as of the last block, ignoring pending transactions
+    pub fn get_balance(&self, address: &Address) -> Result<Amount> {
+        let account_balances = read_recover(&self.account_balances);
+
+        Ok(account_balances.get_receipient_balance(address)?)
+    }
+
+    // Whether transfers to the address would be accepted by the recipient policy
+    pub fn accepts_recipient(&self, address: &Address) -> Result<bool> {
+        if !self.require_existing_recipient {
+            return Ok(true);
+        }
 
-        blocks[blocks.len() - 1].clone()
+        Ok(read_recover(&self.account_balances).has_account(address)?)
     }
 
+    // Balance that can be spent in the next block, without the immature coinbase rewards
+    pub fn get_spendable_balance(&self, address: &Address) -> Result<Amount> {
+        let next_index = self.get_last_block_index() + 1;
+        let account_balances = read_recover(&self.account_balances);
+
+        Ok(account_balances.get_spendable_balance(address, next_index)?)
+    }
+
+    // Amount the spending rule of the address still lets it send in the next block, None when the
+    // address has no rule
+    pub fn get_spending_allowance(&self, address: &Address) -> Option<Amount> {
+        let rule = self.spending_policy.get_rule(address)?;
+
+        let blocks = read_recover(&self.blocks);
+        let height = blocks.len();
+        let window = self.spending_policy.max_window();
+        let recent_blocks = blocks.get_range(height.saturating_sub(window), window);
+        let spent = SpendingPolicy::spent_in_window(address, rule, &recent_blocks);
+
+        Some(rule.max_amount.saturating_sub(spent))
+    }
+
+    // Index of the block that first confirmed the transaction, along with the transaction.
+    // Identical transfers share a hash, so later ones are only found by is_confirmed_after
+    pub fn find_transaction(&self, hash: &TransactionHash) -> Option<(u64, Transaction)> {
+        let block_index = *read_recover(&self.transaction_index).get(hash)?.first()?;
+        let block = self.get_block_by_index(block_index)?;
+
+        block
+            .transactions
+            .into_iter()
+            .find(|transaction| &transaction.hash() == hash)
+            .map(|transaction| (block_index, transaction))
+    }
+
+    pub fn is_confirmed_after(&self, hash: &TransactionHash, block_index: u64) -> bool {
+        read_recover(&self.transaction_index)
+            .get(hash)
+            .and_then(|block_indexes| block_indexes.last())
+            .is_some_and(|last_index| *last_index > block_index)
+    }
+
+    // Blocks of competing branches, which are not part of the main chain
+    pub fn get_side_blocks(&self) -> BlockVec {
+        let side_blocks = read_recover(&self.side_blocks);
+
+        side_blocks.values().cloned().collect()
+    }
+
+    pub fn get_orphan_blocks(&self) -> BlockVec {
+        let orphan_blocks = lock_recover(&self.orphan_blocks);
+
+        orphan_blocks
+            .values()
+            .map(|orphan| orphan.block.clone())
+            .collect()
+    }
+
+    // Clones the whole chain, prefer the other accessors for frequent calls. A chain bootstrapped
+    // from a snapshot starts at its checkpoint block
     pub fn get_all_blocks(&self) -> BlockVec {
-        let blocks = self.blocks.lock().unwrap();
+        let blocks = read_recover(&self.blocks);
+
+        blocks.get_range(0, usize::MAX)
+    }
+
+    pub fn get_block_by_index(&self, index: u64) -> Option<Block> {
+        let blocks = read_recover(&self.blocks);
+
+        blocks.get(index)
+    }
+
+    pub fn get_block_by_hash(&self, hash: &BlockHash) -> Option<Block> {
+        let blocks = read_recover(&self.blocks);
+
+        blocks.find_by_hash(hash)
+    }
+
+    // Up to `limit` blocks starting at index `from`, empty if `from` is past the last block
+    pub fn get_blocks_range(&self, from: usize, limit: usize) -> BlockVec {
+        let blocks = read_recover(&self.blocks);
+
+        blocks.get_range(from, limit)
+    }
+
+    // Blocks of the main chain that follow the given one, None if it is not in the main chain.
+    // Lets a node that trusts a checkpoint fetch what came after it, regardless of the indexes
+    pub fn get_blocks_after_hash(&self, hash: &BlockHash) -> Option<BlockVec> {
+        let blocks = read_recover(&self.blocks);
+        let checkpoint = blocks.find_by_hash(hash)?;
+
+        Some(blocks.get_range(checkpoint.index as usize + 1, usize::MAX))
+    }
+
+    // Time elapsed since the last block was added, or since startup if none was added yet
+    pub fn time_since_last_block(&self) -> Duration {
+        lock_recover(&self.last_block_added_at).elapsed()
+    }
+
+    pub fn is_stalled(&self, threshold: Duration) -> bool {
+        self.time_since_last_block() > threshold
+    }
+
+    // Difficulty the block at the given index must be mined at, the same for every block
+    // after the ramp until the difficulty is retargeted
+    pub fn expected_difficulty(&self, index: u64) -> u32 {
+        self.ramped_difficulty(index)
+    }
+
+    // Rises linearly from 1 at the first block to the configured difficulty at the end of the
+    // ramp. Only integers are involved, so every node computes the same difficulty
+    pub fn ramped_difficulty(&self, height: u64) -> u32 {
+        if height >= self.difficulty_ramp_blocks || self.difficulty <= 1 {
+            return self.difficulty;
+        }
+
+        let increase = (self.difficulty - 1) as u64 * height / self.difficulty_ramp_blocks;
+
+        1 + increase as u32
+    }
+
+    // The subsidy is halved every halving interval, an interval of 0 disables halving
+    pub fn block_subsidy(&self, height: u64) -> u64 {
+        if self.halving_interval == 0 {
+            return BLOCK_SUBSIDY;
+        }
+
+        let halvings = height / self.halving_interval;
+
+        if halvings >= u64::BITS as u64 {
+            return 0;
+        }
+
+        BLOCK_SUBSIDY >> halvings
+    }
+
+    // Every block accepted after subscribing is sent to the returned receiver
+    pub fn subscribe(&self) -> Receiver<Block> {
+        let (sender, receiver) = mpsc::channel();
+        lock_recover(&self.subscribers).push(sender);
 
-        blocks.clone()
+        receiver
     }
 
+    fn notify_subscribers(&self, block: &Block) {
+        let mut subscribers = lock_recover(&self.subscribers);
+
+        // receivers that were dropped are removed from the list
+        subscribers.retain(|subscriber| subscriber.send(block.clone()).is_ok());
+    }
+
+    // A split reward is paid by one coinbase output per share, otherwise by a single one
     fn process_coinbase(
+        &self,
         account_balances: &mut AccountBalanceMap,
-        coinbase: Option<&Transaction>,
+        coinbase: &[Transaction],
+        block_subsidy: u64,
+        spendable_from: u64,
     ) -> Result<()> {
-        let coinbase = match coinbase {
-            Some(transaction) => transaction,
-            None => return Err(BlockchainError::CoinbaseTransactionNotFound.into()),
-        };
+        if coinbase.is_empty() {
+            return Err(BlockchainError::CoinbaseTransactionNotFound.into());
+        }
 
-        let is_valid_amount = coinbase.amount == BLOCK_SUBSIDY;
-        if !is_valid_amount {
+        let total = coinbase
+            .iter()
+            .try_fold(0u64, |total, output| total.checked_add(output.amount));
+        if total != Some(block_subsidy) {
             return Err(BlockchainError::InvalidCoinbaseAmount.into());
         }
 
-        account_balances.add_amount(&coinbase.recipient, coinbase.amount);
+        if !self.reward_split.is_empty() {
+            let matches_split = coinbase
+                .iter()
+                .map(|output| (output.recipient.clone(), output.amount))
+                .eq(self.reward_split.split(block_subsidy));
+            if !matches_split {
+                return Err(BlockchainError::InvalidRewardSplit.into());
+            }
+        }
+
+        for output in coinbase {
+            if self.require_coinbase_recipient && output.recipient == Address::default() {
+                return Err(BlockchainError::InvalidCoinbaseRecipient.into());
+            }
+
+            account_balances.add_amount(&output.recipient, output.amount)?;
+            account_balances.lock_until(&output.recipient, output.amount, spendable_from);
+        }
 
         Ok(())
     }
@@ -111,157 +694,1829 @@ impl Blockchain {
     fn process_transfers(
         new_account_balances: &mut AccountBalanceMap,
         transaction_iter: Iter<Transaction>,
+        block_index: u64,
+        spending_policy: &SpendingPolicy,
+        require_existing_recipient: bool,
+        blocks: &[Block],
     ) -> Result<()> {
+        let mut spent_amounts = HashMap::<Address, Amount>::new();
+
         for transaction in transaction_iter {
-            new_account_balances.transfer(
-                &transaction.sender,
-                &transaction.recipient,
-                transaction.amount,
-            )?
+            Blockchain::process_transfer(
+                new_account_balances,
+                &mut spent_amounts,
+                transaction,
+                block_index,
+                spending_policy,
+                require_existing_recipient,
+                blocks,
+            )?;
+        }
+
+        Ok(())
+    }
+
+    // A refused transfer leaves the balances and the amounts spent in the window untouched
+    fn process_transfer(
+        new_account_balances: &mut AccountBalanceMap,
+        spent_amounts: &mut HashMap<Address, Amount>,
+        transaction: &Transaction,
+        block_index: u64,
+        spending_policy: &SpendingPolicy,
+        require_existing_recipient: bool,
+        blocks: &[Block],
+    ) -> Result<()> {
+        // the default address is the sender of coinbase transactions, so it cannot transfer
+        if transaction.sender == Address::default() {
+            return Err(BlockchainError::MultipleCoinbase.into());
+        }
+
+        if transaction.amount == 0 {
+            return Err(BlockchainError::ZeroAmount.into());
+        }
+
+        if transaction.sender == transaction.recipient {
+            return Err(BlockchainError::SelfTransfer.into());
+        }
+
+        // accounts are created by receiving a coinbase, a genesis allocation or a transfer
+        if require_existing_recipient
+            && !new_account_balances.has_account(&transaction.recipient)?
+        {
+            return Err(BlockchainError::UnknownRecipient.into());
+        }
+
+        let spent = match spending_policy.get_rule(&transaction.sender) {
+            Some(rule) => {
+                let spent = spent_amounts
+                    .get(&transaction.sender)
+                    .copied()
+                    .unwrap_or_else(|| {
+                        SpendingPolicy::spent_in_window(&transaction.sender, rule, blocks)
+                    })
+                    .saturating_add(transaction.amount);
+
+                if spent > rule.max_amount {
+                    return Err(BlockchainError::SpendingLimitExceeded.into());
+                }
+
+                Some(spent)
+            }
+            None => None,
+        };
+
+        // funds the sender has, but only once its coinbase rewards mature
+        let balance = new_account_balances.get_receipient_balance(&transaction.sender)?;
+        let spendable =
+            new_account_balances.get_spendable_balance(&transaction.sender, block_index)?;
+        if transaction.amount > spendable && transaction.amount <= balance {
+            return Err(BlockchainError::ImmatureCoinbase.into());
+        }
+
+        new_account_balances.transfer(
+            &transaction.sender,
+            &transaction.recipient,
+            transaction.amount,
+        )?;
+
+        if let Some(spent) = spent {
+            spent_amounts.insert(transaction.sender.clone(), spent);
         }
 
         Ok(())
     }
 
+    // Splits the transfers into the ones the next block can still include, in order, and the
+    // ones it would be refused for, along with the reason
+    pub fn partition_valid_transfers(
+        &self,
+        transactions: TransactionVec,
+    ) -> (TransactionVec, Vec<(Transaction, anyhow::Error)>) {
+        let blocks = read_recover(&self.blocks);
+        let height = blocks.len();
+        let window = self.spending_policy.max_window();
+        let recent_blocks = blocks.get_range(height.saturating_sub(window), window);
+
+        let mut account_balances = read_recover(&self.account_balances).clone();
+        account_balances.release_matured(height as u64);
+        let mut spent_amounts = HashMap::new();
+
+        let mut valid = TransactionVec::new();
+        let mut invalid = Vec::new();
+
+        for transaction in transactions {
+            let result = Blockchain::process_transfer(
+                &mut account_balances,
+                &mut spent_amounts,
+                &transaction,
+                height as u64,
+                &self.spending_policy,
+                self.require_existing_recipient,
+                &recent_blocks,
+            );
+
+            match result {
+                Ok(()) => valid.push(transaction),
+                Err(error) => invalid.push((transaction, error)),
+            }
+        }
+
+        (valid, invalid)
+    }
+
+    // The changes are recorded as part of the audited block index, if one is given
     fn calculate_new_account_balance(
+        &self,
         account_balances: &AccountBalanceMap,
-        transactions: &[Transaction],
+        block: &Block,
+        block_subsidy: u64,
+        blocks: &[Block],
+        audited_block_index: Option<u64>,
     ) -> Result<AccountBalanceMap> {
         let mut new_account_balances = account_balances.clone();
-        let mut iter = transactions.iter();
 
-        Blockchain::process_coinbase(&mut new_account_balances, iter.next())?;
-        Blockchain::process_transfers(&mut new_account_balances, iter)?;
+        // the first transaction is the coinbase, followed by the outputs of a split reward
+        let coinbase_len = match block.transactions.split_first() {
+            Some((_, rest)) => {
+                let outputs = rest
+                    .iter()
+                    .take(self.reward_split.len().saturating_sub(1))
+                    .take_while(|transaction| transaction.sender == Address::default())
+                    .count();
+                1 + outputs
+            }
+            None => 0,
+        };
+        let (coinbase, transfers) = block.transactions.split_at(coinbase_len);
+
+        if let Some(block_index) = audited_block_index {
+            new_account_balances.start_audit(block_index);
+        }
+
+        new_account_balances.release_matured(block.index);
+
+        self.process_coinbase(
+            &mut new_account_balances,
+            coinbase,
+            block_subsidy,
+            block.index + self.coinbase_maturity,
+        )?;
+        Blockchain::process_transfers(
+            &mut new_account_balances,
+            transfers.iter(),
+            block.index,
+            &self.spending_policy,
+            self.require_existing_recipient,
+            blocks,
+        )?;
 
         Ok(new_account_balances)
     }
 
-    fn udpate_account_balance(&self, transactions: &[Transaction]) -> Result<()> {
-        let mut account_balances = self.account_balances.lock().unwrap();
+    // Replays the whole main chain from the genesis block with the same checks as new blocks,
+    // to confirm the stored blocks are consistent. It takes time on long chains
+    pub fn validate_chain(&self) -> Result<(), ChainValidationError> {
+        self.replay_blocks(&self.get_all_blocks(), true)
+            .map(|_| ())
+            .map_err(|(index, error)| ChainValidationError {
+                index,
+                reason: error.to_string(),
+            })
+    }
+
+    // Balances after the given blocks, which start from the genesis block, or from the checkpoint
+    // block when the chain was bootstrapped from a snapshot, checked one after the other like new
+    // blocks. Fails with the index of the first invalid block
+    fn replay_blocks(
+        &self,
+        blocks: &[Block],
+        verify_hash: bool,
+    ) -> Result<AccountBalanceMap, (u64, anyhow::Error)> {
+        let first_block = &blocks[0];
+        let mut account_balances = if first_block.index == 0 {
+            self.validate_genesis(first_block)
+                .map_err(|error| (0, error))?;
+
+            // the genesis allocations are the only transactions that are not checked
+            let mut account_balances = AccountBalanceMap::default();
+            for transaction in first_block.transactions.iter() {
+                account_balances
+                    .add_amount(&transaction.recipient, transaction.amount)
+                    .map_err(|error| (0, error.into()))?;
+            }
+            account_balances
+        } else {
+            match read_recover(&self.checkpoint).as_ref() {
+                Some(checkpoint) if checkpoint.tip_hash == first_block.hash => {
+                    checkpoint.account_balances.clone()
+                }
+                _ => {
+                    let error = BlockchainError::InvalidSnapshot.into();
+                    return Err((first_block.index, error));
+                }
+            }
+        };
 
-        let new_account_balances =
-            Blockchain::calculate_new_account_balance(&account_balances, transactions)?;
+        let window = self.spending_policy.max_window();
+
+        for height in 1..blocks.len() {
+            let (parent, block) = (&blocks[height - 1], &blocks[height]);
+            let recent_blocks = &blocks[height.saturating_sub(window)..height];
+
+            let result = self
+                .validate_header(block, parent, verify_hash)
+                .and_then(|_| {
+                    self.calculate_new_account_balance(
+                        &account_balances,
+                        block,
+                        self.block_subsidy(block.index),
+                        recent_blocks,
+                        None,
+                    )
+                });
+
+            account_balances = result.map_err(|error| (block.index, error))?;
+        }
 
-        *account_balances = new_account_balances;
+        Ok(account_balances)
+    }
+
+    // The hash field of a block is not proof of its content, so the genesis block is checked in
+    // full. It can only carry the allocations of the configured genesis, which are never validated
+    fn validate_genesis(&self, block: &Block) -> Result<()> {
+        let is_valid = block.index == 0
+            && block.previous_hash == BlockHash::default()
+            && block.hash == block.calculate_hash()
+            && block.transactions == self.genesis_transactions;
+
+        match is_valid {
+            true => Ok(()),
+            false => Err(BlockchainError::InvalidGenesis.into()),
+        }
+    }
+
+    // Hashes are independent of each other, so they can be recomputed in parallel
+    // before the sequential checks that depend on the previous blocks
+    pub fn verify_hashes(blocks: &[Block], parallel: bool) -> Result<()> {
+        let is_invalid = |block: &Block| block.hash != block.calculate_hash();
+
+        let invalid_block = match parallel {
+            true => blocks.par_iter().position_first(is_invalid),
+            false => blocks.iter().position(is_invalid),
+        };
+
+        match invalid_block {
+            Some(_) => Err(BlockchainError::InvalidHash.into()),
+            None => Ok(()),
+        }
+    }
+
+    // Adds the blocks of another chain, which must start from the same genesis block
+    pub fn import_blocks(&self, blocks: &[Block]) -> Result<()> {
+        let mut iter = blocks.iter();
+        let genesis_hash = self.get_block_by_index(0).unwrap().hash;
+
+        match iter.next() {
+            Some(genesis_block) if genesis_block.hash == genesis_hash => {
+                self.validate_genesis(genesis_block)?
+            }
+            _ => return Err(BlockchainError::InvalidGenesis.into()),
+        }
+
+        Blockchain::verify_hashes(blocks, self.parallel_validation)?;
+
+        for block in iter {
+            self.append_block(block.clone(), false)?;
+        }
+
+        Ok(())
+    }
+
+    // Checks a block against its parent, without looking at the transactions
+    fn validate_header(&self, block: &Block, parent: &Block, verify_hash: bool) -> Result<()> {
+        if !(FIRST_BLOCK_VERSION..=BLOCK_VERSION).contains(&block.version) {
+            return Err(BlockchainError::UnsupportedBlockVersion(block.version).into());
+        }
+
+        if block.index != parent.index + 1 {
+            return Err(BlockchainError::InvalidIndex.into());
+        }
+
+        if block.previous_hash != parent.hash {
+            return Err(BlockchainError::InvalidPreviousHash.into());
+        }
+
+        if block.timestamp <= parent.timestamp {
+            return Err(BlockchainError::TimestampTooOld.into());
+        }
+
+        if block.timestamp > Utc::now().timestamp_millis() + MAX_FUTURE_BLOCK_TIME_MS {
+            return Err(BlockchainError::TimestampInFuture.into());
+        }
+
+        if verify_hash && block.hash != block.calculate_hash() {
+            return Err(BlockchainError::InvalidHash.into());
+        }
+
+        // blocks of the first version do not declare their difficulty, only meet the expected one
+        let expected_difficulty = self.expected_difficulty(block.index);
+        let difficulty = match (block.version, block.difficulty) {
+            (FIRST_BLOCK_VERSION, 0) => expected_difficulty,
+            (FIRST_BLOCK_VERSION, _) => return Err(BlockchainError::UnexpectedDifficulty.into()),
+            _ => block.difficulty,
+        };
+
+        if difficulty != expected_difficulty {
+            return Err(BlockchainError::UnexpectedDifficulty.into());
+        }
+
+        if block.hash.leading_zeros() < difficulty {
+            return Err(BlockchainError::InvalidDifficulty.into());
+        }
+
+        Ok(())
+    }
+
+    // Adds a block to the main chain if it extends the tip, or otherwise keeps it as a side branch
+    // block if its parent is known, so competing branches are tracked instead of dropped.
+    // Blocks arriving before their parent wait as orphans until the parent is added
+    pub fn receive_block(&self, block: Block) -> Result<Placement> {
+        let hash = block.hash;
+
+        if block.previous_hash == self.get_last_block().hash {
+            match self.add_block(block.clone()) {
+                Ok(()) => {
+                    self.connect_orphan_blocks(hash);
+                    return Ok(Placement::MainChain);
+                }
+                // another block took the tip in the meantime, so this one now competes with it
+                Err(_) if self.get_last_block().hash != block.previous_hash => {}
+                Err(error) => return Err(error),
+            }
+        }
+
+        if !self.is_known_block(&block.previous_hash) {
+            self.add_orphan_block(block)?;
+            return Ok(Placement::Orphan);
+        }
+
+        self.add_side_block(block)?;
+        self.connect_orphan_blocks(hash);
+
+        Ok(Placement::SideBranch)
+    }
+
+    fn is_known_block(&self, hash: &BlockHash) -> bool {
+        read_recover(&self.side_blocks).contains_key(hash) || self.get_block_by_hash(hash).is_some()
+    }
+
+    fn add_orphan_block(&self, block: Block) -> Result<()> {
+        // the parent is needed for the other checks, but junk blocks can already be refused
+        if block.index == 0 {
+            return Err(BlockchainError::InvalidIndex.into());
+        }
+
+        if block.hash != block.calculate_hash() {
+            return Err(BlockchainError::InvalidHash.into());
+        }
+
+        // buffering is not free, so the block must at least carry the work of its height
+        if block.hash.leading_zeros() < self.expected_difficulty(block.index) {
+            return Err(BlockchainError::InvalidDifficulty.into());
+        }
+
+        let mut orphan_blocks = lock_recover(&self.orphan_blocks);
+
+        if orphan_blocks.contains_key(&block.hash) {
+            return Err(BlockchainError::DuplicateBlock.into());
+        }
+
+        if orphan_blocks.len() >= MAX_ORPHAN_BLOCKS {
+            let oldest = orphan_blocks
+                .values()
+                .min_by_key(|orphan| orphan.received_at)
+                .map(|orphan| orphan.block.hash);
+
+            if let Some(hash) = oldest {
+                orphan_blocks.remove(&hash);
+            }
+        }
+
+        let orphan = OrphanBlock {
+            block,
+            received_at: Instant::now(),
+        };
+        orphan_blocks.insert(orphan.block.hash, orphan);
+
+        Ok(())
+    }
+
+    // Retries the orphans of a block that was just added to the main chain or a side branch, which
+    // connects them one after the other
+    fn connect_orphan_blocks(&self, parent_hash: BlockHash) {
+        let children: BlockVec = {
+            let mut orphan_blocks = lock_recover(&self.orphan_blocks);
+            let hashes: Vec<BlockHash> = orphan_blocks
+                .values()
+                .filter(|orphan| orphan.block.previous_hash == parent_hash)
+                .map(|orphan| orphan.block.hash)
+                .collect();
+
+            hashes
+                .iter()
+                .filter_map(|hash| orphan_blocks.remove(hash))
+                .map(|orphan| orphan.block)
+                .collect()
+        };
+
+        for block in children {
+            let index = block.index;
+
+            if let Err(error) = self.receive_block(block) {
+                warn!("Dropped orphan block {}: {}", index, error);
+            }
+        }
+    }
+
+    // The transactions of the branch are replayed on top of the main chain blocks it forks from,
+    // so a branch that could not become the main chain is not kept
+    fn add_side_block(&self, block: Block) -> Result<()> {
+        if self.get_block_by_hash(&block.hash).is_some() {
+            return Err(BlockchainError::DuplicateBlock.into());
+        }
+
+        // side blocks of the branch, from the one following the main chain to the parent
+        let mut branch = BlockVec::new();
+        let mut fork_hash = block.previous_hash;
+        {
+            let side_blocks = read_recover(&self.side_blocks);
+            while let Some(side_block) = side_blocks.get(&fork_hash) {
+                fork_hash = side_block.previous_hash;
+                branch.push(side_block.clone());
+            }
+        }
+        branch.reverse();
+
+        let fork_block = self
+            .get_block_by_hash(&fork_hash)
+            .ok_or(BlockchainError::InvalidPreviousHash)?;
+        let parent = branch.last().unwrap_or(&fork_block);
+
+        self.validate_header(&block, parent, true)?;
+
+        // the blocks of the branch were already checked when they were added
+        let mut blocks = self.get_blocks_range(0, fork_block.index as usize + 1);
+        blocks.extend(branch);
+        blocks.push(block.clone());
+        self.replay_blocks(&blocks, false)
+            .map_err(|(_, error)| error)?;
+
+        let mut side_blocks = write_recover(&self.side_blocks);
+
+        if side_blocks.len() >= MAX_SIDE_BLOCKS && !side_blocks.contains_key(&block.hash) {
+            let lowest = side_blocks
+                .values()
+                .min_by_key(|side_block| side_block.index)
+                .map(|side_block| side_block.hash);
+
+            if let Some(hash) = lowest {
+                side_blocks.remove(&hash);
+            }
+        }
+
+        side_blocks.insert(block.hash, block);
+
+        Ok(())
+    }
+
+    pub fn add_block(&self, block: Block) -> Result<()> {
+        self.append_block(block, true)
+    }
+
+    fn append_block(&self, block: Block, verify_hash: bool) -> Result<()> {
+        let mut blocks = write_recover(&self.blocks);
+        let last = blocks.last().unwrap();
+
+        self.validate_header(&block, &last, verify_hash)?;
+
+        // the new block is appended right after the existing ones
+        let height = blocks.len();
+        let window = self.spending_policy.max_window();
+        let recent_blocks = blocks.get_range(height.saturating_sub(window), window);
+
+        let audited_block_index = self.audit_log.is_enabled().then_some(block.index);
+
+        let mut account_balances = write_recover(&self.account_balances);
+        let mut new_account_balances = self.calculate_new_account_balance(
+            &account_balances,
+            &block,
+            self.block_subsidy(height as u64),
+            &recent_blocks,
+            audited_block_index,
+        )?;
+
+        // balances are only updated once the block is stored
+        blocks.append(block.clone())?;
+        let audit_entries = new_account_balances.take_audit_entries();
+
+        // uncommitted changes stay on top of the store, so the balances are right either way
+        if let Err(error) = new_account_balances.commit() {
+            error!(
+                "Could not store the balances of block {}: {}",
+                block.index, error
+            );
+        }
+        *account_balances = new_account_balances;
+        drop(account_balances);
+
+        // the block is already part of the chain, so a failed write must not reject it
+        if let Err(error) = self.audit_log.write(&audit_entries) {
+            error!(
+                "Could not write the audit log of block {}: {}",
+                block.index, error
+            );
+        }
+
+        index_transactions(&mut write_recover(&self.transaction_index), &block);
+        drop(blocks);
+
+        *lock_recover(&self.last_block_added_at) = Instant::now();
+
+        self.notify_subscribers(&block);
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{str::FromStr, thread};
+
+    use crate::model::{
+        account_balance_map::AccountBalanceMapError,
+        address::{
+            test_person_util::{person1, person2, person3},
+            Address,
+        },
+        balance_store::BalanceBackend,
+        block_store::StorageBackend,
+        spending_policy::SpendingRule,
+    };
+
+    use super::*;
+
+    const NO_DIFFICULTY: u32 = 0;
+
+    // most tests spend the block rewards right away
+    fn create_blockchain() -> Blockchain {
+        Blockchain::new(NO_DIFFICULTY).with_coinbase_maturity(0)
+    }
+
+    fn assert_err(result: Result<(), anyhow::Error>, error_type: BlockchainError) {
+        let err = result.unwrap_err().downcast::<BlockchainError>().unwrap();
+        assert_eq!(err, error_type);
+    }
+
+    fn assert_balance_err(result: Result<(), anyhow::Error>, error_type: AccountBalanceMapError) {
+        let err = result
+            .unwrap_err()
+            .downcast::<AccountBalanceMapError>()
+            .unwrap();
+        assert_eq!(err, error_type);
+    }
+
+    fn create_next_block(blockchain: &Blockchain, transactions: Vec<Transaction>) -> Block {
+        let last_block = blockchain.get_last_block();
+        let mut block = Block::new(last_block.index + 1, 0, last_block.hash, transactions);
+
+        // blocks created in quick succession could share the same millisecond
+        if block.timestamp <= last_block.timestamp {
+            block.timestamp = last_block.timestamp + 1;
+            block.hash = block.calculate_hash();
+        }
+
+        block
+    }
+
+    #[test]
+    fn should_have_valid_genesis_block() {
+        let blockchain = create_blockchain();
+
+        let blocks = blockchain.get_all_blocks();
+        assert_eq!(blocks.len(), 1);
+
+        let block = blockchain.get_last_block();
+        assert_eq!(block.hash, blocks[0].hash);
+
+        assert_eq!(block.index, 0);
+        assert_eq!(block.nonce, 0);
+        assert_eq!(block.previous_hash, BlockHash::default());
+        assert!(block.transactions.is_empty());
+    }
+
+    #[test]
+    fn should_let_adding_valid_blocks() {
+        let blockchain = create_blockchain();
+
+        let previous_hash = blockchain.get_last_block().hash;
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person2(),
+            amount: BLOCK_SUBSIDY,
+        };
+
+        let transaction1 = Transaction {
+            sender: person2(),
+            recipient: person1(),
+            amount: 5,
+        };
+
+        let transaction2 = Transaction {
+            sender: person1(),
+            recipient: person2(),
+            amount: 5,
+        };
+
+        let block = Block::new(
+            1,
+            0,
+            previous_hash,
+            vec![coinbase, transaction1, transaction2],
+        );
+
+        let result = blockchain.add_block(block.clone());
+        println!("ERROR: {:?}", result);
+        assert!(result.is_ok());
+
+        let blocks = blockchain.get_all_blocks();
+        assert_eq!(blocks.len(), 2);
+
+        let last_block = blockchain.get_last_block();
+        assert_eq!(last_block.hash, block.hash);
+    }
+
+    #[test]
+    fn should_get_blocks_range() {
+        let blockchain = create_blockchain();
+
+        for _ in 0..3 {
+            let coinbase = Transaction {
+                sender: Address::default(),
+                recipient: person1(),
+                amount: BLOCK_SUBSIDY,
+            };
+            let block = create_next_block(&blockchain, vec![coinbase]);
+            blockchain.add_block(block).unwrap();
+        }
+
+        let blocks = blockchain.get_blocks_range(1, 2);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[0].index, 1);
+        assert_eq!(blocks[1].index, 2);
+
+        let blocks = blockchain.get_blocks_range(2, usize::MAX);
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[1].index, 3);
+
+        assert!(blockchain.get_blocks_range(4, 10).is_empty());
+        assert!(blockchain.get_blocks_range(usize::MAX, 10).is_empty());
+    }
+
+    #[test]
+    fn should_get_block_by_index_and_hash() {
+        let blockchain = create_blockchain();
+        let genesis_block = blockchain.get_last_block();
+
+        let block = blockchain.get_block_by_index(0).unwrap();
+        assert_eq!(block.hash, genesis_block.hash);
+
+        let block = blockchain.get_block_by_hash(&genesis_block.hash).unwrap();
+        assert_eq!(block.index, 0);
+
+        assert!(blockchain.get_block_by_index(1).is_none());
+        assert!(blockchain.get_block_by_hash(&BlockHash::MAX).is_none());
+    }
+
+    #[test]
+    fn should_allow_concurrent_readers_while_adding_blocks() {
+        let blockchain = create_blockchain();
+
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..100 {
+                        let last_block = blockchain.get_last_block();
+                        assert!(blockchain.get_all_blocks().len() > last_block.index as usize);
+                    }
+                });
+            }
+
+            scope.spawn(|| {
+                for _ in 0..10 {
+                    let coinbase = Transaction {
+                        sender: Address::default(),
+                        recipient: person1(),
+                        amount: BLOCK_SUBSIDY,
+                    };
+                    let block = create_next_block(&blockchain, vec![coinbase]);
+                    blockchain.add_block(block).unwrap();
+                }
+            });
+        });
+
+        assert_eq!(blockchain.get_last_block().index, 10);
+    }
+
+    #[test]
+    fn should_verify_hashes_in_parallel_and_sequentially() {
+        let blockchain = create_blockchain();
+
+        for _ in 0..20 {
+            let coinbase = Transaction {
+                sender: Address::default(),
+                recipient: person1(),
+                amount: BLOCK_SUBSIDY,
+            };
+            let block = create_next_block(&blockchain, vec![coinbase]);
+            blockchain.add_block(block).unwrap();
+        }
+
+        let mut blocks = blockchain.get_all_blocks();
+        assert!(Blockchain::verify_hashes(&blocks, true).is_ok());
+        assert!(Blockchain::verify_hashes(&blocks, false).is_ok());
+
+        blocks[13].transactions[0].amount += 1;
+
+        let result = Blockchain::verify_hashes(&blocks, true);
+        assert_err(result, BlockchainError::InvalidHash);
+
+        let result = Blockchain::verify_hashes(&blocks, false);
+        assert_err(result, BlockchainError::InvalidHash);
+
+        let imported_blockchain = create_blockchain();
+        let result = imported_blockchain.import_blocks(&blocks);
+        assert_err(result, BlockchainError::InvalidHash);
+        assert_eq!(imported_blockchain.get_all_blocks().len(), 1);
+    }
+
+    #[test]
+    fn should_get_blocks_after_hash() {
+        let blockchain = create_chain_with_transfers();
+        let blocks = blockchain.get_all_blocks();
+        let checkpoint = &blocks[1];
+
+        let blocks_after = blockchain.get_blocks_after_hash(&checkpoint.hash).unwrap();
+        assert_eq!(blocks_after, blocks[2..].to_vec());
+
+        let last_block = blockchain.get_last_block();
+        assert_eq!(
+            blockchain.get_blocks_after_hash(&last_block.hash),
+            Some(vec![])
+        );
+        assert_eq!(blockchain.get_blocks_after_hash(&BlockHash::MAX), None);
+    }
+
+    #[test]
+    fn should_add_blocks_to_sqlite_store() {
+        let store = StorageBackend::Sqlite.create_store().unwrap();
+        let blockchain = create_blockchain().with_store(store).unwrap();
+        let genesis_block = blockchain.get_last_block();
+        assert_eq!(genesis_block, GenesisConfig::default().create_block());
+
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let block = create_next_block(&blockchain, vec![coinbase]);
+        blockchain.add_block(block.clone()).unwrap();
+
+        assert_eq!(blockchain.len(), 2);
+        assert_eq!(blockchain.get_last_block(), block);
+        assert_eq!(blockchain.get_block_by_hash(&block.hash), Some(block));
+
+        let invalid_block = create_next_block(&blockchain, vec![]);
+        assert_err(
+            blockchain.add_block(invalid_block),
+            BlockchainError::CoinbaseTransactionNotFound,
+        );
+        assert_eq!(blockchain.len(), 2);
+    }
+
+    #[test]
+    fn should_spend_genesis_balances() {
+        let genesis = GenesisConfig {
+            timestamp: Some(1_000),
+            balances: [(person1(), 50)].into_iter().collect(),
+        };
+        let blockchain = Blockchain::from_genesis(NO_DIFFICULTY, &genesis);
+
+        let genesis_block = blockchain.get_last_block();
+        assert_eq!(genesis_block.timestamp, 1_000);
+        assert_ne!(
+            genesis_block.hash,
+            create_blockchain().get_last_block().hash
+        );
+
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person3(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let transfer = Transaction {
+            sender: person1(),
+            recipient: person2(),
+            amount: 30,
+        };
+        let block = create_next_block(&blockchain, vec![coinbase, transfer]);
+
+        assert!(blockchain.add_block(block).is_ok());
+    }
+
+    #[test]
+    fn should_get_confirmed_balance() {
+        let blockchain = create_blockchain();
+        assert_eq!(blockchain.get_balance(&person1()).unwrap(), 0);
+
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let transfer = Transaction {
+            sender: person1(),
+            recipient: person2(),
+            amount: 30,
+        };
+        let block = create_next_block(&blockchain, vec![coinbase, transfer]);
+        blockchain.add_block(block).unwrap();
+
+        assert_eq!(
+            blockchain.get_balance(&person1()).unwrap(),
+            BLOCK_SUBSIDY - 30
+        );
+        assert_eq!(blockchain.get_balance(&person2()).unwrap(), 30);
+    }
+
+    #[test]
+    fn should_reject_spending_immature_coinbase() {
+        let coinbase_maturity = 3;
+        let blockchain = create_blockchain().with_coinbase_maturity(coinbase_maturity);
+
+        let reward = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let block = create_next_block(&blockchain, vec![reward]);
+        blockchain.add_block(block).unwrap();
+        assert_eq!(blockchain.get_spendable_balance(&person1()).unwrap(), 0);
+
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person2(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let transfer = Transaction {
+            sender: person1(),
+            recipient: person2(),
+            amount: 30,
+        };
+
+        // one block later the reward is still immature
+        let block = create_next_block(&blockchain, vec![coinbase.clone(), transfer.clone()]);
+        assert_err(
+            blockchain.add_block(block),
+            BlockchainError::ImmatureCoinbase,
+        );
+
+        for _ in 1..coinbase_maturity {
+            let block = create_next_block(&blockchain, vec![coinbase.clone()]);
+            blockchain.add_block(block).unwrap();
+        }
+        assert_eq!(
+            blockchain.get_spendable_balance(&person1()).unwrap(),
+            BLOCK_SUBSIDY
+        );
+
+        let block = create_next_block(&blockchain, vec![coinbase, transfer]);
+        blockchain.add_block(block).unwrap();
+        assert_eq!(
+            blockchain.get_balance(&person1()).unwrap(),
+            BLOCK_SUBSIDY - 30
+        );
+    }
+
+    fn create_chain_with_transfers() -> Blockchain {
+        let blockchain = create_blockchain();
+
+        for amount in [10, 20, 30] {
+            let coinbase = Transaction {
+                sender: Address::default(),
+                recipient: person1(),
+                amount: BLOCK_SUBSIDY,
+            };
+            let transfer = Transaction {
+                sender: person1(),
+                recipient: person2(),
+                amount,
+            };
+            let block = create_next_block(&blockchain, vec![coinbase, transfer]);
+            blockchain.add_block(block).unwrap();
+        }
+
+        blockchain
+    }
+
+    #[test]
+    fn should_restore_snapshot_with_the_balances_of_a_full_replay() {
+        let source = create_chain_with_transfers();
+
+        let snapshot = source.snapshot().unwrap();
+        assert_eq!(snapshot.tip_index, 3);
+        assert_eq!(snapshot.tip_hash, source.get_last_block().hash);
+
+        // later blocks are validated on top of the snapshot
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person2(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let block = create_next_block(&source, vec![coinbase]);
+        source.add_block(block).unwrap();
+
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let snapshot: ChainSnapshot = serde_json::from_str(&json).unwrap();
+
+        let blocks = source.get_all_blocks();
+        let restored = create_blockchain();
+        restored.import_snapshot(snapshot, &blocks[4..]).unwrap();
+
+        let replayed = create_blockchain();
+        replayed.import_blocks(&blocks).unwrap();
+
+        // the chain starts at the snapshot tip, after the genesis block
+        assert_eq!(restored.get_all_blocks(), blocks[3..]);
+        assert_eq!(restored.len(), 5);
+        assert_eq!(restored.get_block_by_index(0), Some(blocks[0].clone()));
+        assert_eq!(restored.get_block_by_index(1), None);
+        assert_eq!(restored.validate_chain(), Ok(()));
+        for address in [person1(), person2()] {
+            assert_eq!(
+                restored.get_balance(&address).unwrap(),
+                replayed.get_balance(&address).unwrap()
+            );
+        }
+        assert_eq!(
+            restored.get_balance(&person2()).unwrap(),
+            60 + BLOCK_SUBSIDY
+        );
+        assert!(restored
+            .find_transaction(&blocks[3].transactions[1].hash())
+            .is_some());
+
+        // the saved blocks could not be loaded back
+        let path = std::env::temp_dir().join(format!("snapshot-chain-{}.bin", std::process::id()));
+        let result = restored.save_to_file(path.to_str().unwrap());
+        assert_err(result, BlockchainError::PrunedChain);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn should_keep_balances_in_balance_store() {
+        let source = create_chain_with_transfers();
+        let blocks = source.get_all_blocks();
+
+        let store = BalanceBackend::Sled.create_store().unwrap();
+        let blockchain = create_blockchain()
+            .with_balance_store(store.clone())
+            .unwrap();
+        blockchain.import_blocks(&blocks).unwrap();
+
+        for address in [person1(), person2()] {
+            assert_eq!(
+                blockchain.get_balance(&address).unwrap(),
+                source.get_balance(&address).unwrap()
+            );
+        }
+        assert_eq!(store.get(&person2()).unwrap(), Some(60));
+
+        // a snapshot replaces the content of the store
+        let restored = create_blockchain()
+            .with_balance_store(store.clone())
+            .unwrap();
+        restored
+            .import_snapshot(source.snapshot().unwrap(), &[])
+            .unwrap();
+        assert_eq!(restored.get_balance(&person2()).unwrap(), 60);
+        assert_eq!(store.get(&person2()).unwrap(), Some(60));
+    }
+
+    #[test]
+    fn should_keep_a_full_chain_from_a_snapshot_at_the_genesis_block() {
+        let source = create_chain_with_transfers();
+        let snapshot = create_blockchain().snapshot().unwrap();
+
+        let restored = create_blockchain();
+        restored
+            .import_snapshot(snapshot, &source.get_all_blocks()[1..])
+            .unwrap();
+        assert_eq!(restored.get_all_blocks(), source.get_all_blocks());
+
+        let path =
+            std::env::temp_dir().join(format!("genesis-snapshot-{}.bin", std::process::id()));
+        restored.save_to_file(path.to_str().unwrap()).unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn should_reject_snapshot_not_matching_the_blocks() {
+        let source = create_chain_with_transfers();
+        let mut snapshot = source.snapshot().unwrap();
+        snapshot.tip_hash = BlockHash::default();
+
+        let result = create_blockchain().import_snapshot(snapshot, &[]);
+        assert_err(result, BlockchainError::InvalidSnapshot);
+
+        let mut snapshot = source.snapshot().unwrap();
+        snapshot.tip_block.transactions.clear();
+        let result = create_blockchain().import_snapshot(snapshot, &[]);
+        assert_err(result, BlockchainError::InvalidSnapshot);
+
+        // only on a new chain of the same network
+        let mut snapshot = source.snapshot().unwrap();
+        snapshot.genesis_hash = BlockHash::default();
+        let result = create_blockchain().import_snapshot(snapshot, &[]);
+        assert_err(result, BlockchainError::InvalidGenesis);
+
+        let result = source.import_snapshot(source.snapshot().unwrap(), &[]);
+        assert_err(result, BlockchainError::InvalidGenesis);
+
+        // trailing blocks must follow the tip
+        let snapshot = source.snapshot().unwrap();
+        let result = create_blockchain().import_snapshot(snapshot, &source.get_all_blocks()[1..]);
+        assert_err(result, BlockchainError::InvalidIndex);
+    }
+
+    #[test]
+    fn should_round_trip_binary_export() {
+        let blockchain = create_chain_with_transfers();
+
+        let bytes = blockchain.export_binary();
+        let json = serde_json::to_vec(&blockchain.get_all_blocks()).unwrap();
+        assert!(bytes.len() < json.len());
+
+        let imported_blockchain = create_blockchain();
+        imported_blockchain.import_binary(&bytes).unwrap();
+        assert_eq!(
+            imported_blockchain.get_all_blocks(),
+            blockchain.get_all_blocks()
+        );
+        for address in [person1(), person2()] {
+            assert_eq!(
+                imported_blockchain.get_balance(&address).unwrap(),
+                blockchain.get_balance(&address).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn should_not_import_invalid_binary_export() {
+        let bytes = create_chain_with_transfers().export_binary();
+
+        assert!(create_blockchain()
+            .import_binary(&bytes[..bytes.len() / 2])
+            .is_err());
+        assert!(create_blockchain().import_binary(&[]).is_err());
+    }
+
+    #[test]
+    fn should_save_and_load_chain_file() {
+        let path = std::env::temp_dir()
+            .join(format!("chain-{}.bin", std::process::id()))
+            .to_string_lossy()
+            .to_string();
+        let blockchain = create_chain_with_transfers();
+        let saved_blocks = blockchain.get_all_blocks();
+
+        blockchain.save_to_file(&path).unwrap();
+
+        // the next save moves the first one to the backup
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let block = create_next_block(&blockchain, vec![coinbase]);
+        blockchain.add_block(block).unwrap();
+        blockchain.save_to_file(&path).unwrap();
+        assert!(fs::metadata(with_suffix(&path, ".tmp")).is_err());
+
+        let loaded = create_blockchain();
+        assert!(loaded.load_from_file(&path).unwrap());
+        assert_eq!(loaded.get_all_blocks(), blockchain.get_all_blocks());
+
+        // a truncated file, like one written by a node killed mid-write
+        let bytes = fs::read(&path).unwrap();
+        fs::write(&path, &bytes[..bytes.len() / 2]).unwrap();
+
+        let loaded = create_blockchain();
+        assert!(loaded.load_from_file(&path).unwrap());
+        assert_eq!(loaded.get_all_blocks(), saved_blocks);
+
+        // a corrupt backup is an error, unlike a chain that was never saved
+        fs::write(with_suffix(&path, ".bak"), &bytes[..bytes.len() / 2]).unwrap();
+        assert!(create_blockchain().load_from_file(&path).is_err());
+
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(with_suffix(&path, ".bak")).unwrap();
+        assert!(!create_blockchain().load_from_file(&path).unwrap());
+    }
+
+    #[test]
+    fn should_validate_consistent_chain() {
+        let blockchain = create_chain_with_transfers();
+
+        assert_eq!(blockchain.validate_chain(), Ok(()));
+    }
+
+    fn create_genesis_block_with_transactions() -> Block {
+        let mut genesis_block = GenesisConfig::default().create_block();
+        genesis_block.transactions = vec![Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: 1000,
+        }];
+        genesis_block.hash = genesis_block.calculate_hash();
+
+        genesis_block
+    }
+
+    #[test]
+    fn should_reject_genesis_block_with_transactions() {
+        let blockchain = create_blockchain();
+        let genesis_hash = blockchain.get_last_block().hash;
+
+        let genesis_block = create_genesis_block_with_transactions();
+        let result = blockchain.import_blocks(std::slice::from_ref(&genesis_block));
+        assert_err(result, BlockchainError::InvalidGenesis);
+
+        // even when it claims the hash of the expected genesis block
+        let mut forged_block = genesis_block.clone();
+        forged_block.hash = genesis_hash;
+        let result = blockchain.import_blocks(&[forged_block]);
+        assert_err(result, BlockchainError::InvalidGenesis);
+
+        let mut store = InMemoryStore::default();
+        store.append(genesis_block).unwrap();
+        let result = create_blockchain().with_store(Box::new(store));
+        assert_err(result.map(|_| ()), BlockchainError::InvalidGenesis);
+    }
+
+    #[test]
+    fn should_find_invalid_genesis_block_of_the_chain() {
+        let mut blockchain = create_blockchain();
+
+        // the store accepts any block, unlike with_store
+        let mut store = InMemoryStore::default();
+        store
+            .append(create_genesis_block_with_transactions())
+            .unwrap();
+        blockchain.blocks = Arc::new(RwLock::new(Box::new(store)));
+
+        let error = blockchain.validate_chain().unwrap_err();
+        assert_eq!(error.index, 0);
+        assert_eq!(error.reason, BlockchainError::InvalidGenesis.to_string());
+    }
+
+    #[test]
+    fn should_accept_configured_genesis_allocations() {
+        let genesis = GenesisConfig {
+            timestamp: None,
+            balances: [(person1(), 1000)].into_iter().collect(),
+        };
+        let blockchain = Blockchain::from_genesis(NO_DIFFICULTY, &genesis);
+
+        let genesis_block = genesis.create_block();
+        assert!(blockchain.import_blocks(&[genesis_block]).is_ok());
+        assert_eq!(blockchain.validate_chain(), Ok(()));
+    }
+
+    #[test]
+    fn should_find_first_invalid_block_of_the_chain() {
+        let blockchain = create_chain_with_transfers();
+        let last_block = blockchain.get_last_block();
+
+        // the store accepts any block, unlike add_block
+        let mut overspending_block = Block::new(last_block.index + 1, 0, last_block.hash, vec![]);
+        overspending_block.transactions = vec![
+            Transaction {
+                sender: Address::default(),
+                recipient: person1(),
+                amount: BLOCK_SUBSIDY,
+            },
+            Transaction {
+                sender: person2(),
+                recipient: person1(),
+                amount: 1000,
+            },
+        ];
+        overspending_block.timestamp = last_block.timestamp + 1;
+        overspending_block.hash = overspending_block.calculate_hash();
+        write_recover(&blockchain.blocks)
+            .append(overspending_block.clone())
+            .unwrap();
+
+        let error = blockchain.validate_chain().unwrap_err();
+        assert_eq!(error.index, overspending_block.index);
+        assert_eq!(
+            error.reason,
+            AccountBalanceMapError::InsufficientFunds.to_string()
+        );
+    }
+
+    #[test]
+    fn should_reject_unknown_block_version() {
+        let blockchain = create_blockchain();
+        assert_eq!(blockchain.get_last_block().version, BLOCK_VERSION);
+
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let mut block = create_next_block(&blockchain, vec![coinbase]);
+        block.version = 99;
+        block.hash = block.calculate_hash();
+
+        assert_err(
+            blockchain.add_block(block),
+            BlockchainError::UnsupportedBlockVersion(99),
+        );
+    }
+
+    #[test]
+    fn should_accept_first_version_blocks() {
+        let blockchain = Blockchain::new(1).with_coinbase_maturity(0);
+
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let mut block = create_next_block(&blockchain, vec![coinbase]);
+        block.version = FIRST_BLOCK_VERSION;
+        block.difficulty = 0;
+        block.hash = block.calculate_hash();
+        while block.hash.leading_zeros() < 1 {
+            block.nonce += 1;
+            block.hash = block.calculate_hash();
+        }
+
+        blockchain.add_block(block.clone()).unwrap();
+        assert_eq!(blockchain.get_last_block(), block);
+
+        // they do not declare a difficulty, as it was not part of their hash
+        let mut next_block = create_next_block(&blockchain, vec![]);
+        next_block.version = FIRST_BLOCK_VERSION;
+        next_block.difficulty = 1;
+        next_block.hash = next_block.calculate_hash();
+        assert_err(
+            blockchain.add_block(next_block),
+            BlockchainError::UnexpectedDifficulty,
+        );
+    }
+
+    #[test]
+    fn should_audit_balance_changes_of_accepted_blocks() {
+        let path =
+            std::env::temp_dir().join(format!("blockchain-audit-{}.jsonl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let _ = std::fs::remove_file(path);
+
+        let audit_log = AuditLog::open(path).unwrap();
+        let blockchain = create_blockchain().with_audit_log(audit_log);
+
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let transfer = Transaction {
+            sender: person1(),
+            recipient: person2(),
+            amount: 30,
+        };
+        let block = create_next_block(&blockchain, vec![coinbase.clone(), transfer]);
+        blockchain.add_block(block).unwrap();
+
+        // rejected blocks leave no trace
+        let unaffordable = Transaction {
+            sender: person2(),
+            recipient: person1(),
+            amount: 1_000,
+        };
+        let block = create_next_block(&blockchain, vec![coinbase, unaffordable]);
+        assert!(blockchain.add_block(block).is_err());
+
+        let contents = std::fs::read_to_string(path).unwrap();
+        std::fs::remove_file(path).unwrap();
+
+        let deltas: Vec<i64> = contents
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap())
+            .map(|entry| entry["delta"].as_i64().unwrap())
+            .collect();
+        assert_eq!(deltas, vec![BLOCK_SUBSIDY as i64, -30, 30]);
+    }
+
+    #[test]
+    fn should_read_blocks_after_a_writer_panicked() {
+        let blockchain = create_blockchain();
+
+        let blocks = blockchain.blocks.clone();
+        let result = thread::spawn(move || {
+            let _guard = blocks.write().unwrap();
+            panic!("poisoning the blocks lock");
+        })
+        .join();
+
+        assert!(result.is_err());
+        assert!(blockchain.blocks.is_poisoned());
+        assert_eq!(blockchain.get_last_block().index, 0);
+        assert_eq!(blockchain.len(), 1);
+    }
+
+    #[test]
+    fn should_read_blocks_without_cloning() {
+        let blockchain = create_blockchain();
+        assert_eq!(blockchain.len(), 1);
+        assert_eq!(blockchain.get_last_block_index(), 0);
+
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let block = create_next_block(&blockchain, vec![coinbase]);
+        blockchain.add_block(block.clone()).unwrap();
+
+        assert_eq!(blockchain.len(), 2);
+        assert_eq!(blockchain.get_last_block_index(), 1);
+
+        let last_hash = blockchain.with_blocks(|blocks| blocks.get(1).unwrap().hash);
+        assert_eq!(last_hash, block.hash);
+    }
+
+    #[test]
+    fn should_keep_side_branch_blocks() {
+        let blockchain = create_blockchain();
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+
+        let block = create_next_block(&blockchain, vec![coinbase.clone()]);
+        blockchain.add_block(block).unwrap();
+
+        // a competing block at the same height, built on the genesis block
+        let genesis_block = blockchain.get_block_by_index(0).unwrap();
+        let mut side_block = Block::new(1, 1, genesis_block.hash, vec![coinbase]);
+        side_block.hash = side_block.calculate_hash();
+
+        let placement = blockchain.receive_block(side_block.clone()).unwrap();
+        assert_eq!(placement, Placement::SideBranch);
+        assert_eq!(blockchain.get_last_block_index(), 1);
+        assert_ne!(blockchain.get_last_block().hash, side_block.hash);
+
+        let side_blocks = blockchain.get_side_blocks();
+        assert_eq!(side_blocks.len(), 1);
+        assert_eq!(side_blocks[0].hash, side_block.hash);
+
+        let result = blockchain.receive_block(blockchain.get_last_block());
+        assert_err(result.map(|_| ()), BlockchainError::DuplicateBlock);
+    }
+
+    #[test]
+    fn should_replay_side_branch_transactions() {
+        let blockchain = create_blockchain();
+        let genesis_block = blockchain.get_last_block();
+        let coinbase = |recipient: Address, amount: u64| Transaction {
+            sender: Address::default(),
+            recipient,
+            amount,
+        };
+
+        let block = create_next_block(&blockchain, vec![coinbase(person1(), BLOCK_SUBSIDY)]);
+        blockchain.add_block(block).unwrap();
+
+        // the branch pays its reward to another address, which only has funds on the branch
+        let mut side_block = Block::new(
+            1,
+            1,
+            genesis_block.hash,
+            vec![coinbase(person2(), BLOCK_SUBSIDY)],
+        );
+        side_block.hash = side_block.calculate_hash();
+        blockchain.receive_block(side_block.clone()).unwrap();
+
+        let create_side_child = |transactions: Vec<Transaction>| {
+            let mut side_child = Block::new(2, 0, side_block.hash, transactions);
+            side_child.timestamp = side_block.timestamp + 1;
+            side_child.hash = side_child.calculate_hash();
+            side_child
+        };
+
+        let transfer = Transaction {
+            sender: person2(),
+            recipient: person3(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let side_child = create_side_child(vec![coinbase(person1(), BLOCK_SUBSIDY), transfer]);
+        let placement = blockchain.receive_block(side_child).unwrap();
+        assert_eq!(placement, Placement::SideBranch);
+
+        let side_child = create_side_child(vec![coinbase(person1(), BLOCK_SUBSIDY + 1)]);
+        let result = blockchain.receive_block(side_child);
+        assert_err(result.map(|_| ()), BlockchainError::InvalidCoinbaseAmount);
+
+        // the reward of the main chain block does not exist on the branch
+        let unfunded = Transaction {
+            sender: person1(),
+            recipient: person3(),
+            amount: 1,
+        };
+        let side_child = create_side_child(vec![coinbase(person2(), BLOCK_SUBSIDY), unfunded]);
+        let result = blockchain.receive_block(side_child);
+        assert_balance_err(
+            result.map(|_| ()),
+            AccountBalanceMapError::SenderAccountDoesNotExist,
+        );
+
+        assert_eq!(blockchain.get_side_blocks().len(), 2);
+    }
+
+    #[test]
+    fn should_connect_blocks_received_before_their_parent() {
+        let blockchain = create_blockchain();
+        let genesis_block = blockchain.get_last_block();
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+
+        let block = create_next_block(&blockchain, vec![coinbase.clone()]);
+        let mut next_block = Block::new(block.index + 1, 0, block.hash, vec![coinbase]);
+        next_block.timestamp = block.timestamp + 1;
+        next_block.hash = next_block.calculate_hash();
+
+        let placement = blockchain.receive_block(next_block.clone()).unwrap();
+        assert_eq!(placement, Placement::Orphan);
+        assert_eq!(blockchain.get_last_block(), genesis_block);
+        assert_eq!(blockchain.get_orphan_blocks(), vec![next_block.clone()]);
+
+        let placement = blockchain.receive_block(block.clone()).unwrap();
+        assert_eq!(placement, Placement::MainChain);
+        assert_eq!(
+            blockchain.get_all_blocks(),
+            vec![genesis_block, block, next_block]
+        );
+        assert!(blockchain.get_orphan_blocks().is_empty());
+    }
+
+    #[test]
+    fn should_connect_orphans_of_side_branch_blocks() {
+        let blockchain = create_blockchain();
+        let genesis_block = blockchain.get_last_block();
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+
+        let block = create_next_block(&blockchain, vec![coinbase.clone()]);
+        blockchain.add_block(block).unwrap();
+
+        let mut side_block = Block::new(1, 1, genesis_block.hash, vec![coinbase.clone()]);
+        side_block.hash = side_block.calculate_hash();
+        let mut side_child = Block::new(2, 0, side_block.hash, vec![coinbase]);
+        side_child.timestamp = side_block.timestamp + 1;
+        side_child.hash = side_child.calculate_hash();
+
+        let placement = blockchain.receive_block(side_child.clone()).unwrap();
+        assert_eq!(placement, Placement::Orphan);
+
+        let placement = blockchain.receive_block(side_block).unwrap();
+        assert_eq!(placement, Placement::SideBranch);
+        assert!(blockchain.get_orphan_blocks().is_empty());
+        assert!(blockchain.get_side_blocks().contains(&side_child));
+    }
+
+    #[test]
+    fn should_refuse_orphans_without_enough_work() {
+        let blockchain = Blockchain::new(32);
+        let block = Block::new(1, 0, BlockHash::MAX, vec![]);
+        assert!(block.hash.leading_zeros() < 32);
+
+        let result = blockchain.receive_block(block);
+        assert_err(result.map(|_| ()), BlockchainError::InvalidDifficulty);
+        assert!(blockchain.get_orphan_blocks().is_empty());
+    }
+
+    #[test]
+    fn should_evict_oldest_orphan_blocks() {
+        let blockchain = create_blockchain();
+
+        let orphans: BlockVec = (0..=MAX_ORPHAN_BLOCKS as u64)
+            .map(|i| Block::new(5, 0, BlockHash::from(i + 1), vec![]))
+            .collect();
+        for orphan in orphans.iter() {
+            let placement = blockchain.receive_block(orphan.clone()).unwrap();
+            assert_eq!(placement, Placement::Orphan);
+        }
+
+        let orphan_blocks = blockchain.get_orphan_blocks();
+        assert_eq!(orphan_blocks.len(), MAX_ORPHAN_BLOCKS);
+        assert!(!orphan_blocks.contains(&orphans[0]));
+        assert!(orphan_blocks.contains(&orphans[MAX_ORPHAN_BLOCKS]));
+    }
+
+    #[test]
+    fn should_keep_blocks_with_unknown_parent_as_orphans() {
+        let blockchain = create_blockchain();
+        let block = Block::new(1, 0, BlockHash::MAX, vec![]);
+
+        let placement = blockchain.receive_block(block.clone()).unwrap();
+        assert_eq!(placement, Placement::Orphan);
+        assert!(blockchain.get_side_blocks().is_empty());
+
+        let result = blockchain.receive_block(block);
+        assert_err(result.map(|_| ()), BlockchainError::DuplicateBlock);
+
+        let mut invalid_block = Block::new(1, 0, BlockHash::MAX, vec![]);
+        invalid_block.hash = BlockHash::default();
+        let result = blockchain.receive_block(invalid_block);
+        assert_err(result.map(|_| ()), BlockchainError::InvalidHash);
+
+        let genesis_like_block = Block::new(0, 0, BlockHash::MAX, vec![]);
+        let result = blockchain.receive_block(genesis_like_block);
+        assert_err(result.map(|_| ()), BlockchainError::InvalidIndex);
+    }
+
+    #[test]
+    fn should_find_confirmed_transaction() {
+        let blockchain = create_blockchain();
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let transaction = Transaction {
+            sender: person1(),
+            recipient: person2(),
+            amount: 10,
+        };
+
+        let block = create_next_block(&blockchain, vec![coinbase, transaction.clone()]);
+        blockchain.add_block(block).unwrap();
+
+        let found = blockchain.find_transaction(&transaction.hash());
+        assert_eq!(found, Some((1, transaction)));
+    }
+
+    #[test]
+    fn should_index_every_block_confirming_an_identical_transfer() {
+        let blockchain = create_blockchain();
+        let transaction = Transaction {
+            sender: person1(),
+            recipient: person2(),
+            amount: 10,
+        };
+
+        for _ in 0..2 {
+            let coinbase = Transaction {
+                sender: Address::default(),
+                recipient: person1(),
+                amount: BLOCK_SUBSIDY,
+            };
+            let block = create_next_block(&blockchain, vec![coinbase, transaction.clone()]);
+            blockchain.add_block(block).unwrap();
+        }
+
+        let found = blockchain.find_transaction(&transaction.hash());
+        assert_eq!(found, Some((1, transaction.clone())));
+        assert!(blockchain.is_confirmed_after(&transaction.hash(), 1));
+        assert!(!blockchain.is_confirmed_after(&transaction.hash(), 2));
+    }
+
+    #[test]
+    fn should_not_find_missing_transaction() {
+        let blockchain = create_blockchain();
+        let transaction = Transaction {
+            sender: person1(),
+            recipient: person2(),
+            amount: 10,
+        };
 
-        Ok(())
+        assert!(blockchain.find_transaction(&transaction.hash()).is_none());
     }
 
-    pub fn add_block(&self, block: Block) -> Result<()> {
-        let mut blocks = self.blocks.lock().unwrap();
-        let last = &blocks[blocks.len() - 1];
-
-        if block.index != last.index + 1 {
-            return Err(BlockchainError::InvalidIndex.into());
-        }
+    #[test]
+    fn should_not_let_add_block_with_multiple_coinbases() {
+        let blockchain = create_blockchain();
 
-        if block.previous_hash != last.hash {
-            return Err(BlockchainError::InvalidPreviousHash.into());
-        }
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let second_coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person2(),
+            amount: BLOCK_SUBSIDY,
+        };
 
-        if block.hash != block.calculate_hash() {
-            return Err(BlockchainError::InvalidHash.into());
-        }
+        let block = create_next_block(&blockchain, vec![coinbase, second_coinbase]);
+        let result = blockchain.add_block(block);
 
-        if block.hash.leading_zeros() < self.difficulty {
-            return Err(BlockchainError::InvalidDifficulty.into());
-        }
+        assert_err(result, BlockchainError::MultipleCoinbase);
+        assert_eq!(blockchain.len(), 1);
+    }
 
-        self.udpate_account_balance(&block.transactions)?;
+    #[test]
+    fn should_reject_zero_coinbase_recipient_if_required() {
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: Address::default(),
+            amount: BLOCK_SUBSIDY,
+        };
 
-        blocks.push(block);
+        let blockchain = create_blockchain();
+        let block = create_next_block(&blockchain, vec![coinbase.clone()]);
+        assert!(blockchain.add_block(block).is_ok());
 
-        Ok(())
+        let blockchain = create_blockchain().with_require_coinbase_recipient(true);
+        let block = create_next_block(&blockchain, vec![coinbase]);
+        let result = blockchain.add_block(block);
+        assert_err(result, BlockchainError::InvalidCoinbaseRecipient);
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::model::{
-        account_balance_map::AccountBalanceMapError,
-        address::{
-            test_person_util::{person1, person2, person3},
-            Address,
-        },
-    };
+    #[test]
+    fn should_halve_block_subsidy() {
+        let halving_interval = 10;
+        let blockchain = create_blockchain().with_halving_interval(halving_interval);
+
+        assert_eq!(blockchain.block_subsidy(0), BLOCK_SUBSIDY);
+        assert_eq!(
+            blockchain.block_subsidy(halving_interval - 1),
+            BLOCK_SUBSIDY
+        );
+        assert_eq!(
+            blockchain.block_subsidy(halving_interval),
+            BLOCK_SUBSIDY / 2
+        );
+        assert_eq!(
+            blockchain.block_subsidy(halving_interval * 2),
+            BLOCK_SUBSIDY / 4
+        );
+        assert_eq!(blockchain.block_subsidy(halving_interval * 7), 0);
+        assert_eq!(blockchain.block_subsidy(halving_interval * 100), 0);
+    }
 
-    use super::*;
+    #[test]
+    fn should_validate_coinbase_with_halved_subsidy() {
+        let blockchain = create_blockchain().with_halving_interval(1);
 
-    const NO_DIFFICULTY: u32 = 0;
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let block = create_next_block(&blockchain, vec![coinbase.clone()]);
+        let result = blockchain.add_block(block);
+        assert_err(result, BlockchainError::InvalidCoinbaseAmount);
 
-    fn assert_err(result: Result<(), anyhow::Error>, error_type: BlockchainError) {
-        let err = result.unwrap_err().downcast::<BlockchainError>().unwrap();
-        assert_eq!(err, error_type);
+        let coinbase = Transaction {
+            amount: blockchain.block_subsidy(1),
+            ..coinbase
+        };
+        let block = create_next_block(&blockchain, vec![coinbase]);
+        let result = blockchain.add_block(block);
+        assert!(result.is_ok());
     }
 
-    fn assert_balance_err(result: Result<(), anyhow::Error>, error_type: AccountBalanceMapError) {
-        let err = result
-            .unwrap_err()
-            .downcast::<AccountBalanceMapError>()
-            .unwrap();
-        assert_eq!(err, error_type);
+    fn create_coinbase_outputs(outputs: Vec<(Address, u64)>) -> Vec<Transaction> {
+        outputs
+            .into_iter()
+            .map(|(recipient, amount)| Transaction {
+                sender: Address::default(),
+                recipient,
+                amount,
+            })
+            .collect()
     }
 
     #[test]
-    fn should_have_valid_genesis_block() {
-        let blockchain = Blockchain::new(NO_DIFFICULTY);
+    fn should_accept_split_coinbase() {
+        let split = RewardSplit::from_str(&format!("{}:90,{}:10", person1(), person2())).unwrap();
+        let blockchain = create_blockchain().with_reward_split(split);
 
-        let blocks = blockchain.get_all_blocks();
-        assert_eq!(blocks.len(), 1);
+        let mut transactions = create_coinbase_outputs(vec![(person1(), 90), (person2(), 10)]);
+        transactions.push(Transaction {
+            sender: person1(),
+            recipient: person3(),
+            amount: 5,
+        });
+        let block = create_next_block(&blockchain, transactions);
+        blockchain.add_block(block).unwrap();
 
-        let block = blockchain.get_last_block();
-        assert_eq!(block.hash, blocks[0].hash);
+        assert_eq!(blockchain.get_balance(&person1()).unwrap(), 85);
+        assert_eq!(blockchain.get_balance(&person2()).unwrap(), 10);
+        assert_eq!(blockchain.get_balance(&person3()).unwrap(), 5);
+    }
 
-        assert_eq!(block.index, 0);
-        assert_eq!(block.nonce, 0);
-        assert_eq!(block.previous_hash, BlockHash::default());
-        assert!(block.transactions.is_empty());
+    #[test]
+    fn should_reject_invalid_split_coinbase() {
+        let split = RewardSplit::from_str(&format!("{}:90,{}:10", person1(), person2())).unwrap();
+        let blockchain = create_blockchain().with_reward_split(split);
+
+        // outputs that do not add up to the subsidy
+        let transactions = create_coinbase_outputs(vec![(person1(), 90), (person2(), 20)]);
+        let block = create_next_block(&blockchain, transactions);
+        let result = blockchain.add_block(block);
+        assert_err(result, BlockchainError::InvalidCoinbaseAmount);
+
+        // the full subsidy, but not shared as configured
+        let transactions = create_coinbase_outputs(vec![(person1(), 50), (person2(), 50)]);
+        let block = create_next_block(&blockchain, transactions);
+        let result = blockchain.add_block(block);
+        assert_err(result, BlockchainError::InvalidRewardSplit);
+
+        // a single output is not enough once the reward is split
+        let transactions = create_coinbase_outputs(vec![(person1(), BLOCK_SUBSIDY)]);
+        let block = create_next_block(&blockchain, transactions);
+        let result = blockchain.add_block(block);
+        assert_err(result, BlockchainError::InvalidRewardSplit);
+
+        // without a split, the reward is paid by a single output
+        let blockchain = create_blockchain();
+        let transactions = create_coinbase_outputs(vec![(person1(), 90), (person2(), 10)]);
+        let block = create_next_block(&blockchain, transactions);
+        let result = blockchain.add_block(block);
+        assert_err(result, BlockchainError::InvalidCoinbaseAmount);
     }
 
     #[test]
-    fn should_let_adding_valid_blocks() {
-        let blockchain = Blockchain::new(NO_DIFFICULTY);
+    fn should_flag_stalled_chain() {
+        let blockchain = create_blockchain();
+        let threshold = Duration::from_millis(50);
+
+        assert!(!blockchain.is_stalled(threshold));
+
+        std::thread::sleep(threshold * 2);
+        assert!(blockchain.is_stalled(threshold));
 
-        let previous_hash = blockchain.get_last_block().hash;
         let coinbase = Transaction {
             sender: Address::default(),
-            recipient: person2(),
+            recipient: person1(),
             amount: BLOCK_SUBSIDY,
         };
+        let block = create_next_block(&blockchain, vec![coinbase]);
+        blockchain.add_block(block).unwrap();
 
-        let transaction1 = Transaction {
-            sender: person2(),
-            recipient: person1(),
-            amount: 5,
-        };
+        assert!(!blockchain.is_stalled(threshold));
+    }
 
-        let transaction2 = Transaction {
-            sender: person1(),
-            recipient: person2(),
-            amount: 5,
-        };
+    #[test]
+    fn should_notify_subscribers_of_new_blocks() {
+        let blockchain = create_blockchain();
+        let receiver = blockchain.subscribe();
+        let dropped_receiver = blockchain.subscribe();
+        drop(dropped_receiver);
 
-        let block = Block::new(
-            1,
-            0,
-            previous_hash,
-            vec![coinbase, transaction1, transaction2],
-        );
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
 
-        let result = blockchain.add_block(block.clone());
-        println!("ERROR: {:?}", result);
-        assert!(result.is_ok());
+        for _ in 0..2 {
+            let block = create_next_block(&blockchain, vec![coinbase.clone()]);
+            let result = blockchain.add_block(block.clone());
+            assert!(result.is_ok());
 
-        let blocks = blockchain.get_all_blocks();
-        assert_eq!(blocks.len(), 2);
+            let received_block = receiver.try_recv().unwrap();
+            assert_eq!(received_block.hash, block.hash);
+        }
 
-        let last_block = blockchain.get_last_block();
-        assert_eq!(last_block.hash, block.hash);
+        assert!(receiver.try_recv().is_err());
     }
 
     #[test]
     fn should_not_let_adding_block_with_invalid_index() {
-        let blockchain = Blockchain::new(NO_DIFFICULTY);
+        let blockchain = create_blockchain();
 
         let invalid_index = 2;
         let previous_hash = blockchain.get_last_block().hash;
@@ -273,7 +2528,7 @@ mod tests {
 
     #[test]
     fn should_not_let_adding_block_with_invalid_previous_hash() {
-        let blockchain = Blockchain::new(NO_DIFFICULTY);
+        let blockchain = create_blockchain();
 
         let invalid_previous_hash = BlockHash::default();
         let block = Block::new(1, 0, invalid_previous_hash, Vec::new());
@@ -282,9 +2537,43 @@ mod tests {
         assert_err(result, BlockchainError::InvalidPreviousHash);
     }
 
+    #[test]
+    fn should_not_let_adding_block_with_backdated_timestamp() {
+        let blockchain = create_blockchain();
+
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+
+        let block = create_next_block(&blockchain, vec![coinbase.clone()]);
+        let last_timestamp = block.timestamp;
+        blockchain.add_block(block).unwrap();
+
+        let mut block = create_next_block(&blockchain, vec![coinbase]);
+        block.timestamp = last_timestamp - 1;
+        block.hash = block.calculate_hash();
+
+        let result = blockchain.add_block(block);
+        assert_err(result, BlockchainError::TimestampTooOld);
+    }
+
+    #[test]
+    fn should_not_let_adding_block_with_future_timestamp() {
+        let blockchain = create_blockchain();
+
+        let mut block = create_next_block(&blockchain, Vec::new());
+        block.timestamp += MAX_FUTURE_BLOCK_TIME_MS + 60 * 1000;
+        block.hash = block.calculate_hash();
+
+        let result = blockchain.add_block(block);
+        assert_err(result, BlockchainError::TimestampInFuture);
+    }
+
     #[test]
     fn should_not_led_adding_block_with_invalid_hash() {
-        let blockchain = Blockchain::new(NO_DIFFICULTY);
+        let blockchain = create_blockchain();
 
         let previous_hash = blockchain.get_last_block().hash;
         let mut block = Block::new(1, 0, previous_hash, Vec::new());
@@ -294,13 +2583,66 @@ mod tests {
         assert_err(result, BlockchainError::InvalidHash);
     }
 
+    #[test]
+    fn should_ramp_difficulty_up_over_first_blocks() {
+        let blockchain = Blockchain::new(20).with_difficulty_ramp(10);
+
+        assert_eq!(blockchain.ramped_difficulty(1), 2);
+        assert_eq!(blockchain.ramped_difficulty(5), 10);
+        assert_eq!(blockchain.ramped_difficulty(9), 18);
+        assert_eq!(blockchain.ramped_difficulty(10), 20);
+        assert_eq!(blockchain.ramped_difficulty(1000), 20);
+
+        let difficulties: Vec<u32> = (1..=10)
+            .map(|height| blockchain.ramped_difficulty(height))
+            .collect();
+        assert!(difficulties.windows(2).all(|pair| pair[0] <= pair[1]));
+
+        let blockchain = Blockchain::new(20);
+        assert_eq!(blockchain.ramped_difficulty(1), 20);
+    }
+
+    #[test]
+    fn should_validate_blocks_against_ramped_difficulty() {
+        let blockchain = Blockchain::new(8).with_difficulty_ramp(4);
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+
+        // the full difficulty is not the one expected for the first block
+        let mut block = create_next_block(&blockchain, vec![coinbase.clone()]);
+        block.difficulty = 8;
+        block.hash = block.calculate_hash();
+        assert_err(
+            blockchain.add_block(block),
+            BlockchainError::UnexpectedDifficulty,
+        );
+
+        // a few tries find a hash for the ramped difficulty of 2
+        let mut block = create_next_block(&blockchain, vec![coinbase]);
+        block.difficulty = blockchain.expected_difficulty(1);
+        block.hash = block.calculate_hash();
+        while block.hash.leading_zeros() < block.difficulty {
+            block.nonce += 1;
+            block.hash = block.calculate_hash();
+        }
+        assert_eq!(block.difficulty, 2);
+        blockchain.add_block(block).unwrap();
+
+        assert_eq!(blockchain.expected_difficulty(4), 8);
+    }
+
     #[test]
     fn should_not_let_adding_block_with_invalid_difficulty() {
         let difficulty: u32 = 30;
         let blockchain = Blockchain::new(difficulty);
 
         let previous_hash = blockchain.get_last_block().hash;
-        let block = Block::new(1, 0, previous_hash, Vec::new());
+        let mut block = Block::new(1, 0, previous_hash, Vec::new());
+        block.difficulty = difficulty;
+        block.hash = block.calculate_hash();
 
         assert!(block.hash.leading_zeros() < difficulty);
 
@@ -308,9 +2650,23 @@ mod tests {
         assert_err(result, BlockchainError::InvalidDifficulty);
     }
 
+    #[test]
+    fn should_not_let_adding_block_with_unexpected_difficulty() {
+        let blockchain = create_blockchain();
+
+        // a harder proof of work than required is still a different consensus rule
+        let previous_hash = blockchain.get_last_block().hash;
+        let mut block = Block::new(1, 0, previous_hash, Vec::new());
+        block.difficulty = 1;
+        block.hash = block.calculate_hash();
+
+        let result = blockchain.add_block(block);
+        assert_err(result, BlockchainError::UnexpectedDifficulty);
+    }
+
     #[test]
     fn should_not_let_adding_block_with_no_coinbase() {
-        let blockchain = Blockchain::new(NO_DIFFICULTY);
+        let blockchain = create_blockchain();
 
         let previous_hash = blockchain.get_last_block().hash;
         let block = Block::new(1, 0, previous_hash, vec![]);
@@ -321,7 +2677,7 @@ mod tests {
 
     #[test]
     fn should_not_let_adding_block_with_invalid_coinbase() {
-        let blockchain = Blockchain::new(NO_DIFFICULTY);
+        let blockchain = create_blockchain();
 
         let previous_hash = blockchain.get_last_block().hash;
         let coinbase = Transaction {
@@ -338,7 +2694,7 @@ mod tests {
 
     #[test]
     fn should_not_let_add_transaction_with_insufficient_funds() {
-        let blockchain = Blockchain::new(NO_DIFFICULTY);
+        let blockchain = create_blockchain();
 
         let previous_hash = blockchain.get_last_block().hash;
         let coinbase = Transaction {
@@ -359,9 +2715,107 @@ mod tests {
         assert_balance_err(result, AccountBalanceMapError::InsufficientFunds);
     }
 
+    #[test]
+    fn should_not_let_add_transaction_with_zero_amount() {
+        let blockchain = create_blockchain();
+
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person2(),
+            amount: BLOCK_SUBSIDY,
+        };
+
+        let invalid_transaction = Transaction {
+            sender: person2(),
+            recipient: person1(),
+            amount: 0,
+        };
+
+        let block = create_next_block(&blockchain, vec![coinbase, invalid_transaction]);
+
+        let result = blockchain.add_block(block);
+        assert_err(result, BlockchainError::ZeroAmount);
+    }
+
+    #[test]
+    fn should_not_let_add_self_transfer() {
+        let blockchain = create_blockchain();
+
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person2(),
+            amount: BLOCK_SUBSIDY,
+        };
+
+        let invalid_transaction = Transaction {
+            sender: person2(),
+            recipient: person2(),
+            amount: 1,
+        };
+
+        let block = create_next_block(&blockchain, vec![coinbase, invalid_transaction]);
+
+        let result = blockchain.add_block(block);
+        assert_err(result, BlockchainError::SelfTransfer);
+    }
+
+    fn create_transfer_to_fresh_address(blockchain: &Blockchain) -> Block {
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person1(),
+            amount: BLOCK_SUBSIDY,
+        };
+
+        let transaction = Transaction {
+            sender: person1(),
+            recipient: person3(),
+            amount: 10,
+        };
+
+        create_next_block(blockchain, vec![coinbase, transaction])
+    }
+
+    #[test]
+    fn should_let_send_to_fresh_address_by_default() {
+        let blockchain = create_blockchain();
+        assert!(blockchain.accepts_recipient(&person3()).unwrap());
+
+        let block = create_transfer_to_fresh_address(&blockchain);
+        blockchain.add_block(block).unwrap();
+
+        assert_eq!(blockchain.get_balance(&person3()).unwrap(), 10);
+    }
+
+    #[test]
+    fn should_not_let_send_to_fresh_address_when_recipients_must_exist() {
+        let blockchain = create_blockchain().with_require_existing_recipient(true);
+        assert!(!blockchain.accepts_recipient(&person3()).unwrap());
+
+        let block = create_transfer_to_fresh_address(&blockchain);
+        let result = blockchain.add_block(block);
+        assert_err(result, BlockchainError::UnknownRecipient);
+
+        // the coinbase creates the account, which can then receive transfers
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person3(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let block = create_next_block(&blockchain, vec![coinbase]);
+        blockchain.add_block(block).unwrap();
+        assert!(blockchain.accepts_recipient(&person3()).unwrap());
+
+        let block = create_transfer_to_fresh_address(&blockchain);
+        blockchain.add_block(block).unwrap();
+        assert_eq!(
+            blockchain.get_balance(&person3()).unwrap(),
+            BLOCK_SUBSIDY + 10
+        );
+    }
+
     #[test]
     fn should_not_let_add_transaction_with_non_existent_sender() {
-        let blockchain = Blockchain::new(NO_DIFFICULTY);
+        let blockchain = create_blockchain();
 
         let previous_hash = blockchain.get_last_block().hash;
 
@@ -382,4 +2836,72 @@ mod tests {
         let result = blockchain.add_block(block.clone());
         assert_balance_err(result, AccountBalanceMapError::SenderAccountDoesNotExist);
     }
+
+    #[test]
+    fn should_enforce_spending_limit_per_window() {
+        let mut spending_policy = SpendingPolicy::default();
+        let rule = SpendingRule {
+            max_amount: 10,
+            window: 2,
+        };
+        spending_policy.add_rule(person2(), rule);
+
+        let blockchain = create_blockchain().with_spending_policy(spending_policy);
+
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person2(),
+            amount: BLOCK_SUBSIDY,
+        };
+
+        let transaction = Transaction {
+            sender: person2(),
+            recipient: person1(),
+            amount: 6,
+        };
+
+        // first spending fits within the window limit
+        let block = create_next_block(&blockchain, vec![coinbase.clone(), transaction.clone()]);
+        let result = blockchain.add_block(block);
+        assert!(result.is_ok());
+
+        // second spending in the same window goes over the limit
+        let block = create_next_block(&blockchain, vec![coinbase, transaction]);
+        let result = blockchain.add_block(block);
+        assert_err(result, BlockchainError::SpendingLimitExceeded);
+    }
+
+    #[test]
+    fn should_get_spending_allowance_of_the_next_block() {
+        let mut spending_policy = SpendingPolicy::default();
+        let rule = SpendingRule {
+            max_amount: 10,
+            window: 2,
+        };
+        spending_policy.add_rule(person2(), rule);
+
+        let blockchain = create_blockchain().with_spending_policy(spending_policy);
+        assert_eq!(blockchain.get_spending_allowance(&person1()), None);
+        assert_eq!(blockchain.get_spending_allowance(&person2()), Some(10));
+
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: person2(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let transaction = Transaction {
+            sender: person2(),
+            recipient: person1(),
+            amount: 6,
+        };
+
+        let block = create_next_block(&blockchain, vec![coinbase.clone(), transaction]);
+        blockchain.add_block(block).unwrap();
+        assert_eq!(blockchain.get_spending_allowance(&person2()), Some(4));
+
+        // the spending leaves the window once the next block is added
+        let block = create_next_block(&blockchain, vec![coinbase]);
+        blockchain.add_block(block).unwrap();
+        assert_eq!(blockchain.get_spending_allowance(&person2()), Some(10));
+    }
 }
@@ -0,0 +1,131 @@
+use super::block::BlockHash;
+
+// Difficulties are the number of leading zero bits of a valid hash, while external miners
+// expect the target in the compact "bits" form: the size of the target in bytes in the first
+// byte, followed by its three most significant bytes
+
+const MANTISSA_MASK: u32 = 0x007f_ffff;
+const SIGN_BIT: u32 = 0x0080_0000;
+
+// Hashes below the target have at least `difficulty` leading zero bits
+pub fn difficulty_to_target(difficulty: u32) -> BlockHash {
+    BlockHash::MAX >> difficulty
+}
+
+// Only the three most significant bytes are kept, the rest of the target is rounded down
+pub fn target_to_compact(target: BlockHash) -> u32 {
+    let mut size = (target.bits() as u32).div_ceil(8);
+
+    let mut mantissa = match size {
+        0..=3 => target.low_u32() << (8 * (3 - size)),
+        _ => (target >> (8 * (size - 3) as usize)).low_u32(),
+    };
+
+    // the mantissa is signed, so a set high bit moves it to the next byte
+    if mantissa & SIGN_BIT != 0 {
+        mantissa >>= 8;
+        size += 1;
+    }
+
+    (size << 24) | mantissa
+}
+
+// Negative targets can't be met and are read as zero, the ones overflowing 256 bits as the maximum
+pub fn compact_to_target(compact: u32) -> BlockHash {
+    let size = compact >> 24;
+    let mantissa = compact & MANTISSA_MASK;
+
+    if mantissa == 0 || compact & SIGN_BIT != 0 {
+        return BlockHash::zero();
+    }
+
+    match size {
+        0..=3 => BlockHash::from(mantissa >> (8 * (3 - size))),
+        _ => {
+            let shift = 8 * (size - 3) as usize;
+            let mantissa = BlockHash::from(mantissa);
+
+            match shift + mantissa.bits() > 256 {
+                true => BlockHash::MAX,
+                false => mantissa << shift,
+            }
+        }
+    }
+}
+
+// Each extra zero bit doubles the hashes needed to find a block, so the difficulty is the
+// number of times the hashes computed in the block time can be halved
+pub fn difficulty_for_block_time(hashrate: f64, target_block_time_ms: u64) -> u32 {
+    let expected_hashes = hashrate * target_block_time_ms as f64 / 1000.0;
+
+    match expected_hashes > 1.0 {
+        true => (expected_hashes.log2().floor() as u32).min(BlockHash::MAX.bits() as u32),
+        false => 0,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MAX_DIFFICULTY: u32 = 256;
+
+    #[test]
+    fn difficulty_to_target_valid_difficulty() {
+        for difficulty in 0..MAX_DIFFICULTY {
+            let target = difficulty_to_target(difficulty);
+            assert_eq!(target.leading_zeros(), difficulty);
+        }
+    }
+
+    #[test]
+    fn difficulty_to_target_overflowing_difficulty() {
+        let target = difficulty_to_target(MAX_DIFFICULTY + 1);
+        assert_eq!(target.leading_zeros(), MAX_DIFFICULTY);
+    }
+
+    #[test]
+    fn difficulty_for_block_time_follows_hashrate() {
+        // a million hashes per second find a 1 in 2^23 hash in about 8 seconds
+        assert_eq!(difficulty_for_block_time(1_000_000.0, 10_000), 23);
+        assert_eq!(difficulty_for_block_time(1_000_000.0, 20_000), 24);
+
+        let fast = difficulty_for_block_time(2_000_000.0, 10_000);
+        let throttled = difficulty_for_block_time(20_000.0, 10_000);
+        assert!(fast > throttled);
+
+        assert_eq!(difficulty_for_block_time(0.0, 10_000), 0);
+        assert_eq!(difficulty_for_block_time(f64::MAX, u64::MAX), 256);
+    }
+
+    #[test]
+    fn compact_round_trips_difficulties() {
+        for difficulty in [0, 1, 7, 8, 10, 20, 32, 100, 200, 240, 255] {
+            let target = difficulty_to_target(difficulty);
+            let compact_target = compact_to_target(target_to_compact(target));
+
+            assert!(compact_target <= target);
+            assert_eq!(compact_target.leading_zeros(), difficulty);
+        }
+    }
+
+    #[test]
+    fn compact_round_trips_normalized_bits() {
+        for compact in [0x1d00ffff, 0x1b0404cb, 0x2100ffff, 0x03123456, 0x05009234] {
+            assert_eq!(target_to_compact(compact_to_target(compact)), compact);
+        }
+    }
+
+    #[test]
+    fn compact_to_target_known_values() {
+        // the compact target of the first Bitcoin block
+        assert_eq!(
+            compact_to_target(0x1d00ffff),
+            BlockHash::from(0xffff) << (8 * (0x1d - 3))
+        );
+        assert_eq!(compact_to_target(0x01120000), BlockHash::from(0x12));
+        assert_eq!(compact_to_target(0x04923456), BlockHash::zero());
+        assert_eq!(compact_to_target(0xff123456), BlockHash::MAX);
+        assert_eq!(target_to_compact(BlockHash::zero()), 0);
+    }
+}
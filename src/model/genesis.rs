@@ -0,0 +1,113 @@
+use std::{collections::BTreeMap, fs};
+
+use anyhow::Result;
+use serde::Deserialize;
+
+use super::{
+    account_balance_map::{AccountBalanceMap, Amount},
+    address::Address,
+    block::{Block, BlockHash},
+    transaction::Transaction,
+};
+
+// Parameters of the first block, so test networks can start with funded accounts
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct GenesisConfig {
+    pub timestamp: Option<i64>,
+    #[serde(default)]
+    pub balances: BTreeMap<Address, Amount>,
+}
+
+impl GenesisConfig {
+    pub fn from_file(path: &str) -> Result<GenesisConfig> {
+        let contents = fs::read_to_string(path)?;
+
+        Ok(serde_json::from_str(&contents)?)
+    }
+
+    // Initial balances are recorded as transactions from the default address, sorted by recipient
+    // so the same config always yields the same genesis hash
+    pub fn create_block(&self) -> Block {
+        let transactions = self
+            .balances
+            .iter()
+            .map(|(address, amount)| Transaction {
+                sender: Address::default(),
+                recipient: address.clone(),
+                amount: *amount,
+            })
+            .collect();
+
+        let mut block = Block::new(0, 0, BlockHash::default(), transactions);
+
+        block.timestamp = self.timestamp.unwrap_or(0);
+        block.hash = block.calculate_hash();
+
+        block
+    }
+
+    pub fn create_account_balances(&self) -> AccountBalanceMap {
+        let mut account_balances = AccountBalanceMap::default();
+
+        // each address appears once, so the allocations are set rather than accumulated
+        for (address, amount) in self.balances.iter() {
+            account_balances.update_balance(address, *amount);
+        }
+
+        account_balances
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::address::test_person_util::{person1, person2};
+
+    use super::*;
+
+    fn create_genesis_config(balances: Vec<(Address, Amount)>) -> GenesisConfig {
+        GenesisConfig {
+            timestamp: Some(1_700_000_000_000),
+            balances: balances.into_iter().collect(),
+        }
+    }
+
+    #[test]
+    fn different_allocations_yield_different_genesis_hashes() {
+        let genesis = create_genesis_config(vec![(person1(), 100)]).create_block();
+        let other_genesis = create_genesis_config(vec![(person2(), 100)]).create_block();
+        let same_genesis = create_genesis_config(vec![(person1(), 100)]).create_block();
+
+        assert_ne!(genesis.hash, other_genesis.hash);
+        assert_eq!(genesis.hash, same_genesis.hash);
+    }
+
+    #[test]
+    fn default_genesis_is_empty() {
+        let genesis = GenesisConfig::default().create_block();
+
+        assert_eq!(genesis.timestamp, 0);
+        assert!(genesis.transactions.is_empty());
+    }
+
+    #[test]
+    fn parse_genesis_config() {
+        let json = format!(
+            r#"{{"timestamp": 42, "balances": {{"{}": 500, "{}": 20}}}}"#,
+            person2(),
+            person1()
+        );
+        let genesis_config: GenesisConfig = serde_json::from_str(&json).unwrap();
+
+        assert_eq!(genesis_config.timestamp, Some(42));
+
+        let account_balances = genesis_config.create_account_balances();
+        assert_eq!(
+            account_balances.get_receipient_balance(&person1()).unwrap(),
+            20
+        );
+        assert_eq!(
+            account_balances.get_receipient_balance(&person2()).unwrap(),
+            500
+        );
+    }
+}
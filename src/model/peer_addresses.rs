@@ -0,0 +1,130 @@
+use std::sync::{Arc, Mutex};
+
+use isahc::http::Uri;
+use thiserror::Error;
+
+use crate::util::sync::lock_recover;
+
+type SyncedAddressVec = Arc<Mutex<Vec<String>>>;
+
+#[derive(Error, PartialEq, Debug)]
+pub enum PeerAddressError {
+    #[error("Peer address must be an http or https url")]
+    InvalidAddress,
+
+    #[error("Peer is already known")]
+    DuplicatePeer,
+}
+
+// Addresses of the peers to synchronize with, which can grow while the node is running
+#[derive(Debug, Default, Clone)]
+pub struct PeerAddresses {
+    addresses: SyncedAddressVec,
+}
+
+impl PeerAddresses {
+    pub fn new(addresses: Vec<String>) -> PeerAddresses {
+        let addresses = addresses
+            .iter()
+            .map(|address| PeerAddresses::normalize(address))
+            .collect();
+
+        PeerAddresses {
+            addresses: Arc::new(Mutex::new(addresses)),
+        }
+    }
+
+    // Addresses can include the base path of the peer api, like "http://host/node1", and
+    // a trailing slash would produce urls like "http://host/node1//blocks"
+    fn normalize(address: &str) -> String {
+        address.trim_end_matches('/').to_string()
+    }
+
+    fn validate(address: &str) -> Result<(), PeerAddressError> {
+        let uri = address
+            .parse::<Uri>()
+            .map_err(|_| PeerAddressError::InvalidAddress)?;
+
+        let has_http_scheme = matches!(uri.scheme_str(), Some("http") | Some("https"));
+        if !has_http_scheme || uri.host().is_none() {
+            return Err(PeerAddressError::InvalidAddress);
+        }
+
+        Ok(())
+    }
+
+    pub fn add(&self, address: &str) -> Result<(), PeerAddressError> {
+        PeerAddresses::validate(address)?;
+
+        let address = PeerAddresses::normalize(address);
+        let mut addresses = lock_recover(&self.addresses);
+
+        if addresses.contains(&address) {
+            return Err(PeerAddressError::DuplicatePeer);
+        }
+
+        addresses.push(address);
+
+        Ok(())
+    }
+
+    pub fn get_all(&self) -> Vec<String> {
+        lock_recover(&self.addresses).clone()
+    }
+
+    pub fn len(&self) -> usize {
+        lock_recover(&self.addresses).len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_add_valid_address() {
+        let peer_addresses = PeerAddresses::new(vec!["http://localhost:8000".to_string()]);
+
+        peer_addresses.add("https://example.com:8001/").unwrap();
+
+        let expected = vec!["http://localhost:8000", "https://example.com:8001"];
+        assert_eq!(peer_addresses.get_all(), expected);
+    }
+
+    #[test]
+    fn should_keep_base_path_of_addresses() {
+        let peer_addresses = PeerAddresses::new(vec!["http://proxy/node1/".to_string()]);
+
+        peer_addresses.add("http://proxy/node2/").unwrap();
+
+        let expected = vec!["http://proxy/node1", "http://proxy/node2"];
+        assert_eq!(peer_addresses.get_all(), expected);
+    }
+
+    #[test]
+    fn should_reject_invalid_and_duplicate_addresses() {
+        let peer_addresses = PeerAddresses::new(vec!["http://localhost:8000".to_string()]);
+
+        for address in [
+            "localhost:8001",
+            "ftp://localhost:8001",
+            "http://",
+            "not a url",
+        ] {
+            assert_eq!(
+                peer_addresses.add(address),
+                Err(PeerAddressError::InvalidAddress)
+            );
+        }
+
+        assert_eq!(
+            peer_addresses.add("http://localhost:8000"),
+            Err(PeerAddressError::DuplicatePeer)
+        );
+        assert_eq!(peer_addresses.len(), 1);
+    }
+}
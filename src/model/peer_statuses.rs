@@ -0,0 +1,82 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::util::sync::lock_recover;
+
+type SyncedPeerStatusMap = Arc<Mutex<HashMap<String, PeerStatus>>>;
+
+#[derive(Debug, Default, Clone, PartialEq, Serialize)]
+pub struct PeerStatus {
+    pub reachable: bool,
+    pub last_seen: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+// Outcome of the last synchronization with each peer, shared between the peer system and the api
+#[derive(Debug, Default, Clone)]
+pub struct PeerStatuses {
+    statuses: SyncedPeerStatusMap,
+}
+
+impl PeerStatuses {
+    pub fn record_success(&self, address: &str) {
+        let mut statuses = lock_recover(&self.statuses);
+        let status = statuses.entry(address.to_string()).or_default();
+
+        status.reachable = true;
+        status.last_seen = Some(Utc::now().timestamp_millis());
+        status.last_error = None;
+    }
+
+    // The last successful sync is kept, so it shows for how long the peer has been unreachable
+    pub fn record_failure(&self, address: &str, error: &str) {
+        let mut statuses = lock_recover(&self.statuses);
+        let status = statuses.entry(address.to_string()).or_default();
+
+        status.reachable = false;
+        status.last_error = Some(error.to_string());
+    }
+
+    // Peers that were never synchronized are reported as unreachable
+    pub fn get(&self, address: &str) -> PeerStatus {
+        let statuses = lock_recover(&self.statuses);
+
+        statuses.get(address).cloned().unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const ADDRESS: &str = "http://localhost:8001";
+
+    #[test]
+    fn should_report_unknown_peer_as_unreachable() {
+        let status = PeerStatuses::default().get(ADDRESS);
+
+        assert!(!status.reachable);
+        assert_eq!(status.last_seen, None);
+    }
+
+    #[test]
+    fn should_keep_last_seen_after_failure() {
+        let peer_statuses = PeerStatuses::default();
+
+        peer_statuses.record_success(ADDRESS);
+        let last_seen = peer_statuses.get(ADDRESS).last_seen;
+        assert!(last_seen.is_some());
+
+        peer_statuses.record_failure(ADDRESS, "Peer is unreachable");
+
+        let status = peer_statuses.get(ADDRESS);
+        assert!(!status.reachable);
+        assert_eq!(status.last_seen, last_seen);
+        assert_eq!(status.last_error, Some("Peer is unreachable".to_string()));
+    }
+}
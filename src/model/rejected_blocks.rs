@@ -0,0 +1,115 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+};
+
+use chrono::Utc;
+use serde::Serialize;
+
+use crate::util::sync::lock_recover;
+
+use super::block::Block;
+
+pub type RejectedBlockVec = Vec<RejectedBlock>;
+
+type SyncedRejectedBlockDeque = Arc<Mutex<VecDeque<RejectedBlock>>>;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct RejectedBlock {
+    pub block: Block,
+    pub reason: String,
+    pub source: String,
+    pub rejected_at: i64,
+}
+
+// Keeps the most recently rejected blocks, evicting the oldest ones once full
+#[derive(Debug, Default, Clone)]
+pub struct RejectedBlocks {
+    capacity: usize,
+    rejected_blocks: SyncedRejectedBlockDeque,
+}
+
+impl RejectedBlocks {
+    // A capacity of 0 disables the recording of rejected blocks
+    pub fn new(capacity: usize) -> RejectedBlocks {
+        RejectedBlocks {
+            capacity,
+            rejected_blocks: SyncedRejectedBlockDeque::default(),
+        }
+    }
+
+    pub fn record(&self, block: &Block, reason: &str, source: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut rejected_blocks = lock_recover(&self.rejected_blocks);
+
+        if rejected_blocks.len() == self.capacity {
+            rejected_blocks.pop_front();
+        }
+
+        rejected_blocks.push_back(RejectedBlock {
+            block: block.clone(),
+            reason: reason.to_string(),
+            source: source.to_string(),
+            rejected_at: Utc::now().timestamp_millis(),
+        });
+    }
+
+    pub fn get_all(&self) -> RejectedBlockVec {
+        let rejected_blocks = lock_recover(&self.rejected_blocks);
+
+        rejected_blocks.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::model::BlockHash;
+
+    use super::*;
+
+    fn create_block(index: u64) -> Block {
+        Block::new(index, 0, BlockHash::default(), vec![])
+    }
+
+    #[test]
+    fn should_record_reason_and_source() {
+        let rejected_blocks = RejectedBlocks::new(10);
+        let block = create_block(1);
+
+        rejected_blocks.record(&block, "Invalid index", "http://localhost:8001");
+
+        let all = rejected_blocks.get_all();
+        assert_eq!(all.len(), 1);
+        assert_eq!(all[0].block.hash, block.hash);
+        assert_eq!(all[0].reason, "Invalid index");
+        assert_eq!(all[0].source, "http://localhost:8001");
+    }
+
+    #[test]
+    fn should_evict_oldest_when_full() {
+        let rejected_blocks = RejectedBlocks::new(2);
+
+        for index in 1..=3 {
+            rejected_blocks.record(&create_block(index), "Invalid hash", "peer");
+        }
+
+        let indexes: Vec<u64> = rejected_blocks
+            .get_all()
+            .iter()
+            .map(|rejected| rejected.block.index)
+            .collect();
+        assert_eq!(indexes, vec![2, 3]);
+    }
+
+    #[test]
+    fn should_not_record_when_disabled() {
+        let rejected_blocks = RejectedBlocks::new(0);
+
+        rejected_blocks.record(&create_block(1), "Invalid hash", "peer");
+
+        assert!(rejected_blocks.get_all().is_empty());
+    }
+}
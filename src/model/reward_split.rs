@@ -0,0 +1,128 @@
+use std::str::FromStr;
+
+use thiserror::Error;
+
+use super::{account_balance_map::Amount, address::Address};
+
+#[derive(Error, PartialEq, Debug)]
+pub enum RewardSplitError {
+    #[error("Invalid reward share `{0}`")]
+    InvalidShare(String),
+
+    #[error("Reward shares add up to {0}% instead of 100%")]
+    InvalidTotal(u64),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RewardShare {
+    pub recipient: Address,
+    pub percent: u8,
+}
+
+// Recipients of the block reward, in the order of the coinbase outputs. Empty when the whole
+// reward goes to a single output
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RewardSplit(Vec<RewardShare>);
+
+impl RewardSplit {
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    // Integer division leaves up to one unit per share, which goes to the first output,
+    // so the outputs always add up to the reward
+    pub fn split(&self, reward: Amount) -> Vec<(Address, Amount)> {
+        let mut outputs: Vec<(Address, Amount)> = self
+            .0
+            .iter()
+            .map(|share| {
+                let amount = (reward as u128 * share.percent as u128 / 100) as Amount;
+                (share.recipient.clone(), amount)
+            })
+            .collect();
+
+        let assigned: Amount = outputs.iter().map(|(_, amount)| amount).sum();
+        if let Some((_, amount)) = outputs.first_mut() {
+            *amount += reward - assigned;
+        }
+
+        outputs
+    }
+}
+
+// Shares are given as comma-separated "address:percent" entries, which must add up to 100
+impl FromStr for RewardSplit {
+    type Err = RewardSplitError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let mut shares = Vec::new();
+
+        for entry in string.split_terminator(',') {
+            let invalid_share = || RewardSplitError::InvalidShare(entry.to_string());
+            let parts: Vec<&str> = entry.trim().split(':').collect();
+
+            if parts.len() != 2 {
+                return Err(invalid_share());
+            }
+
+            let recipient = Address::from_str(parts[0]).map_err(|_| invalid_share())?;
+            let percent = parts[1].parse::<u8>().map_err(|_| invalid_share())?;
+            if percent == 0 {
+                return Err(invalid_share());
+            }
+
+            shares.push(RewardShare { recipient, percent });
+        }
+
+        let total: u64 = shares.iter().map(|share| share.percent as u64).sum();
+        if !shares.is_empty() && total != 100 {
+            return Err(RewardSplitError::InvalidTotal(total));
+        }
+
+        Ok(RewardSplit(shares))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::model::address::test_person_util::{person1, person2, person3};
+
+    use super::*;
+
+    #[test]
+    fn parse_valid_split() {
+        let shares = format!("{}:90,{}:10", person1(), person2());
+        let split = RewardSplit::from_str(&shares).unwrap();
+
+        assert_eq!(split.split(100), vec![(person1(), 90), (person2(), 10)]);
+    }
+
+    #[test]
+    fn assign_remainder_to_first_share() {
+        let shares = format!("{}:34,{}:33,{}:33", person1(), person2(), person3());
+        let split = RewardSplit::from_str(&shares).unwrap();
+
+        let outputs = split.split(10);
+        assert_eq!(
+            outputs,
+            vec![(person1(), 4), (person2(), 3), (person3(), 3)]
+        );
+    }
+
+    #[test]
+    fn reject_invalid_split() {
+        let shares = format!("{}:90,{}:20", person1(), person2());
+        let err = RewardSplit::from_str(&shares).unwrap_err();
+        assert_eq!(err, RewardSplitError::InvalidTotal(110));
+
+        let shares = format!("{}:100:1", person1());
+        let err = RewardSplit::from_str(&shares).unwrap_err();
+        assert_eq!(err, RewardSplitError::InvalidShare(shares));
+    }
+}
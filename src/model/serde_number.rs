@@ -0,0 +1,83 @@
+use std::{fmt::Display, str::FromStr};
+
+use serde::{de, Deserialize, Deserializer};
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NumberOrString<T> {
+    Number(T),
+    String(String),
+}
+
+// Accepts numbers both as JSON numbers and as strings, which clients use to avoid precision loss
+pub fn deserialize<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr,
+    T::Err: Display,
+{
+    match NumberOrString::<T>::deserialize(deserializer)? {
+        NumberOrString::Number(number) => Ok(number),
+        NumberOrString::String(string) => string.parse::<T>().map_err(de::Error::custom),
+    }
+}
+
+// Like deserialize, for optional fields, which also need #[serde(default)] to be left out
+pub fn deserialize_option<'de, D, T>(deserializer: D) -> Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: Deserialize<'de> + FromStr,
+    T::Err: Display,
+{
+    match Option::<NumberOrString<T>>::deserialize(deserializer)? {
+        None => Ok(None),
+        Some(NumberOrString::Number(number)) => Ok(Some(number)),
+        Some(NumberOrString::String(string)) => {
+            string.parse::<T>().map(Some).map_err(de::Error::custom)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde::Deserialize;
+
+    #[derive(Deserialize)]
+    struct Amount {
+        #[serde(deserialize_with = "super::deserialize")]
+        value: u64,
+    }
+
+    #[test]
+    fn parse_number_and_string() {
+        let amount: Amount = serde_json::from_str(r#"{"value": 42}"#).unwrap();
+        assert_eq!(amount.value, 42);
+
+        let amount: Amount = serde_json::from_str(r#"{"value": "18446744073709551615"}"#).unwrap();
+        assert_eq!(amount.value, u64::MAX);
+    }
+
+    #[derive(Deserialize)]
+    struct OptionalAmount {
+        #[serde(default, deserialize_with = "super::deserialize_option")]
+        value: Option<u64>,
+    }
+
+    #[test]
+    fn parse_optional_number_and_string() {
+        let amount: OptionalAmount = serde_json::from_str(r#"{"value": 42}"#).unwrap();
+        assert_eq!(amount.value, Some(42));
+
+        let amount: OptionalAmount = serde_json::from_str(r#"{"value": "42"}"#).unwrap();
+        assert_eq!(amount.value, Some(42));
+
+        let amount: OptionalAmount = serde_json::from_str(r#"{}"#).unwrap();
+        assert_eq!(amount.value, None);
+    }
+
+    #[test]
+    fn reject_invalid_string() {
+        let result = serde_json::from_str::<Amount>(r#"{"value": "abc"}"#);
+        assert!(result.is_err());
+    }
+}
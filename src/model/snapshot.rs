@@ -0,0 +1,18 @@
+use serde::{Deserialize, Serialize};
+
+use super::{
+    account_balance_map::AccountBalanceMap,
+    block::{Block, BlockHash},
+};
+
+// Balances at a given block, so new nodes can skip replaying the blocks up to it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChainSnapshot {
+    // identifies the network the snapshot was taken on
+    pub genesis_hash: BlockHash,
+    pub tip_index: u64,
+    pub tip_hash: BlockHash,
+    pub account_balances: AccountBalanceMap,
+    // the checkpoint the new node starts its chain from, without the blocks before it
+    pub tip_block: Block,
+}
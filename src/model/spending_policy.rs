@@ -0,0 +1,109 @@
+use std::{collections::HashMap, str::FromStr};
+
+use thiserror::Error;
+
+use super::{account_balance_map::Amount, address::Address, block::Block};
+
+#[derive(Error, PartialEq, Debug)]
+pub enum SpendingPolicyError {
+    #[error("Invalid spending rule `{0}`")]
+    InvalidRule(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpendingRule {
+    pub max_amount: Amount,
+    pub window: u64,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct SpendingPolicy(HashMap<Address, SpendingRule>);
+
+impl SpendingPolicy {
+    pub fn add_rule(&mut self, address: Address, rule: SpendingRule) {
+        self.0.insert(address, rule);
+    }
+
+    pub fn get_rule(&self, address: &Address) -> Option<&SpendingRule> {
+        self.0.get(address)
+    }
+
+    // Number of previous blocks needed to evaluate every rule
+    pub fn max_window(&self) -> usize {
+        self.0
+            .values()
+            .map(|rule| rule.window.saturating_sub(1) as usize)
+            .max()
+            .unwrap_or(0)
+    }
+
+    // Amount sent by the address in the blocks that share a window with the next block
+    pub fn spent_in_window(address: &Address, rule: &SpendingRule, blocks: &[Block]) -> Amount {
+        let previous_blocks = rule.window.saturating_sub(1) as usize;
+        let first = blocks.len().saturating_sub(previous_blocks);
+
+        blocks[first..]
+            .iter()
+            .flat_map(|block| block.transactions.iter().skip(1))
+            .filter(|transaction| &transaction.sender == address)
+            .fold(0, |spent, transaction| {
+                spent.saturating_add(transaction.amount)
+            })
+    }
+}
+
+// Rules are given as comma-separated "address:max_amount:window" entries
+impl FromStr for SpendingPolicy {
+    type Err = SpendingPolicyError;
+
+    fn from_str(string: &str) -> Result<Self, Self::Err> {
+        let mut policy = SpendingPolicy::default();
+
+        for entry in string.split_terminator(',') {
+            let invalid_rule = || SpendingPolicyError::InvalidRule(entry.to_string());
+            let parts: Vec<&str> = entry.trim().split(':').collect();
+
+            if parts.len() != 3 {
+                return Err(invalid_rule());
+            }
+
+            let address = Address::from_str(parts[0]).map_err(|_| invalid_rule())?;
+            let max_amount = parts[1].parse::<Amount>().map_err(|_| invalid_rule())?;
+            let window = parts[2].parse::<u64>().map_err(|_| invalid_rule())?;
+
+            policy.add_rule(address, SpendingRule { max_amount, window });
+        }
+
+        Ok(policy)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use crate::model::address::test_person_util::{person1, person2};
+
+    use super::*;
+
+    #[test]
+    fn parse_valid_policy() {
+        let rules = format!("{}:10:5,{}:20:1", person1(), person2());
+        let policy = SpendingPolicy::from_str(&rules).unwrap();
+
+        let rule = policy.get_rule(&person1()).unwrap();
+        assert_eq!(rule.max_amount, 10);
+        assert_eq!(rule.window, 5);
+
+        let rule = policy.get_rule(&person2()).unwrap();
+        assert_eq!(rule.max_amount, 20);
+        assert_eq!(rule.window, 1);
+    }
+
+    #[test]
+    fn reject_invalid_policy() {
+        let rules = format!("{}:10", person1());
+        let err = SpendingPolicy::from_str(&rules).unwrap_err();
+        assert_eq!(err, SpendingPolicyError::InvalidRule(rules));
+    }
+}
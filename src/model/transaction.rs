@@ -1,10 +1,21 @@
+use ethereum_types::U256;
 use serde::{Deserialize, Serialize};
 
-use super::address::Address;
+use super::{address::Address, block::sha256, serde_number};
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+pub type TransactionHash = U256;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Transaction {
     pub sender: Address,
     pub recipient: Address,
+    #[serde(deserialize_with = "serde_number::deserialize")]
     pub amount: u64,
 }
+
+impl Transaction {
+    // Transactions with the same sender, recipient and amount share the same hash
+    pub fn hash(&self) -> TransactionHash {
+        sha256(&serde_json::to_vec(self).unwrap())
+    }
+}
@@ -1,37 +1,274 @@
-use std::sync::{Arc, Mutex};
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
 use log::info;
+use thiserror::Error;
 
-use super::transaction::Transaction;
+use crate::util::sync::lock_recover;
+
+use super::{account_balance_map::Amount, address::Address, transaction::Transaction};
 
 pub type TransactionVec = Vec<Transaction>;
 
-type SyncedTransactionVec = Arc<Mutex<TransactionVec>>;
+type SyncedPooledTransactions = Arc<Mutex<PooledTransactions>>;
+
+#[derive(Error, PartialEq, Debug)]
+pub enum PoolError {
+    #[error("Transaction amount must be greater than zero")]
+    ZeroAmount,
+
+    #[error("Sender and recipient must be different")]
+    SelfTransfer,
+
+    #[error("Insufficient funds once the pooled transactions are spent")]
+    InsufficientPendingFunds,
+
+    #[error("Spending limit exceeded once the pooled transactions are spent")]
+    SpendingLimitExceeded,
+}
 
 #[derive(Debug, Clone)]
+struct PooledTransaction {
+    transaction: Transaction,
+    added_at: Instant,
+}
+
+// Transactions are only appended under the lock, so the list keeps the order the pool received
+// them in, which blocks follow: the same pool always yields the same block, and transfers
+// spending the funds of earlier ones come after them
+#[derive(Debug, Default)]
+struct PooledTransactions {
+    list: Vec<PooledTransaction>,
+    // popped transactions, which still spend the funds of their sender until they are released
+    // once mined or dropped, or requeued
+    in_flight: Vec<PooledTransaction>,
+}
+
+impl PooledTransactions {
+    fn take_in_flight(&mut self, transaction: &Transaction) -> Option<PooledTransaction> {
+        let position = self
+            .in_flight
+            .iter()
+            .position(|pooled| &pooled.transaction == transaction)?;
+
+        Some(self.in_flight.remove(position))
+    }
+
+    // Amount the sender is already spending in the pooled and popped transactions
+    fn pending_outflow(&self, sender: &Address) -> Amount {
+        self.list
+            .iter()
+            .chain(self.in_flight.iter())
+            .filter(|pooled| &pooled.transaction.sender == sender)
+            .fold(0, |outflow, pooled| {
+                outflow.saturating_add(pooled.transaction.amount)
+            })
+    }
+}
+
+#[derive(Debug, Default, Clone)]
 pub struct TransactionPool {
-    transaction: SyncedTransactionVec,
+    transaction: SyncedPooledTransactions,
+    // transactions not mined within this time are dropped, zero keeps them forever
+    ttl: Duration,
+    // senders cannot queue more than they can spend, counting their pooled transactions
+    check_pending_funds: bool,
 }
 
 impl TransactionPool {
     pub fn new() -> TransactionPool {
         TransactionPool {
-            transaction: SyncedTransactionVec::default(),
+            transaction: SyncedPooledTransactions::default(),
+            ttl: Duration::ZERO,
+            check_pending_funds: false,
         }
     }
 
-    pub fn add_transaction(&self, transaction: Transaction) {
-        let mut transactions = self.transaction.lock().unwrap();
-        transactions.push(transaction);
+    pub fn with_ttl(mut self, ttl: Duration) -> TransactionPool {
+        self.ttl = ttl;
+
+        self
+    }
+
+    pub fn with_pending_funds_check(mut self, check_pending_funds: bool) -> TransactionPool {
+        self.check_pending_funds = check_pending_funds;
+
+        self
+    }
+
+    // Expired transactions are removed whenever the pool is read
+    fn evict_expired(&self, transactions: &mut Vec<PooledTransaction>, now: Instant) {
+        if self.ttl.is_zero() {
+            return;
+        }
+
+        let count = transactions.len();
+        transactions.retain(|pooled| now.saturating_duration_since(pooled.added_at) < self.ttl);
+
+        let expired = count - transactions.len();
+        if expired > 0 {
+            info!("Dropped {} expired transactions from the pool", expired);
+        }
+    }
+
+    fn validate_transaction(transaction: &Transaction) -> Result<(), PoolError> {
+        if transaction.amount == 0 {
+            return Err(PoolError::ZeroAmount);
+        }
+
+        // the coinbase sender is the default address, which is exempt from this rule
+        let is_coinbase = transaction.sender == Address::default();
+        if !is_coinbase && transaction.sender == transaction.recipient {
+            return Err(PoolError::SelfTransfer);
+        }
+
+        Ok(())
+    }
+
+    pub fn add_transaction(&self, transaction: Transaction) -> Result<(), PoolError> {
+        self.add_transaction_spending(transaction, Amount::MAX, None)
+    }
+
+    // Adds a transaction whose sender can spend up to the given confirmed balance, and send up to
+    // the allowance of its spending rule in the next block, if it has one. The pooled transactions
+    // all go in that block, so the ones of the sender are taken from the allowance. With the
+    // pending funds check, they are taken from the balance as well
+    pub fn add_transaction_spending(
+        &self,
+        transaction: Transaction,
+        spendable: Amount,
+        allowance: Option<Amount>,
+    ) -> Result<(), PoolError> {
+        self.add_transaction_spending_at(transaction, spendable, allowance, Instant::now())
+    }
+
+    fn add_transaction_spending_at(
+        &self,
+        transaction: Transaction,
+        spendable: Amount,
+        allowance: Option<Amount>,
+        now: Instant,
+    ) -> Result<(), PoolError> {
+        TransactionPool::validate_transaction(&transaction)?;
+
+        let mut transactions = lock_recover(&self.transaction);
+
+        if self.check_pending_funds || allowance.is_some() {
+            // expired transactions will never be mined, so they no longer spend anything
+            self.evict_expired(&mut transactions.list, now);
+            let outflow = transactions
+                .pending_outflow(&transaction.sender)
+                .saturating_add(transaction.amount);
+
+            if allowance.is_some_and(|allowance| outflow > allowance) {
+                return Err(PoolError::SpendingLimitExceeded);
+            }
+
+            if self.check_pending_funds && outflow > spendable {
+                return Err(PoolError::InsufficientPendingFunds);
+            }
+        }
+
+        transactions.list.push(PooledTransaction {
+            transaction,
+            added_at: now,
+        });
         info!("Transaction added");
+
+        Ok(())
+    }
+
+    pub fn len(&self) -> usize {
+        self.len_at(Instant::now())
+    }
+
+    fn len_at(&self, now: Instant) -> usize {
+        let mut transactions = lock_recover(&self.transaction);
+        self.evict_expired(&mut transactions.list, now);
+
+        transactions.list.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
     }
 
+    pub fn peek(&self) -> TransactionVec {
+        self.peek_at(Instant::now())
+    }
+
+    fn peek_at(&self, now: Instant) -> TransactionVec {
+        let mut transactions = lock_recover(&self.transaction);
+        self.evict_expired(&mut transactions.list, now);
+
+        transactions
+            .list
+            .iter()
+            .map(|pooled| pooled.transaction.clone())
+            .collect()
+    }
+
+    // Empties the pool for mining. The popped transactions stay pending, so their funds cannot be
+    // queued again, until they are released or requeued
     pub fn pop(&self) -> TransactionVec {
-        let mut transactions = self.transaction.lock().unwrap();
-        let transactions_clone = transactions.clone();
-        transactions.clear();
+        self.pop_at(Instant::now())
+    }
+
+    fn pop_at(&self, now: Instant) -> TransactionVec {
+        let mut transactions = lock_recover(&self.transaction);
+        self.evict_expired(&mut transactions.list, now);
+
+        let popped: Vec<PooledTransaction> = transactions.list.drain(..).collect();
+        transactions.in_flight.extend(popped.iter().cloned());
 
-        transactions_clone
+        popped
+            .into_iter()
+            .map(|pooled| pooled.transaction)
+            .collect()
+    }
+
+    // Forgets popped transactions that were mined or dropped
+    pub fn release(&self, released: &[Transaction]) {
+        let mut transactions = lock_recover(&self.transaction);
+
+        for transaction in released {
+            transactions.take_in_flight(transaction);
+        }
+    }
+
+    // Puts popped transactions that did not make it into a block back in front of the pool, so
+    // they keep their place ahead of the ones received since. They also keep the time they were
+    // first added at, so failed mining rounds do not extend their time to live
+    pub fn requeue(&self, requeued: TransactionVec) {
+        self.requeue_at(requeued, Instant::now())
+    }
+
+    fn requeue_at(&self, requeued: TransactionVec, now: Instant) {
+        if requeued.is_empty() {
+            return;
+        }
+
+        let mut transactions = lock_recover(&self.transaction);
+
+        let count = requeued.len();
+        let requeued: Vec<PooledTransaction> = requeued
+            .into_iter()
+            .map(|transaction| {
+                transactions
+                    .take_in_flight(&transaction)
+                    .unwrap_or(PooledTransaction {
+                        transaction,
+                        added_at: now,
+                    })
+            })
+            .collect();
+        transactions.list.splice(0..0, requeued);
+        info!("Requeued {} transactions", count);
+
+        // the ones that expired while being mined are dropped right away
+        self.evict_expired(&mut transactions.list, now);
     }
 }
 
@@ -42,7 +279,12 @@ mod tests {
         transaction::Transaction,
     };
 
-    use super::TransactionPool;
+    use std::{
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use super::{PoolError, TransactionPool};
 
     fn create_mock_transaction(amount: u64) -> Transaction {
         Transaction {
@@ -60,12 +302,32 @@ mod tests {
         assert!(transactions.is_empty());
     }
 
+    #[test]
+    fn should_peek_without_draining() {
+        let transaction_pool = TransactionPool::new();
+
+        let transaction = create_mock_transaction(1);
+        transaction_pool
+            .add_transaction(transaction.clone())
+            .unwrap();
+
+        let transactions = transaction_pool.peek();
+        assert_eq!(transactions.len(), 1);
+        assert_eq!(transactions[0].amount, transaction.amount);
+        assert_eq!(transaction_pool.len(), 1);
+
+        let transactions = transaction_pool.pop();
+        assert_eq!(transactions.len(), 1);
+    }
+
     #[test]
     fn should_pop_single_value() {
         let transaction_pool = TransactionPool::new();
 
         let transaction = create_mock_transaction(1);
-        transaction_pool.add_transaction(transaction.clone());
+        transaction_pool
+            .add_transaction(transaction.clone())
+            .unwrap();
 
         let mut transactions = transaction_pool.pop();
         assert_eq!(transactions.len(), 1);
@@ -81,8 +343,12 @@ mod tests {
 
         let transaction_a = create_mock_transaction(1);
         let transaction_b = create_mock_transaction(2);
-        transaction_pool.add_transaction(transaction_a.clone());
-        transaction_pool.add_transaction(transaction_b.clone());
+        transaction_pool
+            .add_transaction(transaction_a.clone())
+            .unwrap();
+        transaction_pool
+            .add_transaction(transaction_b.clone())
+            .unwrap();
 
         let mut transactions = transaction_pool.pop();
         assert_eq!(transactions.len(), 2);
@@ -92,4 +358,240 @@ mod tests {
         transactions = transaction_pool.pop();
         assert!(transactions.is_empty());
     }
+
+    #[test]
+    fn should_keep_order_of_concurrent_additions() {
+        let transaction_pool = TransactionPool::new();
+
+        let handles: Vec<_> = (0..4)
+            .map(|thread_index| {
+                let transaction_pool = transaction_pool.clone();
+                thread::spawn(move || {
+                    for i in 1..=25 {
+                        let transaction = create_mock_transaction(thread_index * 100 + i);
+                        transaction_pool.add_transaction(transaction).unwrap();
+                    }
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .for_each(|handle| handle.join().unwrap());
+
+        assert_eq!(transaction_pool.len(), 100);
+
+        // every read of the same pool yields the same order
+        let peeked = transaction_pool.peek();
+        assert_eq!(transaction_pool.peek(), peeked);
+        assert_eq!(transaction_pool.pop(), peeked);
+
+        // transactions of each thread keep the order they were added in
+        for thread_index in 0..4 {
+            let amounts: Vec<u64> = peeked
+                .iter()
+                .map(|transaction| transaction.amount)
+                .filter(|amount| amount / 100 == thread_index)
+                .collect();
+            assert!(amounts.windows(2).all(|pair| pair[0] < pair[1]));
+        }
+    }
+
+    #[test]
+    fn should_drop_expired_transactions() {
+        let ttl = Duration::from_millis(600000);
+        let transaction_pool = TransactionPool::new().with_ttl(ttl);
+
+        transaction_pool
+            .add_transaction(create_mock_transaction(1))
+            .unwrap();
+        let now = Instant::now();
+
+        assert_eq!(transaction_pool.peek_at(now + ttl / 2).len(), 1);
+        assert_eq!(transaction_pool.len_at(now + ttl), 0);
+        assert!(transaction_pool.pop_at(now).is_empty());
+    }
+
+    #[test]
+    fn should_keep_transactions_without_ttl() {
+        let transaction_pool = TransactionPool::new();
+
+        transaction_pool
+            .add_transaction(create_mock_transaction(1))
+            .unwrap();
+        let later = Instant::now() + Duration::from_secs(365 * 24 * 60 * 60);
+
+        assert_eq!(transaction_pool.pop_at(later).len(), 1);
+    }
+
+    #[test]
+    fn should_reject_zero_amount() {
+        let transaction_pool = TransactionPool::new();
+
+        let transaction = create_mock_transaction(0);
+        let result = transaction_pool.add_transaction(transaction);
+        assert_eq!(result.unwrap_err(), PoolError::ZeroAmount);
+
+        assert!(transaction_pool.pop().is_empty());
+    }
+
+    #[test]
+    fn should_reject_transfers_overspending_together() {
+        let transaction_pool = TransactionPool::new().with_pending_funds_check(true);
+        let spendable = 100;
+
+        transaction_pool
+            .add_transaction_spending(create_mock_transaction(60), spendable, None)
+            .unwrap();
+
+        // each transfer is affordable on its own, but not after the pooled one
+        let result =
+            transaction_pool.add_transaction_spending(create_mock_transaction(60), spendable, None);
+        assert_eq!(result.unwrap_err(), PoolError::InsufficientPendingFunds);
+
+        transaction_pool
+            .add_transaction_spending(create_mock_transaction(40), spendable, None)
+            .unwrap();
+        assert_eq!(transaction_pool.len(), 2);
+
+        // popped transactions are pending until they are mined
+        let popped = transaction_pool.pop();
+        let result =
+            transaction_pool.add_transaction_spending(create_mock_transaction(60), spendable, None);
+        assert_eq!(result.unwrap_err(), PoolError::InsufficientPendingFunds);
+
+        transaction_pool.release(&popped);
+        transaction_pool
+            .add_transaction_spending(create_mock_transaction(60), spendable, None)
+            .unwrap();
+    }
+
+    #[test]
+    fn should_count_requeued_transactions_once() {
+        let transaction_pool = TransactionPool::new().with_pending_funds_check(true);
+
+        transaction_pool
+            .add_transaction_spending(create_mock_transaction(60), 100, None)
+            .unwrap();
+        let popped = transaction_pool.pop();
+        transaction_pool.requeue(popped);
+
+        transaction_pool
+            .add_transaction_spending(create_mock_transaction(40), 100, None)
+            .unwrap();
+        assert_eq!(transaction_pool.len(), 2);
+    }
+
+    #[test]
+    fn should_requeue_transactions_in_front() {
+        let transaction_pool = TransactionPool::new();
+        transaction_pool
+            .add_transaction(create_mock_transaction(1))
+            .unwrap();
+        let popped = transaction_pool.pop();
+
+        transaction_pool
+            .add_transaction(create_mock_transaction(2))
+            .unwrap();
+        transaction_pool.requeue(popped);
+
+        let amounts: Vec<u64> = transaction_pool
+            .pop()
+            .iter()
+            .map(|transaction| transaction.amount)
+            .collect();
+        assert_eq!(amounts, vec![1, 2]);
+    }
+
+    #[test]
+    fn should_keep_time_to_live_of_requeued_transactions() {
+        let ttl = Duration::from_millis(600000);
+        let transaction_pool = TransactionPool::new().with_ttl(ttl);
+
+        transaction_pool
+            .add_transaction(create_mock_transaction(1))
+            .unwrap();
+        let now = Instant::now();
+
+        let popped = transaction_pool.pop_at(now + ttl / 2);
+        transaction_pool.requeue_at(popped, now + ttl / 2);
+        assert_eq!(transaction_pool.len_at(now + ttl / 2), 1);
+        assert_eq!(transaction_pool.len_at(now + ttl), 0);
+    }
+
+    #[test]
+    fn should_not_count_expired_transfers_as_pending() {
+        let ttl = Duration::from_millis(600000);
+        let transaction_pool = TransactionPool::new()
+            .with_ttl(ttl)
+            .with_pending_funds_check(true);
+        let now = Instant::now();
+
+        transaction_pool
+            .add_transaction_spending_at(create_mock_transaction(60), 100, None, now)
+            .unwrap();
+
+        let result = transaction_pool.add_transaction_spending_at(
+            create_mock_transaction(60),
+            100,
+            None,
+            now + ttl / 2,
+        );
+        assert_eq!(result.unwrap_err(), PoolError::InsufficientPendingFunds);
+
+        transaction_pool
+            .add_transaction_spending_at(create_mock_transaction(60), 100, None, now + ttl)
+            .unwrap();
+        assert_eq!(transaction_pool.len_at(now + ttl), 1);
+    }
+
+    #[test]
+    fn should_reject_transfers_over_the_spending_allowance_together() {
+        let transaction_pool = TransactionPool::new();
+        let allowance = Some(100);
+
+        transaction_pool
+            .add_transaction_spending(create_mock_transaction(60), u64::MAX, allowance)
+            .unwrap();
+
+        // the allowance applies without the pending funds check
+        let result = transaction_pool.add_transaction_spending(
+            create_mock_transaction(60),
+            u64::MAX,
+            allowance,
+        );
+        assert_eq!(result.unwrap_err(), PoolError::SpendingLimitExceeded);
+
+        transaction_pool
+            .add_transaction_spending(create_mock_transaction(40), u64::MAX, allowance)
+            .unwrap();
+        assert_eq!(transaction_pool.len(), 2);
+    }
+
+    #[test]
+    fn should_accept_overspending_transfers_without_pending_funds_check() {
+        let transaction_pool = TransactionPool::new();
+
+        for _ in 0..2 {
+            transaction_pool
+                .add_transaction_spending(create_mock_transaction(60), 100, None)
+                .unwrap();
+        }
+
+        assert_eq!(transaction_pool.len(), 2);
+    }
+
+    #[test]
+    fn should_reject_self_transfer() {
+        let transaction_pool = TransactionPool::new();
+
+        let transaction = Transaction {
+            sender: person1(),
+            recipient: person1(),
+            amount: 1,
+        };
+        let result = transaction_pool.add_transaction(transaction);
+        assert_eq!(result.unwrap_err(), PoolError::SelfTransfer);
+
+        assert!(transaction_pool.pop().is_empty());
+    }
 }
@@ -1,20 +1,162 @@
-use std::panic;
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Mutex,
+    },
+    thread,
+    time::Duration,
+};
 
 use anyhow::Result;
-use isahc::{ReadResponseExt, Request};
+use isahc::{config::Configurable, HttpClient, ReadResponseExt, Request};
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
 
 use crate::{
-    model::{Block, Blockchain},
+    model::{
+        Block, BlockHash, Blockchain, PeerAddresses, PeerStatuses, Placement, RejectedBlocks,
+        Transaction, TransactionPool, BLOCK_VERSION, HASH_ALGORITHM,
+    },
     util::{
         execution::{sleep_millis, Runnable},
-        Context,
+        request_id::generate_request_id,
+        sync::lock_recover,
+        termination::{is_shutdown_requested, sleep_unless_shutdown, ShutdownFlag},
+        Context, Metrics,
     },
 };
 
+// Failed requests are retried after 100, 200 and 400 milliseconds
+const MAX_RETRIES: u32 = 3;
+const INITIAL_BACKOFF_MS: u64 = 100;
+
+// Connecting is given at most this long, within the timeout of the whole request
+const MAX_CONNECT_TIMEOUT_MS: u64 = 2000;
+
+// Only the latest blocks sent to each peer are remembered, older ones are not posted again anyway
+const MAX_SENT_BLOCKS_PER_PEER: usize = 1024;
+
+#[derive(Error, PartialEq, Debug)]
+pub enum PeerError {
+    #[error("Peer is unreachable")]
+    Unreachable,
+
+    #[error("Peer responded with status `{0}`")]
+    BadStatus(u16),
+
+    #[error("Peer responded with an invalid body")]
+    InvalidBody,
+
+    #[error("Peer uses block version {0} hashed with {1}")]
+    IncompatibleVersion(u32, String),
+}
+
+impl PeerError {
+    // Connection failures and server errors may succeed later, unlike rejections
+    fn is_transient(&self) -> bool {
+        match self {
+            PeerError::Unreachable => true,
+            PeerError::BadStatus(status) => *status >= 500,
+            PeerError::InvalidBody => false,
+            PeerError::IncompatibleVersion(..) => false,
+        }
+    }
+}
+
+// Answer of a peer to a posted block, peers that send no body are treated as not knowing it
+#[derive(Deserialize, Default)]
+struct BlockAcknowledgement {
+    known: bool,
+}
+
+// Rules a node validates blocks with, which peers exchange at /version before syncing
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct NodeVersion {
+    pub version: String,
+    pub block_version: u32,
+    pub hash_algorithm: String,
+}
+
+impl NodeVersion {
+    pub fn current() -> NodeVersion {
+        NodeVersion {
+            version: env!("CARGO_PKG_VERSION").to_string(),
+            block_version: BLOCK_VERSION,
+            hash_algorithm: HASH_ALGORITHM.to_string(),
+        }
+    }
+
+    // Nodes of different releases can sync, as long as they build and hash blocks alike
+    pub fn is_compatible(&self, other: &NodeVersion) -> bool {
+        self.block_version == other.block_version && self.hash_algorithm == other.hash_algorithm
+    }
+}
+
+// A single client is shared by all requests, so connections to peers are reused
+fn create_client(timeout_ms: u64) -> HttpClient {
+    HttpClient::builder()
+        .timeout(Duration::from_millis(timeout_ms))
+        .connect_timeout(Duration::from_millis(
+            timeout_ms.min(MAX_CONNECT_TIMEOUT_MS),
+        ))
+        .build()
+        .unwrap()
+}
+
+// Randomizes the backoff so peers failing at the same time do not retry in lockstep
+fn with_jitter(backoff_ms: u64) -> u64 {
+    backoff_ms + rand::thread_rng().gen_range(0..=backoff_ms / 2)
+}
+
 pub struct Peer {
-    peer_addresses: Vec<String>,
+    peer_addresses: PeerAddresses,
     blockchain: Blockchain,
+    pool: TransactionPool,
+    rejected_blocks: RejectedBlocks,
+    peer_statuses: PeerStatuses,
+    metrics: Metrics,
     peer_sync_ms: u64,
+    // ceiling on the requests sent to peers at the same time while syncing
+    max_concurrent_requests: usize,
+    sync_after_hash: bool,
+    // peers are asked for their version first, and skipped if their blocks would be rejected
+    check_version: bool,
+    incompatible_peers: Mutex<HashSet<String>>,
+    flush_pool_on_shutdown: bool,
+    // header carrying the id of the requests sent to peers, so they can be found in their logs
+    request_id_header: String,
+    client: HttpClient,
+    // latest blocks each peer is known to have, so they are not posted to it again
+    sent_blocks: Mutex<HashMap<String, SentBlocks>>,
+    shutdown: ShutdownFlag,
+}
+
+// Hashes in the order they were sent, to forget the oldest ones
+#[derive(Default)]
+struct SentBlocks {
+    hashes: HashSet<BlockHash>,
+    order: VecDeque<BlockHash>,
+}
+
+impl SentBlocks {
+    fn insert(&mut self, hash: BlockHash) {
+        if !self.hashes.insert(hash) {
+            return;
+        }
+
+        self.order.push_back(hash);
+        if self.order.len() > MAX_SENT_BLOCKS_PER_PEER {
+            if let Some(oldest) = self.order.pop_front() {
+                self.hashes.remove(&oldest);
+            }
+        }
+    }
+
+    fn contains(&self, hash: &BlockHash) -> bool {
+        self.hashes.contains(hash)
+    }
 }
 
 impl Runnable for Peer {
@@ -26,133 +168,943 @@ impl Runnable for Peer {
 impl Peer {
     pub fn new(context: &Context) -> Peer {
         Peer {
-            peer_addresses: context.config.peers.clone(),
+            peer_addresses: context.peer_addresses.clone(),
             blockchain: context.blockchain.clone(),
+            pool: context.pool.clone(),
+            rejected_blocks: context.rejected_blocks.clone(),
+            peer_statuses: context.peer_statuses.clone(),
+            metrics: context.metrics.clone(),
             peer_sync_ms: context.config.peer_sync_ms,
+            max_concurrent_requests: context.config.max_concurrent_peer_requests,
+            sync_after_hash: context.config.peer_sync_after_hash,
+            check_version: context.config.peer_version_check,
+            incompatible_peers: Mutex::default(),
+            flush_pool_on_shutdown: context.config.flush_pool_to_peers_on_shutdown,
+            request_id_header: context.config.request_id_header.clone(),
+            client: create_client(context.config.peer_timeout_ms),
+            sent_blocks: Mutex::default(),
+            shutdown: context.shutdown.clone(),
         }
     }
 
+    fn mark_as_sent(&self, address: &str, block: &Block) {
+        lock_recover(&self.sent_blocks)
+            .entry(address.to_string())
+            .or_default()
+            .insert(block.hash);
+    }
+
+    fn was_sent(&self, address: &str, block: &Block) -> bool {
+        lock_recover(&self.sent_blocks)
+            .get(address)
+            .is_some_and(|hashes| hashes.contains(&block.hash))
+    }
+
     fn get_last_block_index(&self) -> usize {
-        self.blockchain.get_last_block().index as usize
+        self.blockchain.get_last_block_index() as usize
+    }
+
+    fn request_version(&self, address: &str) -> Result<NodeVersion, PeerError> {
+        let uri = format!("{}/version", address);
+        let mut response = self.client.get(uri).map_err(|_| PeerError::Unreachable)?;
+
+        let status = response.status().as_u16();
+        if status != 200 {
+            return Err(PeerError::BadStatus(status));
+        }
+
+        let raw_body = response.text().map_err(|_| PeerError::Unreachable)?;
+
+        serde_json::from_str(&raw_body).map_err(|_| PeerError::InvalidBody)
+    }
+
+    fn is_incompatible(&self, address: &str) -> bool {
+        lock_recover(&self.incompatible_peers).contains(address)
+    }
+
+    // Peers that cannot tell their version, like older nodes, are synced as before. The warning
+    // is only logged when a peer turns incompatible, not on every sync
+    fn check_peer_version(&self, address: &str) -> Result<(), PeerError> {
+        if !self.check_version {
+            return Ok(());
+        }
+
+        let version = match self.request_version(address) {
+            Ok(version) => version,
+            Err(_) => return Ok(()),
+        };
+
+        let current = NodeVersion::current();
+        let mut incompatible_peers = lock_recover(&self.incompatible_peers);
+
+        if current.is_compatible(&version) {
+            if incompatible_peers.remove(address) {
+                info!("Peer {} is compatible again, resuming sync", address);
+            }
+
+            return Ok(());
+        }
+
+        if incompatible_peers.insert(address.to_string()) {
+            warn!(
+                "Skipping peer {}, which uses block version {} hashed with {} instead of {} with {}",
+                address,
+                version.block_version,
+                version.hash_algorithm,
+                current.block_version,
+                current.hash_algorithm
+            );
+        }
+
+        Err(PeerError::IncompatibleVersion(
+            version.block_version,
+            version.hash_algorithm,
+        ))
     }
 
-    fn get_new_blocks_from_peer(&self, address: &str) -> Vec<Block> {
-        let last_index = self.blockchain.get_last_block().index as usize;
+    fn get_new_blocks_from_peer(&self, address: &str) -> Result<Vec<Block>, PeerError> {
+        self.check_peer_version(address)?;
 
-        let peer_blocks = self.get_blocks_from_peer(address);
-        let peer_last_index = peer_blocks.last().unwrap().index as usize;
+        // the last block is the checkpoint, peers that do not have it are asked by index
+        if self.sync_after_hash {
+            let last_block = self.blockchain.get_last_block();
 
-        if peer_last_index <= last_index {
-            return Vec::<Block>::new();
+            match self.request_blocks_after_hash(address, &last_block.hash) {
+                Ok(new_blocks) => return Ok(new_blocks),
+                Err(error) => debug!(
+                    "Could not get blocks after {:#x} from peer {}: {}",
+                    last_block.hash, address, error
+                ),
+            }
         }
 
-        let first_new = last_index + 1;
-        let last_new = peer_last_index;
-        let new_blocks_range = first_new..=last_new;
+        let last_index = self.get_last_block_index();
+
+        let peer_blocks = self.get_blocks_from_peer(address, last_index + 1)?;
 
-        peer_blocks.get(new_blocks_range).unwrap().to_vec()
+        // peers that do not support pagination send the whole chain
+        let new_blocks = peer_blocks
+            .into_iter()
+            .filter(|block| block.index as usize > last_index)
+            .collect();
+
+        Ok(new_blocks)
     }
 
-    fn add_new_blocks(&self, new_blocks: &[Block]) {
+    fn add_new_blocks(&self, address: &str, new_blocks: &[Block]) {
         for block in new_blocks.iter() {
-            let result = self.blockchain.add_block(block.clone());
+            // the peer sent the block, so it does not need it back
+            self.mark_as_sent(address, block);
+
+            let result = self.blockchain.receive_block(block.clone());
 
-            if result.is_err() {
-                error!("Could not add peer block {} to the blockchain", block.index);
-                return;
+            if result.is_ok() {
+                self.metrics.peer_blocks_received.inc();
             }
 
-            info!("Added new peer block {} to the blockchain", block.index);
+            match result {
+                Ok(Placement::MainChain) => {
+                    info!("Added new peer block {} to the blockchain", block.index)
+                }
+
+                Ok(Placement::SideBranch) => {
+                    info!("Stored peer block {} as a side branch", block.index)
+                }
+
+                Ok(Placement::Orphan) => {
+                    info!("Stored peer block {} until its parent arrives", block.index)
+                }
+
+                Err(error) => {
+                    error!(
+                        "Could not add peer block {} to the blockchain: {}",
+                        block.index, error
+                    );
+                    self.rejected_blocks
+                        .record(block, &error.to_string(), address);
+                    return;
+                }
+            }
         }
     }
 
+    // Peers are asked for their new blocks by at most max_concurrent_requests threads at once,
+    // the other peers wait for a thread to be free. Results are in the order of the addresses
+    fn fetch_new_blocks(&self, addresses: &[String]) -> Vec<Result<Vec<Block>, PeerError>> {
+        let next_position = AtomicUsize::new(0);
+        let results = Mutex::new(addresses.iter().map(|_| None).collect::<Vec<_>>());
+        let workers = self
+            .max_concurrent_requests
+            .clamp(1, addresses.len().max(1));
+
+        thread::scope(|scope| {
+            for _ in 0..workers {
+                scope.spawn(|| loop {
+                    let position = next_position.fetch_add(1, Ordering::SeqCst);
+                    let address = match addresses.get(position) {
+                        Some(address) => address,
+                        None => break,
+                    };
+
+                    let result = self.get_new_blocks_from_peer(address);
+                    lock_recover(&results)[position] = Some(result);
+                });
+            }
+        });
+
+        let results = lock_recover(&results).drain(..).flatten().collect();
+
+        results
+    }
+
     fn try_receive_new_blocks(&self) {
-        for address in self.peer_addresses.iter() {
-            let result = panic::catch_unwind(|| {
-                let new_blocks = self.get_new_blocks_from_peer(address);
+        let addresses = self.peer_addresses.get_all();
+        let results = self.fetch_new_blocks(&addresses);
 
-                if !new_blocks.is_empty() {
-                    self.add_new_blocks(&new_blocks);
+        // blocks are added one peer at a time, as they may build on each other
+        for (address, result) in addresses.iter().zip(results) {
+            match result {
+                Ok(new_blocks) => {
+                    self.peer_statuses.record_success(address);
+
+                    if !new_blocks.is_empty() {
+                        self.add_new_blocks(address, &new_blocks);
+                    }
+                }
+
+                // already reported when the peer was found to be incompatible
+                Err(error @ PeerError::IncompatibleVersion(..)) => {
+                    self.peer_statuses
+                        .record_failure(address, &error.to_string());
                 }
-            });
 
-            if result.is_err() {
-                error!("Could not sync blocks from peer {}", address);
+                Err(error) => {
+                    error!("Could not sync blocks from peer {}: {}", address, error);
+                    self.peer_statuses
+                        .record_failure(address, &error.to_string());
+                }
             }
         }
     }
 
-    fn get_blocks_from_peer(&self, address: &str) -> Vec<Block> {
-        let uri = format!("{}/blocks", address);
-        let mut response = isahc::get(uri).unwrap();
+    fn request_blocks_from_peer(
+        &self,
+        address: &str,
+        from: usize,
+    ) -> Result<Vec<Block>, PeerError> {
+        self.request_blocks(format!("{}/blocks?from={}", address, from))
+    }
 
-        assert_eq!(response.status().as_u16(), 200);
+    fn request_blocks_after_hash(
+        &self,
+        address: &str,
+        hash: &BlockHash,
+    ) -> Result<Vec<Block>, PeerError> {
+        self.request_blocks(format!("{}/blocks/after/{:#x}", address, hash))
+    }
 
-        let raw_body = response.text().unwrap();
+    fn request_blocks(&self, uri: String) -> Result<Vec<Block>, PeerError> {
+        let mut response = self.client.get(uri).map_err(|_| PeerError::Unreachable)?;
+
+        let status = response.status().as_u16();
+        if status != 200 {
+            return Err(PeerError::BadStatus(status));
+        }
 
-        serde_json::from_str(&raw_body).unwrap()
+        let raw_body = response.text().map_err(|_| PeerError::Unreachable)?;
+
+        serde_json::from_str(&raw_body).map_err(|_| PeerError::InvalidBody)
     }
 
-    fn get_new_blocks_since(&self, start_index: usize) -> Vec<Block> {
-        let last_block_index = self.get_last_block_index();
-        let new_blocks_range = start_index + 1..=last_block_index;
+    fn get_blocks_from_peer(&self, address: &str, from: usize) -> Result<Vec<Block>, PeerError> {
+        let mut backoff_ms = INITIAL_BACKOFF_MS;
+        let mut retries = 0;
+
+        loop {
+            let error = match self.request_blocks_from_peer(address, from) {
+                Ok(blocks) => return Ok(blocks),
+                Err(error) => error,
+            };
 
+            warn!(
+                "Request for blocks to peer {} failed (attempt {}): {}",
+                address,
+                retries + 1,
+                error
+            );
+
+            if retries == MAX_RETRIES {
+                return Err(error);
+            }
+
+            sleep_millis(backoff_ms);
+            backoff_ms *= 2;
+            retries += 1;
+        }
+    }
+
+    fn get_new_blocks_since(&self, start_index: usize) -> Vec<Block> {
         self.blockchain
-            .get_all_blocks()
-            .get(new_blocks_range)
-            .unwrap()
-            .to_vec()
+            .get_blocks_range(start_index + 1, usize::MAX)
     }
 
-    fn send_block_to_peer(address: &str, block: &Block) {
+    // Returns whether the peer already knew the block
+    fn send_block_to_peer(
+        &self,
+        address: &str,
+        block: &Block,
+        request_id: &str,
+    ) -> Result<bool, PeerError> {
         let uri = format!("{}/blocks", address);
         let body = serde_json::to_string(&block).unwrap();
 
+        debug!(
+            "Sending block {} to peer {} [{}]",
+            block.index, address, request_id
+        );
+
         let request = Request::post(uri)
             .header("Content-Type", "application/json")
+            .header(self.request_id_header.as_str(), request_id)
             .body(body)
             .unwrap();
 
-        isahc::send(request).unwrap();
+        let mut response = self
+            .client
+            .send(request)
+            .map_err(|_| PeerError::Unreachable)?;
+
+        let status = response.status().as_u16();
+        if status != 200 {
+            return Err(PeerError::BadStatus(status));
+        }
+
+        let acknowledgement: BlockAcknowledgement = response
+            .text()
+            .ok()
+            .and_then(|body| serde_json::from_str(&body).ok())
+            .unwrap_or_default();
+
+        Ok(acknowledgement.known)
+    }
+
+    // Only transient failures are retried, as a peer rejecting a block would keep rejecting it
+    fn send_block_with_retries(
+        &self,
+        address: &str,
+        block: &Block,
+        request_id: &str,
+    ) -> Result<bool, PeerError> {
+        let mut backoff_ms = INITIAL_BACKOFF_MS;
+        let mut retries = 0;
+
+        loop {
+            let error = match self.send_block_to_peer(address, block, request_id) {
+                Ok(known) => return Ok(known),
+                Err(error) => error,
+            };
+
+            if !error.is_transient() || retries == MAX_RETRIES {
+                return Err(error);
+            }
+
+            warn!(
+                "Sending block {} to peer {} failed (attempt {}): {}",
+                block.index,
+                address,
+                retries + 1,
+                error
+            );
+
+            sleep_millis(with_jitter(backoff_ms));
+            backoff_ms *= 2;
+            retries += 1;
+        }
+    }
+
+    // Every peer gets the same request id, so the block can be followed across their logs
+    fn send_block_to_peers<'a>(
+        &self,
+        block: &Block,
+        peer_addresses: &'a [String],
+        failed_peers: &mut HashSet<&'a String>,
+        request_id: &str,
+    ) {
+        for address in peer_addresses.iter() {
+            if failed_peers.contains(address)
+                || self.is_incompatible(address)
+                || self.was_sent(address, block)
+            {
+                continue;
+            }
+
+            match self.send_block_with_retries(address, block, request_id) {
+                Ok(known) => {
+                    match known {
+                        true => info!("Peer {} already had block {}", address, block.index),
+                        false => info!("Sended new block {} to peer {}", block.index, address),
+                    }
+                    self.mark_as_sent(address, block);
+                }
+
+                // later blocks would not connect, so the peer is skipped for this cycle
+                Err(error) => {
+                    error!(
+                        "Could not send block {} to peer {}: {}",
+                        block.index, address, error
+                    );
+                    failed_peers.insert(address);
+                }
+            }
+        }
     }
 
     fn try_send_new_blocks(&self, last_send_block_index: usize) {
         let new_blocks = self.get_new_blocks_since(last_send_block_index);
+        let peer_addresses = self.peer_addresses.get_all();
+        let mut failed_peers = HashSet::new();
 
         for block in new_blocks.iter() {
-            for address in self.peer_addresses.iter() {
-                let result = panic::catch_unwind(|| Peer::send_block_to_peer(address, block));
+            let request_id = generate_request_id();
+            self.send_block_to_peers(block, &peer_addresses, &mut failed_peers, &request_id);
+        }
+    }
 
-                if result.is_err() {
-                    error!("Could not send block {} to peer {}", block.index, address);
-                    return;
-                }
+    // Sends the block to the peers right away, instead of at the next sync. Peers it was already
+    // sent to are skipped, so the sync does not send it again either. The request id is the one
+    // of the request that brought the block, so it can be traced from node to node
+    pub fn broadcast_block(&self, block: &Block, request_id: &str) {
+        let peer_addresses = self.peer_addresses.get_all();
 
-                info!("Sended new block {} to peer {}", block.index, address);
-            }
+        self.send_block_to_peers(block, &peer_addresses, &mut HashSet::new(), request_id);
+    }
+
+    fn send_transaction_to_peer(
+        &self,
+        address: &str,
+        transaction: &Transaction,
+    ) -> Result<(), PeerError> {
+        let uri = format!("{}/transactions", address);
+        let body = serde_json::to_string(&transaction).unwrap();
+        let request_id = generate_request_id();
+
+        debug!(
+            "Sending transaction {} to peer {} [{}]",
+            transaction.hash(),
+            address,
+            request_id
+        );
+
+        let request = Request::post(uri)
+            .header("Content-Type", "application/json")
+            .header(self.request_id_header.as_str(), request_id.as_str())
+            .body(body)
+            .unwrap();
+
+        let response = self
+            .client
+            .send(request)
+            .map_err(|_| PeerError::Unreachable)?;
+
+        let status = response.status().as_u16();
+        if status != 200 {
+            return Err(PeerError::BadStatus(status));
         }
+
+        Ok(())
     }
 
-    pub fn start(&self) -> Result<()> {
-        if self.peer_addresses.is_empty() {
-            info!("No peers configured, exiting peer sync system");
+    // Hands the transactions that were not mined yet to the peers, so they are not lost with the
+    // node. There are no retries, as the node is shutting down
+    fn flush_pool_to_peers(&self) {
+        let transactions = self.pool.pop();
+        if transactions.is_empty() {
+            return;
+        }
 
-            return Ok(());
+        info!(
+            "Flushing {} pooled transactions to peers",
+            transactions.len()
+        );
+
+        for address in self.peer_addresses.get_all().iter() {
+            let mut flushed = 0;
+
+            for transaction in transactions.iter() {
+                match self.send_transaction_to_peer(address, transaction) {
+                    Ok(()) => flushed += 1,
+
+                    // the remaining transactions would not reach the peer either
+                    Err(PeerError::Unreachable) => {
+                        error!("Could not flush transactions to peer {}", address);
+                        break;
+                    }
+
+                    Err(error) => {
+                        warn!("Peer {} did not accept a transaction: {}", address, error)
+                    }
+                }
+            }
+
+            info!("Flushed {} transactions to peer {}", flushed, address);
         }
+    }
 
+    // Peers can be added while running, so the system keeps going even without peers
+    pub fn start(&self) -> Result<()> {
         info!(
             "Start peer system with peers: {}",
-            self.peer_addresses.join(", ")
+            self.peer_addresses.get_all().join(", ")
         );
 
         let mut last_sent_block_index = self.get_last_block_index();
 
-        loop {
+        while !is_shutdown_requested(&self.shutdown) {
             self.try_receive_new_blocks();
             self.try_send_new_blocks(last_sent_block_index);
             last_sent_block_index = self.get_last_block_index();
 
-            sleep_millis(self.peer_sync_ms);
+            sleep_unless_shutdown(&self.shutdown, self.peer_sync_ms);
+        }
+
+        info!("Shutdown requested, stopping peer system");
+
+        if self.flush_pool_on_shutdown {
+            self.flush_pool_to_peers();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        net::{TcpListener, TcpStream},
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc, Mutex,
+        },
+        thread,
+        time::{Duration, Instant},
+    };
+
+    use crate::{
+        model::{Address, Transaction, BLOCK_SUBSIDY},
+        util::request_id::DEFAULT_REQUEST_ID_HEADER,
+    };
+
+    use super::*;
+
+    fn create_peer(peer_addresses: Vec<String>) -> Peer {
+        Peer {
+            peer_addresses: PeerAddresses::new(peer_addresses),
+            blockchain: Blockchain::new(0),
+            pool: TransactionPool::new(),
+            rejected_blocks: RejectedBlocks::new(10),
+            peer_statuses: PeerStatuses::default(),
+            metrics: Metrics::new(),
+            peer_sync_ms: 1,
+            max_concurrent_requests: 8,
+            sync_after_hash: false,
+            check_version: false,
+            incompatible_peers: Mutex::default(),
+            flush_pool_on_shutdown: true,
+            request_id_header: DEFAULT_REQUEST_ID_HEADER.to_string(),
+            client: create_client(1000),
+            sent_blocks: Mutex::default(),
+            shutdown: ShutdownFlag::default(),
+        }
+    }
+
+    #[test]
+    fn test_get_blocks_from_unreachable_peer() {
+        // nothing listens on port 1, so every attempt is refused right away
+        let address = "http://localhost:1".to_string();
+        let peer = create_peer(vec![address.clone()]);
+
+        let start = Instant::now();
+        let result = peer.get_blocks_from_peer(&address, 0);
+
+        assert_eq!(result.unwrap_err(), PeerError::Unreachable);
+        assert!(start.elapsed() >= Duration::from_millis(700));
+    }
+
+    #[test]
+    fn test_receive_from_unreachable_peer_does_not_panic() {
+        let peer = create_peer(vec!["http://localhost:1".to_string()]);
+
+        peer.try_receive_new_blocks();
+
+        assert_eq!(peer.get_last_block_index(), 0);
+    }
+
+    #[test]
+    fn test_unreachable_peer_status() {
+        let address = "http://localhost:1";
+        let peer = create_peer(vec![address.to_string()]);
+
+        peer.try_receive_new_blocks();
+
+        let status = peer.peer_statuses.get(address);
+        assert!(!status.reachable);
+        assert_eq!(status.last_seen, None);
+        assert_eq!(status.last_error, Some("Peer is unreachable".to_string()));
+    }
+
+    #[test]
+    fn test_rejected_peer_block_is_recorded() {
+        let address = "http://localhost:8001";
+        let peer = create_peer(vec![address.to_string()]);
+        let genesis_hash = peer.blockchain.get_last_block().hash;
+        let block = Block::new(2, 0, genesis_hash, vec![]);
+
+        peer.add_new_blocks(address, std::slice::from_ref(&block));
+
+        let rejected = peer.rejected_blocks.get_all();
+        assert_eq!(rejected.len(), 1);
+        assert_eq!(rejected[0].block.hash, block.hash);
+        assert_eq!(rejected[0].reason, "Invalid index");
+        assert_eq!(rejected[0].source, address);
+    }
+
+    // Answers each request with the next status, and returns the url and the number of requests
+    fn serve_statuses(statuses: Vec<u16>) -> (String, Arc<AtomicUsize>) {
+        let responses = statuses
+            .into_iter()
+            .map(|status| (status, String::new()))
+            .collect();
+
+        serve_responses(responses)
+    }
+
+    // Like serve_statuses, with a body for each response
+    fn serve_responses(responses: Vec<(u16, String)>) -> (String, Arc<AtomicUsize>) {
+        let (address, requests, _) = serve_and_record(responses);
+
+        (address, requests)
+    }
+
+    // Like serve_responses, also keeping the text of each request received
+    fn serve_and_record(
+        responses: Vec<(u16, String)>,
+    ) -> (String, Arc<AtomicUsize>, Arc<Mutex<Vec<String>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(AtomicUsize::new(0));
+        let thread_requests = requests.clone();
+        let recorded = Arc::new(Mutex::new(Vec::new()));
+        let thread_recorded = recorded.clone();
+
+        thread::spawn(move || {
+            for (status, body) in responses {
+                let (mut stream, _) = listener.accept().unwrap();
+                let request = read_request(&mut stream);
+                thread_recorded.lock().unwrap().push(request);
+                thread_requests.fetch_add(1, Ordering::SeqCst);
+
+                let response = format!(
+                    "HTTP/1.1 {} Status\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    status,
+                    body.len(),
+                    body
+                );
+                stream.write_all(response.as_bytes()).unwrap();
+            }
+        });
+
+        (address, requests, recorded)
+    }
+
+    fn read_request(stream: &mut TcpStream) -> String {
+        let mut request = Vec::new();
+        let mut buffer = [0; 1024];
+
+        loop {
+            let read = stream.read(&mut buffer).unwrap();
+            request.extend_from_slice(&buffer[..read]);
+
+            let text = String::from_utf8_lossy(&request);
+            if let Some(headers_end) = text.find("\r\n\r\n") {
+                let content_length = text[..headers_end]
+                    .lines()
+                    .find_map(|line| {
+                        line.to_lowercase()
+                            .strip_prefix("content-length:")
+                            .map(|value| value.trim().parse::<usize>().unwrap())
+                    })
+                    .unwrap_or(0);
+
+                if request.len() >= headers_end + 4 + content_length {
+                    return text.into_owned();
+                }
+            }
+
+            if read == 0 {
+                return String::from_utf8_lossy(&request).into_owned();
+            }
+        }
+    }
+
+    #[test]
+    fn test_send_retries_transient_failure() {
+        let (address, requests) = serve_statuses(vec![503, 200]);
+        let block = Block::new(1, 0, BlockHash::default(), vec![]);
+
+        let peer = create_peer(vec![address.clone()]);
+        let result = peer.send_block_with_retries(&address, &block, "trace-123");
+
+        assert!(result.is_ok());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_send_retries_with_the_same_request_id() {
+        let (address, _, recorded) =
+            serve_and_record(vec![(503, String::new()), (200, String::new())]);
+        let block = Block::new(1, 0, BlockHash::default(), vec![]);
+
+        let peer = create_peer(vec![address.clone()]);
+        peer.send_block_with_retries(&address, &block, "trace-123")
+            .unwrap();
+
+        let requests = recorded.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        for request in requests.iter() {
+            assert!(request.to_lowercase().contains("x-request-id: trace-123"));
+        }
+    }
+
+    #[test]
+    fn test_send_does_not_retry_rejection() {
+        let (address, requests) = serve_statuses(vec![400, 200]);
+        let block = Block::new(1, 0, BlockHash::default(), vec![]);
+
+        let peer = create_peer(vec![address.clone()]);
+        let result = peer.send_block_with_retries(&address, &block, "trace-123");
+
+        assert_eq!(result.unwrap_err(), PeerError::BadStatus(400));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_send_block_only_once_per_peer() {
+        let (address, requests) = serve_statuses(vec![200, 200]);
+        let peer = create_peer(vec![address.clone()]);
+
+        let genesis = peer.blockchain.get_last_block();
+        let coinbase = Transaction {
+            sender: Address::default(),
+            recipient: Address::default(),
+            amount: BLOCK_SUBSIDY,
+        };
+        let block = Block::new(1, 0, genesis.hash, vec![coinbase]);
+        peer.blockchain.add_block(block.clone()).unwrap();
+
+        peer.try_send_new_blocks(0);
+        peer.try_send_new_blocks(0);
+
+        assert!(peer.was_sent(&address, &block));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_broadcast_block_once_per_peer() {
+        let (address, requests) = serve_statuses(vec![200, 200]);
+        let peer = create_peer(vec![address.clone()]);
+        let block = Block::new(1, 0, BlockHash::default(), vec![]);
+
+        peer.broadcast_block(&block, "trace-123");
+        peer.broadcast_block(&block, "trace-123");
+
+        assert!(peer.was_sent(&address, &block));
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_forget_oldest_sent_blocks() {
+        let mut sent_blocks = SentBlocks::default();
+        for index in 0..=MAX_SENT_BLOCKS_PER_PEER {
+            sent_blocks.insert(BlockHash::from(index));
+        }
+
+        assert!(!sent_blocks.contains(&BlockHash::from(0)));
+        assert!(sent_blocks.contains(&BlockHash::from(1)));
+        assert!(sent_blocks.contains(&BlockHash::from(MAX_SENT_BLOCKS_PER_PEER)));
+        assert_eq!(sent_blocks.hashes.len(), MAX_SENT_BLOCKS_PER_PEER);
+    }
+
+    #[test]
+    fn test_sync_after_last_block_hash() {
+        let mut peer = create_peer(vec![]);
+        peer.sync_after_hash = true;
+
+        let genesis = peer.blockchain.get_last_block();
+        let block = Block::new(1, 0, genesis.hash, vec![]);
+        let body = serde_json::to_string(&vec![block.clone()]).unwrap();
+
+        let (address, requests) = serve_responses(vec![(200, body)]);
+        let new_blocks = peer.get_new_blocks_from_peer(&address).unwrap();
+
+        assert_eq!(new_blocks, vec![block]);
+        assert_eq!(requests.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_sync_by_index_when_peer_lacks_last_block() {
+        let mut peer = create_peer(vec![]);
+        peer.sync_after_hash = true;
+
+        let (address, requests) = serve_responses(vec![(404, String::new()), (200, "[]".into())]);
+        let new_blocks = peer.get_new_blocks_from_peer(&address).unwrap();
+
+        assert!(new_blocks.is_empty());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_flush_pool_to_peers_on_shutdown() {
+        let (address, requests) = serve_statuses(vec![200, 200]);
+        // the unreachable peer does not keep the others from getting the transactions
+        let peer = create_peer(vec!["http://localhost:1".to_string(), address]);
+
+        for amount in 1..=2 {
+            let transaction = Transaction {
+                sender: Address::default(),
+                recipient: Address::default(),
+                amount,
+            };
+            peer.pool.add_transaction(transaction).unwrap();
+        }
+
+        peer.shutdown.store(true, Ordering::SeqCst);
+        peer.start().unwrap();
+
+        assert!(peer.pool.is_empty());
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_request_to_unresponsive_peer_times_out() {
+        // accepts connections but never answers, like a hung peer
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+        let peer = create_peer(vec![address.clone()]);
+
+        let start = Instant::now();
+        let result = peer.request_blocks_from_peer(&address, 0);
+
+        assert_eq!(result.unwrap_err(), PeerError::Unreachable);
+        assert!(start.elapsed() < Duration::from_secs(3));
+        drop(listener);
+    }
+
+    #[test]
+    fn test_request_to_non_routable_peer_times_out() {
+        let address = "http://10.255.255.1:8000";
+        let peer = create_peer(vec![address.to_string()]);
+
+        let start = Instant::now();
+        let result = peer.request_blocks_from_peer(address, 0);
+
+        assert_eq!(result.unwrap_err(), PeerError::Unreachable);
+        assert!(start.elapsed() < Duration::from_secs(3));
+    }
+
+    // Peer answering a single request with an empty chain after a delay, counting the requests
+    // it is handling at the same time as the others sharing the counters
+    fn serve_slowly(in_flight: Arc<AtomicUsize>, max_in_flight: Arc<AtomicUsize>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", listener.local_addr().unwrap());
+
+        thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            read_request(&mut stream);
+
+            let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            max_in_flight.fetch_max(current, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(100));
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            let response = "HTTP/1.1 200 OK\r\nContent-Length: 2\r\nConnection: close\r\n\r\n[]";
+            stream.write_all(response.as_bytes()).unwrap();
+        });
+
+        address
+    }
+
+    #[test]
+    fn test_limit_concurrent_requests_to_peers() {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let max_in_flight = Arc::new(AtomicUsize::new(0));
+        let addresses: Vec<String> = (0..12)
+            .map(|_| serve_slowly(in_flight.clone(), max_in_flight.clone()))
+            .collect();
+
+        let mut peer = create_peer(addresses.clone());
+        peer.max_concurrent_requests = 3;
+
+        let start = Instant::now();
+        peer.try_receive_new_blocks();
+
+        // the peers were asked in parallel, but never more than three at once
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 3);
+        assert!(start.elapsed() < Duration::from_millis(1000));
+        for address in addresses.iter() {
+            assert!(peer.peer_statuses.get(address).reachable);
         }
     }
+
+    fn version_body(hash_algorithm: &str) -> String {
+        let version = NodeVersion {
+            hash_algorithm: hash_algorithm.to_string(),
+            ..NodeVersion::current()
+        };
+
+        serde_json::to_string(&version).unwrap()
+    }
+
+    #[test]
+    fn test_skip_peer_with_incompatible_hash_algorithm() {
+        // the peer answers every sync with its version, and is never asked for blocks
+        let (address, requests) = serve_responses(vec![
+            (200, version_body("blake3")),
+            (200, version_body("blake3")),
+        ]);
+        let mut peer = create_peer(vec![address.clone()]);
+        peer.check_version = true;
+
+        peer.try_receive_new_blocks();
+        peer.try_receive_new_blocks();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert!(peer.is_incompatible(&address));
+        assert!(peer.rejected_blocks.get_all().is_empty());
+
+        let status = peer.peer_statuses.get(&address);
+        assert_eq!(
+            status.last_error,
+            Some(format!(
+                "Peer uses block version {} hashed with blake3",
+                BLOCK_VERSION
+            ))
+        );
+    }
+
+    #[test]
+    fn test_sync_with_peer_of_compatible_version() {
+        let (address, requests) = serve_responses(vec![
+            (200, version_body(HASH_ALGORITHM)),
+            (200, "[]".to_string()),
+        ]);
+        let mut peer = create_peer(vec![address.clone()]);
+        peer.check_version = true;
+
+        peer.try_receive_new_blocks();
+
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+        assert!(!peer.is_incompatible(&address));
+        assert!(peer.peer_statuses.get(&address).reachable);
+    }
 }
@@ -2,8 +2,14 @@ mod config;
 mod context;
 pub mod execution;
 mod logger;
+mod metrics;
+mod rate_limiter;
+pub mod request_id;
+pub mod sync;
 pub mod termination;
 
-pub use config::Config;
+pub use config::{Config, ConfigError};
 pub use context::Context;
 pub use logger::initialize_logger;
+pub use metrics::Metrics;
+pub use rate_limiter::RateLimiter;
@@ -1,9 +1,14 @@
-use crate::model::{Blockchain, TransactionPool};
+use crate::model::{Blockchain, PeerAddresses, PeerStatuses, RejectedBlocks, TransactionPool};
 
-use super::config::Config;
+use super::{config::Config, metrics::Metrics, termination::ShutdownFlag};
 
 pub struct Context {
     pub config: Config,
     pub blockchain: Blockchain,
     pub pool: TransactionPool,
+    pub rejected_blocks: RejectedBlocks,
+    pub peer_addresses: PeerAddresses,
+    pub peer_statuses: PeerStatuses,
+    pub metrics: Metrics,
+    pub shutdown: ShutdownFlag,
 }
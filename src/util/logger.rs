@@ -1,8 +1,47 @@
+use std::{env, str::FromStr};
+
+use dotenv::dotenv;
 use env_logger::{Builder, Target};
+use log::LevelFilter;
+
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::Info;
+
+fn parse_level(value: &str) -> LevelFilter {
+    LevelFilter::from_str(value.trim()).unwrap_or(DEFAULT_LEVEL)
+}
 
+// RUST_LOG takes precedence over LOG_LEVEL, as it allows per-module filters
 pub fn initialize_logger() {
+    dotenv().ok();
+
     let mut builder = Builder::from_default_env();
     builder.target(Target::Stdout);
-    builder.filter(None, log::LevelFilter::Info);
+
+    if env::var("RUST_LOG").is_err() {
+        let level = env::var("LOG_LEVEL").map_or(DEFAULT_LEVEL, |value| parse_level(&value));
+        builder.filter(None, level);
+    }
+
     builder.init();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_log_levels() {
+        assert_eq!(parse_level("error"), LevelFilter::Error);
+        assert_eq!(parse_level("warn"), LevelFilter::Warn);
+        assert_eq!(parse_level("info"), LevelFilter::Info);
+        assert_eq!(parse_level("debug"), LevelFilter::Debug);
+        assert_eq!(parse_level("trace"), LevelFilter::Trace);
+        assert_eq!(parse_level("DEBUG"), LevelFilter::Debug);
+    }
+
+    #[test]
+    fn parse_invalid_log_level() {
+        assert_eq!(parse_level("verbose"), DEFAULT_LEVEL);
+        assert_eq!(parse_level(""), DEFAULT_LEVEL);
+    }
+}
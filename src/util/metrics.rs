@@ -0,0 +1,96 @@
+use prometheus::{Encoder, Gauge, IntCounter, IntGauge, Registry, TextEncoder};
+
+#[derive(Clone)]
+pub struct Metrics {
+    registry: Registry,
+    pub blocks_mined: IntCounter,
+    pub transactions_received: IntCounter,
+    pub peer_blocks_received: IntCounter,
+    pub blockchain_height: IntGauge,
+    pub mempool_size: IntGauge,
+    pub hashrate: Gauge,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Metrics::new()
+    }
+}
+
+impl Metrics {
+    pub fn new() -> Metrics {
+        let registry = Registry::new();
+
+        let blocks_mined =
+            IntCounter::new("blocks_mined_total", "Blocks mined by this node").unwrap();
+        let transactions_received = IntCounter::new(
+            "transactions_received_total",
+            "Transactions added to the pool",
+        )
+        .unwrap();
+        let peer_blocks_received =
+            IntCounter::new("peer_blocks_received_total", "Blocks received from peers").unwrap();
+        let blockchain_height =
+            IntGauge::new("blockchain_height", "Index of the last block of the chain").unwrap();
+        let mempool_size =
+            IntGauge::new("mempool_size", "Transactions waiting in the pool").unwrap();
+        let hashrate = Gauge::new(
+            "mining_hashrate",
+            "Hashes per second over the last mined blocks",
+        )
+        .unwrap();
+
+        registry.register(Box::new(blocks_mined.clone())).unwrap();
+        registry
+            .register(Box::new(transactions_received.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(peer_blocks_received.clone()))
+            .unwrap();
+        registry
+            .register(Box::new(blockchain_height.clone()))
+            .unwrap();
+        registry.register(Box::new(mempool_size.clone())).unwrap();
+        registry.register(Box::new(hashrate.clone())).unwrap();
+
+        Metrics {
+            registry,
+            blocks_mined,
+            transactions_received,
+            peer_blocks_received,
+            blockchain_height,
+            mempool_size,
+            hashrate,
+        }
+    }
+
+    // Metrics in the Prometheus text format
+    pub fn encode(&self) -> String {
+        let mut buffer = Vec::new();
+        let encoder = TextEncoder::new();
+
+        encoder
+            .encode(&self.registry.gather(), &mut buffer)
+            .unwrap();
+
+        String::from_utf8(buffer).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_counters_and_gauges() {
+        let metrics = Metrics::new();
+        metrics.blocks_mined.inc();
+        metrics.blockchain_height.set(5);
+
+        let output = metrics.encode();
+
+        assert!(output.contains("blocks_mined_total 1"));
+        assert!(output.contains("blockchain_height 5"));
+        assert!(output.contains("mempool_size 0"));
+    }
+}
@@ -0,0 +1,210 @@
+use std::{
+    collections::{BTreeSet, HashMap},
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use super::sync::lock_recover;
+
+// Beyond this number of tracked senders, the one whose bucket was used the least recently is
+// forgotten, which only hands it a full bucket again
+const MAX_TRACKED_SENDERS: usize = 1024;
+
+// Source of the current time, which tests replace to control the refills
+pub type Clock = Arc<dyn Fn() -> Instant + Send + Sync>;
+
+#[derive(Debug, Clone)]
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+impl TokenBucket {
+    fn new(capacity: f64, now: Instant) -> TokenBucket {
+        TokenBucket {
+            tokens: capacity,
+            updated_at: now,
+        }
+    }
+
+    fn refill(&mut self, capacity: f64, tokens_per_sec: f64, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated_at).as_secs_f64();
+
+        self.tokens = (self.tokens + elapsed * tokens_per_sec).min(capacity);
+        self.updated_at = now;
+    }
+}
+
+// Buckets of the tracked senders, along with the order they were last used in
+#[derive(Debug, Default)]
+struct Buckets {
+    by_sender: HashMap<IpAddr, TokenBucket>,
+    by_use: BTreeSet<(Instant, IpAddr)>,
+}
+
+impl Buckets {
+    fn remove(&mut self, sender: &IpAddr) -> Option<TokenBucket> {
+        let bucket = self.by_sender.remove(sender)?;
+        self.by_use.remove(&(bucket.updated_at, *sender));
+
+        Some(bucket)
+    }
+
+    fn insert(&mut self, sender: IpAddr, bucket: TokenBucket) {
+        if self.by_sender.len() >= MAX_TRACKED_SENDERS {
+            if let Some((_, least_recent)) = self.by_use.pop_first() {
+                self.by_sender.remove(&least_recent);
+            }
+        }
+
+        self.by_use.insert((bucket.updated_at, sender));
+        self.by_sender.insert(sender, bucket);
+    }
+}
+
+// Allows each sender a burst of up to `max_per_minute` requests, refilled evenly over a minute
+#[derive(Clone)]
+pub struct RateLimiter {
+    max_per_minute: u32,
+    buckets: Arc<Mutex<Buckets>>,
+    clock: Clock,
+}
+
+impl RateLimiter {
+    // A limit of zero disables the rate limiting
+    pub fn new(max_per_minute: u32) -> RateLimiter {
+        RateLimiter {
+            max_per_minute,
+            buckets: Arc::default(),
+            clock: Arc::new(Instant::now),
+        }
+    }
+
+    pub fn with_clock(mut self, clock: Clock) -> RateLimiter {
+        self.clock = clock;
+
+        self
+    }
+
+    pub fn try_acquire(&self, sender: IpAddr) -> bool {
+        self.try_acquire_at(sender, (self.clock)())
+    }
+
+    fn try_acquire_at(&self, sender: IpAddr, now: Instant) -> bool {
+        if self.max_per_minute == 0 {
+            return true;
+        }
+
+        let capacity = self.max_per_minute as f64;
+        let tokens_per_sec = capacity / Duration::from_secs(60).as_secs_f64();
+
+        let mut buckets = lock_recover(&self.buckets);
+
+        let mut bucket = buckets
+            .remove(&sender)
+            .unwrap_or_else(|| TokenBucket::new(capacity, now));
+        bucket.refill(capacity, tokens_per_sec, now);
+
+        let acquired = bucket.tokens >= 1.0;
+        if acquired {
+            bucket.tokens -= 1.0;
+        }
+
+        buckets.insert(sender, bucket);
+
+        acquired
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::net::Ipv4Addr;
+
+    use super::*;
+
+    const SENDER: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 1));
+    const OTHER_SENDER: IpAddr = IpAddr::V4(Ipv4Addr::new(10, 0, 0, 2));
+
+    #[test]
+    fn should_limit_fast_sender() {
+        let limiter = RateLimiter::new(3);
+        let now = Instant::now();
+
+        for _ in 0..3 {
+            assert!(limiter.try_acquire_at(SENDER, now));
+        }
+        assert!(!limiter.try_acquire_at(SENDER, now));
+
+        // other senders have their own bucket
+        assert!(limiter.try_acquire_at(OTHER_SENDER, now));
+    }
+
+    #[test]
+    fn should_not_limit_slow_sender() {
+        let limiter = RateLimiter::new(3);
+        let start = Instant::now();
+
+        // one request every 20 seconds is exactly the allowed rate
+        for i in 0..10 {
+            let now = start + Duration::from_secs(20 * i);
+            assert!(limiter.try_acquire_at(SENDER, now));
+        }
+    }
+
+    #[test]
+    fn should_refill_after_waiting() {
+        let limiter = RateLimiter::new(60);
+        let now = Instant::now();
+
+        while limiter.try_acquire_at(SENDER, now) {}
+
+        assert!(!limiter.try_acquire_at(SENDER, now + Duration::from_millis(500)));
+        assert!(limiter.try_acquire_at(SENDER, now + Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn should_forget_least_recent_senders() {
+        let limiter = RateLimiter::new(3);
+        let start = Instant::now();
+
+        let senders: Vec<IpAddr> = (0..=MAX_TRACKED_SENDERS as u32)
+            .map(|i| IpAddr::V4(Ipv4Addr::from(i)))
+            .collect();
+        for (i, sender) in senders.iter().enumerate() {
+            let now = start + Duration::from_millis(i as u64);
+            assert!(limiter.try_acquire_at(*sender, now));
+        }
+
+        let buckets = lock_recover(&limiter.buckets);
+        assert_eq!(buckets.by_sender.len(), MAX_TRACKED_SENDERS);
+        assert_eq!(buckets.by_use.len(), MAX_TRACKED_SENDERS);
+        assert!(!buckets.by_sender.contains_key(&senders[0]));
+        assert!(buckets
+            .by_sender
+            .contains_key(&senders[MAX_TRACKED_SENDERS]));
+    }
+
+    #[test]
+    fn should_read_time_from_clock() {
+        let now = Arc::new(Mutex::new(Instant::now()));
+        let clock_now = now.clone();
+        let limiter = RateLimiter::new(60).with_clock(Arc::new(move || *lock_recover(&clock_now)));
+
+        while limiter.try_acquire(SENDER) {}
+
+        *lock_recover(&now) += Duration::from_secs(1);
+        assert!(limiter.try_acquire(SENDER));
+        assert!(!limiter.try_acquire(SENDER));
+    }
+
+    #[test]
+    fn should_not_limit_when_disabled() {
+        let limiter = RateLimiter::new(0);
+        let now = Instant::now();
+
+        for _ in 0..1000 {
+            assert!(limiter.try_acquire_at(SENDER, now));
+        }
+    }
+}
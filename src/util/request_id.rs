@@ -0,0 +1,58 @@
+use rand::Rng;
+
+pub const DEFAULT_REQUEST_ID_HEADER: &str = "X-Request-Id";
+
+// Longer ids sent by clients are replaced, so they cannot flood the logs
+const MAX_REQUEST_ID_LEN: usize = 64;
+
+pub fn generate_request_id() -> String {
+    format!("{:016x}", rand::thread_rng().gen::<u64>())
+}
+
+// Ids are written to the logs and echoed in a header, so only printable characters are kept
+pub fn is_valid_request_id(value: &str) -> bool {
+    !value.is_empty()
+        && value.len() <= MAX_REQUEST_ID_LEN
+        && value.chars().all(|char| char.is_ascii_graphic())
+}
+
+pub fn is_valid_header_name(name: &str) -> bool {
+    !name.is_empty()
+        && name
+            .chars()
+            .all(|char| char.is_ascii_alphanumeric() || char == '-' || char == '_')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_generate_valid_request_ids() {
+        let request_id = generate_request_id();
+
+        assert_eq!(request_id.len(), 16);
+        assert!(is_valid_request_id(&request_id));
+        assert_ne!(request_id, generate_request_id());
+    }
+
+    #[test]
+    fn should_reject_invalid_request_ids() {
+        assert!(is_valid_request_id("trace-123"));
+
+        assert!(!is_valid_request_id(""));
+        assert!(!is_valid_request_id("with space"));
+        assert!(!is_valid_request_id("line\nbreak"));
+        assert!(!is_valid_request_id(&"a".repeat(MAX_REQUEST_ID_LEN + 1)));
+    }
+
+    #[test]
+    fn should_validate_header_names() {
+        assert!(is_valid_header_name(DEFAULT_REQUEST_ID_HEADER));
+        assert!(is_valid_header_name("X-Correlation-Id"));
+
+        assert!(!is_valid_header_name(""));
+        assert!(!is_valid_header_name("X Request Id"));
+        assert!(!is_valid_header_name("X-Request-Id:"));
+    }
+}
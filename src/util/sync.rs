@@ -0,0 +1,40 @@
+use std::sync::{Mutex, MutexGuard, RwLock, RwLockReadGuard, RwLockWriteGuard};
+
+// A thread that panics while holding a lock poisons it. The shared data is only replaced
+// once it is fully updated, so it stays consistent and the guard is recovered instead of
+// making every later access panic too.
+
+pub fn lock_recover<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+pub fn read_recover<T>(lock: &RwLock<T>) -> RwLockReadGuard<'_, T> {
+    lock.read().unwrap_or_else(|error| error.into_inner())
+}
+
+pub fn write_recover<T>(lock: &RwLock<T>) -> RwLockWriteGuard<'_, T> {
+    lock.write().unwrap_or_else(|error| error.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{panic, sync::Arc, thread};
+
+    use super::*;
+
+    #[test]
+    fn should_recover_poisoned_mutex() {
+        let mutex = Arc::new(Mutex::new(1));
+
+        let poisoning_mutex = mutex.clone();
+        let result = thread::spawn(move || {
+            let _guard = poisoning_mutex.lock().unwrap();
+            panic!("poisoning the mutex");
+        })
+        .join();
+
+        assert!(result.is_err());
+        assert!(mutex.is_poisoned());
+        assert_eq!(*lock_recover(&mutex), 1);
+    }
+}
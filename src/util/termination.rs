@@ -1,6 +1,37 @@
-pub fn set_ctrlc_handler() {
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use super::execution::sleep_millis;
+
+pub type ShutdownFlag = Arc<AtomicBool>;
+
+const SHUTDOWN_POLLING_MS: u64 = 100;
+
+pub fn set_ctrlc_handler(shutdown: ShutdownFlag) {
     ctrlc::set_handler(move || {
-        std::process::exit(0);
+        // a second Ctrl-C forces the exit if the threads take too long to stop
+        if shutdown.swap(true, Ordering::SeqCst) {
+            std::process::exit(1);
+        }
+
+        info!("Shutdown requested, stopping all threads");
     })
     .expect("Error setting Ctrl-C handler");
 }
+
+pub fn is_shutdown_requested(shutdown: &ShutdownFlag) -> bool {
+    shutdown.load(Ordering::SeqCst)
+}
+
+// Sleeps in short steps so a shutdown request is noticed without waiting for the whole period
+pub fn sleep_unless_shutdown(shutdown: &ShutdownFlag, millis: u64) {
+    let mut remaining = millis;
+
+    while remaining > 0 && !is_shutdown_requested(shutdown) {
+        let step = remaining.min(SHUTDOWN_POLLING_MS);
+        sleep_millis(step);
+        remaining -= step;
+    }
+}
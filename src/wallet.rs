@@ -0,0 +1,59 @@
+use crypto::{digest::Digest, ed25519, sha2::Sha256};
+
+use crate::model::Address;
+
+const SEED_LEN: usize = 32;
+const PUBLIC_KEY_LEN: usize = 32;
+
+pub struct Wallet {
+    private_key: [u8; SEED_LEN],
+    public_key: [u8; PUBLIC_KEY_LEN],
+}
+
+impl Wallet {
+    // Generates a new ed25519 keypair from a random seed, which acts as the private key
+    pub fn generate() -> Wallet {
+        let private_key: [u8; SEED_LEN] = rand::random();
+        let (_, public_key) = ed25519::keypair(&private_key);
+
+        Wallet {
+            private_key,
+            public_key,
+        }
+    }
+
+    pub fn private_key(&self) -> String {
+        hex::encode(self.private_key)
+    }
+
+    pub fn public_key(&self) -> String {
+        hex::encode(self.public_key)
+    }
+
+    // The address of a wallet is the SHA256 of its public key
+    pub fn address(&self) -> Address {
+        let mut hash = vec![0; 32];
+        let mut hasher = Sha256::new();
+
+        hasher.input(&self.public_key);
+        hasher.result(&mut hash);
+
+        Address::try_from(hash).unwrap()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn generated_address_round_trips() {
+        let wallet = Wallet::generate();
+        let address = wallet.address();
+
+        let parsed_address = Address::try_from(address.to_string()).unwrap();
+
+        assert_eq!(parsed_address, address);
+        assert_ne!(Wallet::generate().address(), address);
+    }
+}
@@ -0,0 +1,71 @@
+use std::time::Duration;
+
+use anyhow::Result;
+
+use crate::{
+    model::Blockchain,
+    util::{
+        execution::Runnable,
+        termination::{is_shutdown_requested, sleep_unless_shutdown, ShutdownFlag},
+        Context,
+    },
+};
+
+const MAX_CHECK_INTERVAL_MS: u64 = 1000;
+
+pub struct Watchdog {
+    blockchain: Blockchain,
+    stall_threshold_ms: u64,
+    shutdown: ShutdownFlag,
+}
+
+impl Runnable for Watchdog {
+    fn run(&self) -> Result<()> {
+        self.start()
+    }
+}
+
+impl Watchdog {
+    pub fn new(context: &Context) -> Watchdog {
+        Watchdog {
+            blockchain: context.blockchain.clone(),
+            stall_threshold_ms: context.config.stall_threshold_ms,
+            shutdown: context.shutdown.clone(),
+        }
+    }
+
+    fn check_interval_ms(&self) -> u64 {
+        self.stall_threshold_ms.min(MAX_CHECK_INTERVAL_MS)
+    }
+
+    pub fn start(&self) -> Result<()> {
+        if self.stall_threshold_ms == 0 {
+            info!("No stall threshold configured, exiting watchdog");
+
+            return Ok(());
+        }
+
+        let threshold = Duration::from_millis(self.stall_threshold_ms);
+        let mut was_stalled = false;
+
+        while !is_shutdown_requested(&self.shutdown) {
+            let is_stalled = self.blockchain.is_stalled(threshold);
+
+            if is_stalled && !was_stalled {
+                warn!(
+                    "No new block was added in the last {} ms, the chain looks stalled",
+                    self.blockchain.time_since_last_block().as_millis()
+                );
+            }
+
+            if !is_stalled && was_stalled {
+                info!("New block added, the chain is no longer stalled");
+            }
+
+            was_stalled = is_stalled;
+            sleep_unless_shutdown(&self.shutdown, self.check_interval_ms());
+        }
+
+        Ok(())
+    }
+}
@@ -1,8 +1,20 @@
+// integration tests only run on windows, so their imports are unused elsewhere
+#![cfg_attr(not(windows), allow(unused_imports))]
+
 mod common;
 
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
 use crate::common::{
-    Api, Block, BlockHash, ServerBuilder, Transaction, BLOCK_SUBSIDY, MINER_ADDRESS, PERSON1,
-    PERSON2,
+    current_timestamp, read_error, read_pushed_block, sha256, Api, Block, BlockHash, ServerBuilder,
+    Transaction, BLOCK_SUBSIDY, PERSON1, PERSON2,
+};
+use isahc::{
+    config::{Configurable, SslOption},
+    ReadResponseExt, Request, RequestExt,
 };
 use serial_test::serial;
 
@@ -23,16 +35,198 @@ fn test_should_get_a_valid_genesis_block() {
     assert!(genesis_block.transactions.is_empty());
 }
 
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_get_genesis_block_by_index_and_hash() {
+    let node = ServerBuilder::new().start();
+    let genesis_block = node.get_last_block();
+
+    let mut res = node.get_block_by_index(0);
+    assert_eq!(res.status().as_u16(), 200);
+    let block: Block = serde_json::from_str(&res.text().unwrap()).unwrap();
+    assert_eq!(block, genesis_block);
+
+    let mut res = node.get_block_by_hash(&genesis_block.hash);
+    assert_eq!(res.status().as_u16(), 200);
+    let block: Block = serde_json::from_str(&res.text().unwrap()).unwrap();
+    assert_eq!(block, genesis_block);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_get_blocks_after_hash() {
+    let node = ServerBuilder::new().start();
+    node.add_valid_block();
+    let checkpoint = node.get_last_block();
+    node.add_valid_block();
+    let last_block = node.get_last_block();
+
+    let mut res = node.get_blocks_after_hash(&checkpoint.hash);
+    assert_eq!(res.status().as_u16(), 200);
+    let blocks: Vec<Block> = serde_json::from_str(&res.text().unwrap()).unwrap();
+    assert_eq!(blocks, vec![last_block]);
+
+    let res = node.get_blocks_after_hash(&BlockHash::MAX);
+    assert_eq!(res.status().as_u16(), 404);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_reuse_blocks_response_until_new_block() {
+    let node = ServerBuilder::new().start();
+    node.add_valid_block();
+
+    let first = node.get_blocks_bytes();
+    let second = node.get_blocks_bytes();
+    assert_eq!(first, second);
+
+    node.add_valid_block();
+    let bytes = node.get_blocks_bytes();
+    let blocks: Vec<Block> = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(blocks.len(), 3);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_get_raw_block() {
+    let node = ServerBuilder::new().start();
+    node.add_valid_block();
+    let last_block = node.get_last_block();
+
+    let mut res = node.get_raw_block(1);
+    assert_eq!(res.status().as_u16(), 200);
+
+    // anyone can recompute the hash from the bytes, which leave the hash out
+    let bytes = hex::decode(res.text().unwrap()).unwrap();
+    assert_eq!(sha256(&bytes), last_block.hash);
+
+    let block: Block = serde_json::from_slice(&bytes).unwrap();
+    assert_eq!(block.hash, BlockHash::default());
+    assert_eq!(block.transactions, last_block.transactions);
+
+    let res = node.get_raw_block(2);
+    assert_eq!(res.status().as_u16(), 404);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_not_find_nonexistent_block() {
+    let node = ServerBuilder::new().start();
+
+    let res = node.get_block_by_index(1);
+    assert_eq!(res.status().as_u16(), 404);
+
+    let res = node.get_block_by_hash(&BlockHash::MAX);
+    assert_eq!(res.status().as_u16(), 404);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_report_status_of_a_fresh_node() {
+    let difficulty = 5;
+    let node = ServerBuilder::new().difficulty(difficulty).start();
+
+    let status = node.get_status();
+
+    assert_eq!(status.height, 0);
+    assert_eq!(status.difficulty, difficulty);
+    assert_eq!(status.peer_count, 0);
+    assert_eq!(status.mempool_size, 0);
+    assert!(!status.stalled);
+    assert_eq!(status.hashrate, 0.0);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_serve_api_under_base_path() {
+    let node = ServerBuilder::new().api_base_path("/node1").start();
+
+    // the helpers request the urls under the base path
+    assert_eq!(node.get_blocks().len(), 1);
+    assert_eq!(node.add_valid_block().status().as_u16(), 200);
+    assert_eq!(node.get_status().height, 1);
+
+    let uri = format!("http://localhost:{}/blocks", node.config.port);
+    let res = isahc::get(uri).unwrap();
+    assert_eq!(res.status().as_u16(), 404);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_describe_block_for_explorers() {
+    // keep the miner waiting so only the requested block is mined
+    let node = ServerBuilder::new().transaction_waiting_ms(60000).start();
+    let genesis_block = node.get_last_block();
+
+    let mut res = node.mine();
+    assert_eq!(res.status().as_u16(), 200);
+    let mined_block: Block = serde_json::from_str(&res.text().unwrap()).unwrap();
+
+    let mut res = node.get_explorer_block(1);
+    assert_eq!(res.status().as_u16(), 200);
+    let explorer_block: serde_json::Value = serde_json::from_str(&res.text().unwrap()).unwrap();
+
+    let block: Block = serde_json::from_value(explorer_block["block"].clone()).unwrap();
+    assert_eq!(block, mined_block);
+    assert_eq!(
+        explorer_block["hash"],
+        format!("{:0>64}", format!("{:x}", mined_block.hash))
+    );
+    assert_eq!(
+        explorer_block["leading_zeros"],
+        mined_block.hash.leading_zeros()
+    );
+    assert_eq!(
+        explorer_block["time_since_previous_ms"],
+        mined_block.timestamp - genesis_block.timestamp
+    );
+    assert_eq!(explorer_block["coinbase_reward"], BLOCK_SUBSIDY);
+    assert_eq!(explorer_block["transaction_count"], 1);
+
+    let genesis_explorer_block: serde_json::Value =
+        serde_json::from_str(&node.get_explorer_block(0).text().unwrap()).unwrap();
+    assert!(genesis_explorer_block["time_since_previous_ms"].is_null());
+    assert!(genesis_explorer_block["coinbase_reward"].is_null());
+
+    assert_eq!(node.get_explorer_block(2).status().as_u16(), 404);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_report_difficulty_as_compact_bits() {
+    let node = ServerBuilder::new().difficulty(5).start();
+
+    let difficulty = node.get_difficulty();
+
+    assert_eq!(difficulty["difficulty"], 5);
+    assert_eq!(difficulty["bits"], "2007ffff");
+
+    let target: BlockHash = serde_json::from_value(difficulty["target"].clone()).unwrap();
+    assert_eq!(target.leading_zeros(), 5);
+}
+
 #[test]
 #[serial]
 #[cfg(windows)]
 fn test_should_let_add_transactions() {
     let mut node = ServerBuilder::new().start();
-    let genesis_block = node.get_last_block();
+
+    // transfers are only accepted from accounts with confirmed funds
+    node.add_valid_block();
+    let funded_block = node.get_last_block();
 
     let transaction = Transaction {
-        sender: MINER_ADDRESS.to_string(),
-        recipient: PERSON2.to_string(),
+        sender: PERSON2.to_string(),
+        recipient: PERSON1.to_string(),
         amount: 10 as u64,
     };
     let res = node.add_transaction(&transaction);
@@ -43,18 +237,120 @@ fn test_should_let_add_transactions() {
 
     let blocks = node.get_blocks();
 
-    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks.len(), 3);
 
     let mined_block = blocks.last().unwrap();
 
-    assert_eq!(mined_block.index, 1);
-    assert_eq!(mined_block.previous_hash, genesis_block.hash);
+    assert_eq!(mined_block.index, 2);
+    assert_eq!(mined_block.previous_hash, funded_block.hash);
     assert_eq!(mined_block.transactions.len(), 2);
 
     let mined_transaction = mined_block.transactions.last().unwrap();
     assert_eq!(*mined_transaction, transaction);
 }
 
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_not_let_add_invalid_transactions() {
+    let node = ServerBuilder::new().start();
+
+    let zero_amount = Transaction {
+        sender: PERSON1.to_string(),
+        recipient: PERSON2.to_string(),
+        amount: 0,
+    };
+    let res = node.add_transaction(&zero_amount);
+    assert_eq!(res.status().as_u16(), 400);
+
+    let self_transfer = Transaction {
+        sender: PERSON1.to_string(),
+        recipient: PERSON1.to_string(),
+        amount: 10,
+    };
+    let res = node.add_transaction(&self_transfer);
+    assert_eq!(res.status().as_u16(), 400);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_not_let_add_unaffordable_transactions() {
+    let node = ServerBuilder::new().transaction_waiting_ms(60000).start();
+    node.add_valid_block();
+
+    let transaction = Transaction {
+        sender: PERSON2.to_string(),
+        recipient: PERSON1.to_string(),
+        amount: BLOCK_SUBSIDY + 1,
+    };
+    let mut res = node.add_transaction(&transaction);
+
+    assert_eq!(res.status().as_u16(), 400);
+    let error = read_error(&mut res);
+    assert_eq!(error.error, "InsufficientFunds");
+    assert_eq!(error.message, "Insufficient funds");
+    assert_eq!(node.get_pending_transactions().count, 0);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_list_pending_transactions() {
+    // keep the miner waiting so the transactions stay in the pool
+    let node = ServerBuilder::new().transaction_waiting_ms(60000).start();
+    node.add_valid_block();
+
+    let transaction = Transaction {
+        sender: PERSON2.to_string(),
+        recipient: PERSON1.to_string(),
+        amount: 10,
+    };
+
+    for _ in 0..2 {
+        let res = node.add_transaction(&transaction);
+        assert_eq!(res.status().as_u16(), 200);
+    }
+
+    let pending = node.get_pending_transactions();
+
+    assert_eq!(pending.count, 2);
+    assert_eq!(pending.transactions, vec![transaction.clone(), transaction]);
+    assert_eq!(node.get_blocks().len(), 2);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_accept_valid_transactions_of_a_batch() {
+    let node = ServerBuilder::new().transaction_waiting_ms(60000).start();
+    node.add_valid_block();
+
+    let valid_transaction = Transaction {
+        sender: PERSON2.to_string(),
+        recipient: PERSON1.to_string(),
+        amount: 10,
+    };
+    let zero_amount = Transaction {
+        sender: PERSON1.to_string(),
+        recipient: PERSON2.to_string(),
+        amount: 0,
+    };
+    let batch = serde_json::json!([valid_transaction, zero_amount, {"sender": "not-an-address"}]);
+
+    let results = node.add_transaction_batch(&batch);
+
+    let expected = serde_json::json!([
+        {"accepted": true},
+        {"accepted": false, "error": "ZeroAmount"},
+        {"accepted": false, "error": "InvalidTransaction"}
+    ]);
+    assert_eq!(results, expected);
+
+    let pending = node.get_pending_transactions();
+    assert_eq!(pending.transactions, vec![valid_transaction]);
+}
+
 #[test]
 #[serial]
 #[cfg(windows)]
@@ -69,8 +365,10 @@ fn test_should_let_add_valid_block() {
     };
 
     let valid_block = Block {
+        version: 2,
         index: 1,
-        timestamp: 0,
+        timestamp: current_timestamp(),
+        difficulty: 0,
         nonce: 0,
         previous_hash: genesis_block.hash,
         hash: BlockHash::default(),
@@ -82,15 +380,76 @@ fn test_should_let_add_valid_block() {
     assert_eq!(res.status().as_u16(), 200);
 }
 
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_acknowledge_already_known_block() {
+    let mut node = ServerBuilder::new().start();
+    let genesis_block = node.get_last_block();
+
+    let coinbase = Transaction {
+        sender: PERSON1.to_string(),
+        recipient: PERSON1.to_string(),
+        amount: BLOCK_SUBSIDY,
+    };
+
+    let valid_block = Block {
+        version: 2,
+        index: 1,
+        timestamp: current_timestamp(),
+        difficulty: 0,
+        nonce: 0,
+        previous_hash: genesis_block.hash,
+        hash: BlockHash::default(),
+        transactions: vec![coinbase],
+    };
+
+    let mut res = node.add_block(&valid_block);
+    assert_eq!(res.status().as_u16(), 200);
+    assert_eq!(res.text().unwrap(), r#"{"known":false}"#);
+    node.wait_to_receive_block_in_api();
+
+    let mut res = node.add_block(&valid_block);
+    assert_eq!(res.status().as_u16(), 200);
+    assert_eq!(res.text().unwrap(), r#"{"known":true}"#);
+
+    assert_eq!(node.count_log_messages("Received new block"), 1);
+    assert!(node.get_rejected_blocks().is_empty());
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_paginate_blocks() {
+    let node = ServerBuilder::new().start();
+
+    node.add_valid_block();
+    node.add_valid_block();
+
+    let blocks = node.get_blocks_range(1, 1);
+    assert_eq!(blocks.len(), 1);
+    assert_eq!(blocks[0].index, 1);
+
+    let blocks = node.get_blocks_range(1, 10);
+    assert_eq!(blocks.len(), 2);
+    assert_eq!(blocks[1].index, 2);
+
+    let blocks = node.get_blocks_range(10, 10);
+    assert!(blocks.is_empty());
+}
+
 #[test]
 #[serial]
 #[cfg(windows)]
 fn test_should_not_let_add_invalid_block() {
     let node = ServerBuilder::new().start();
 
+    // with a zero timestamp the block would be the genesis block, which is accepted as known
     let invalid_block = Block {
+        version: 2,
         index: 0,
-        timestamp: 0,
+        timestamp: 1,
+        difficulty: 0,
         nonce: 0,
         previous_hash: BlockHash::default(),
         hash: BlockHash::default(),
@@ -101,3 +460,499 @@ fn test_should_not_let_add_invalid_block() {
 
     assert_eq!(res.status().as_u16(), 400);
 }
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_name_the_error_of_an_invalid_block() {
+    let node = ServerBuilder::new().start();
+    let genesis_block = node.get_last_block();
+
+    let mut invalid_block = Block {
+        version: 2,
+        index: genesis_block.index + 2,
+        timestamp: current_timestamp(),
+        difficulty: 0,
+        nonce: 0,
+        previous_hash: genesis_block.hash,
+        hash: BlockHash::default(),
+        transactions: [].to_vec(),
+    };
+    invalid_block.hash = node.calculate_hash(&invalid_block);
+
+    let mut res = node.add_block(&invalid_block);
+
+    assert_eq!(res.status().as_u16(), 400);
+    let error = read_error(&mut res);
+    assert_eq!(error.error, "InvalidIndex");
+    assert!(!error.message.is_empty());
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_rate_limit_fast_block_senders() {
+    // a block every 20 seconds, so the bucket does not refill while the test posts
+    let node = ServerBuilder::new().max_blocks_per_minute(3).start();
+    let genesis_block = node.get_last_block();
+
+    let block = Block {
+        version: 2,
+        index: genesis_block.index + 2,
+        timestamp: current_timestamp(),
+        difficulty: 0,
+        nonce: 0,
+        previous_hash: genesis_block.hash,
+        hash: BlockHash::default(),
+        transactions: [].to_vec(),
+    };
+
+    // the bucket holds a minute worth of blocks, the next ones are refused
+    let statuses: Vec<u16> = (0..5)
+        .map(|_| node.add_block(&block).status().as_u16())
+        .collect();
+    assert_eq!(statuses, vec![400, 400, 400, 429, 429]);
+
+    // reading blocks is not limited
+    assert_eq!(node.get_blocks().len(), 1);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_add_blocks_received_before_their_parent() {
+    let node = ServerBuilder::new().start();
+    let genesis_block = node.get_last_block();
+
+    let coinbase = Transaction {
+        sender: PERSON1.to_string(),
+        recipient: PERSON2.to_string(),
+        amount: BLOCK_SUBSIDY,
+    };
+    let mut block = Block {
+        version: 2,
+        index: genesis_block.index + 1,
+        timestamp: current_timestamp(),
+        difficulty: 0,
+        nonce: 0,
+        previous_hash: genesis_block.hash,
+        hash: BlockHash::default(),
+        transactions: vec![coinbase.clone()],
+    };
+    block.hash = node.calculate_hash(&block);
+
+    let next_block = Block {
+        index: block.index + 1,
+        timestamp: block.timestamp + 1,
+        previous_hash: block.hash,
+        ..block.clone()
+    };
+
+    assert_eq!(node.add_block(&next_block).status().as_u16(), 200);
+    assert_eq!(node.get_blocks().len(), 1);
+
+    assert_eq!(node.add_block(&block).status().as_u16(), 200);
+    let blocks = node.get_blocks();
+    assert_eq!(blocks.len(), 3);
+    assert_eq!(blocks[2].previous_hash, blocks[1].hash);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_verify_mined_chain() {
+    let mut node = ServerBuilder::new().start();
+
+    let transaction = Transaction {
+        sender: PERSON2.to_string(),
+        recipient: PERSON1.to_string(),
+        amount: 10,
+    };
+    node.add_valid_block();
+    node.add_transaction(&transaction);
+    node.wait_for_mining();
+
+    assert_eq!(node.get_blocks().len(), 3);
+    assert_eq!(node.verify_chain(), serde_json::json!({ "valid": true }));
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_record_rejected_block() {
+    let node = ServerBuilder::new().start();
+    let genesis_block = node.get_last_block();
+
+    let invalid_block = Block {
+        version: 2,
+        index: 5,
+        timestamp: current_timestamp(),
+        difficulty: 0,
+        nonce: 0,
+        previous_hash: genesis_block.hash,
+        hash: BlockHash::default(),
+        transactions: [].to_vec(),
+    };
+
+    let res = node.add_block(&invalid_block);
+    assert_eq!(res.status().as_u16(), 400);
+
+    let rejected_blocks = node.get_rejected_blocks();
+    assert_eq!(rejected_blocks.len(), 1);
+    assert_eq!(rejected_blocks[0].block.index, 5);
+    assert_eq!(rejected_blocks[0].reason, "Invalid index");
+    assert!(["127.0.0.1", "::1"].contains(&rejected_blocks[0].source.as_str()));
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_expose_mining_metrics() {
+    let mut node = ServerBuilder::new().start();
+    node.add_valid_block();
+
+    let transaction = Transaction {
+        sender: PERSON2.to_string(),
+        recipient: PERSON1.to_string(),
+        amount: 10,
+    };
+    node.add_transaction(&transaction);
+    node.wait_for_mining();
+
+    let blocks_mined = |metrics: &str| -> u64 {
+        metrics
+            .lines()
+            .find_map(|line| line.strip_prefix("blocks_mined_total "))
+            .unwrap()
+            .parse()
+            .unwrap()
+    };
+
+    // the counter is incremented right after the mining log message
+    let start = Instant::now();
+    let mut metrics = node.get_metrics();
+    while blocks_mined(&metrics) == 0 && start.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(50));
+        metrics = node.get_metrics();
+    }
+
+    assert!(blocks_mined(&metrics) >= 1);
+    assert!(metrics.contains("transactions_received_total 1"));
+    assert!(metrics.contains("blockchain_height 2"));
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_calculate_hash_of_known_block() {
+    let node = ServerBuilder::new().start();
+    node.add_valid_block();
+
+    let block = node.get_last_block();
+    let hash = node.calculate_hash(&block);
+
+    assert_eq!(hash, block.hash);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_not_find_missing_transaction() {
+    let node = ServerBuilder::new().start();
+
+    let res = node.get_transaction("0x1234");
+    assert_eq!(res.status().as_u16(), 404);
+
+    let res = node.get_transaction("not-a-hash");
+    assert_eq!(res.status().as_u16(), 400);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_summarize_recent_blocks() {
+    let node = ServerBuilder::new().start();
+
+    for _ in 0..3 {
+        node.add_valid_block();
+    }
+
+    let last_block = node.get_last_block();
+    let recent_blocks = node.get_recent_blocks(2);
+
+    assert_eq!(recent_blocks.len(), 2);
+    assert_eq!(recent_blocks[0].height, 3);
+    assert_eq!(recent_blocks[1].height, 2);
+
+    assert_eq!(recent_blocks[0].timestamp, last_block.timestamp);
+    assert_eq!(recent_blocks[0].transaction_count, 1);
+    assert_eq!(recent_blocks[0].miner, Some(PERSON2.to_string()));
+
+    let hash = format!("{:064x}", last_block.hash);
+    assert_eq!(recent_blocks[0].short_hash, hash[..16]);
+
+    let recent_blocks = node.get_recent_blocks(10);
+    assert_eq!(recent_blocks.len(), 4);
+    assert_eq!(recent_blocks[3].miner, None);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_push_mined_blocks_over_websocket() {
+    let mut node = ServerBuilder::new().start();
+    node.add_valid_block();
+    let mut socket = node.subscribe_blocks();
+
+    let transaction = Transaction {
+        sender: PERSON2.to_string(),
+        recipient: PERSON1.to_string(),
+        amount: 10,
+    };
+    node.add_transaction(&transaction);
+    node.wait_for_mining();
+
+    let pushed_block = read_pushed_block(&mut socket);
+
+    assert_eq!(pushed_block.index, 2);
+    assert_eq!(pushed_block, node.get_last_block());
+    assert_eq!(*pushed_block.transactions.last().unwrap(), transaction);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_serve_api_over_tls() {
+    let node = ServerBuilder::new().tls().start();
+
+    let uri = format!("https://localhost:{}/status", node.config.port);
+    let mut res = Request::get(uri)
+        .ssl_options(SslOption::DANGER_ACCEPT_INVALID_CERTS)
+        .body(())
+        .unwrap()
+        .send()
+        .unwrap();
+
+    assert_eq!(res.status().as_u16(), 200);
+    assert!(res.text().unwrap().contains("\"height\":0"));
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_allow_configured_cors_origins() {
+    let node = ServerBuilder::new()
+        .cors_allowed_origins("http://dashboard.local")
+        .start();
+
+    let uri = format!("http://localhost:{}/status", node.config.port);
+    let get_with_origin = |origin: &str| {
+        Request::get(&uri)
+            .header("Origin", origin)
+            .body(())
+            .unwrap()
+            .send()
+            .unwrap()
+    };
+
+    let res = get_with_origin("http://dashboard.local");
+    assert_eq!(res.status().as_u16(), 200);
+    assert_eq!(
+        res.headers()["Access-Control-Allow-Origin"],
+        "http://dashboard.local"
+    );
+
+    let res = get_with_origin("http://other.local");
+    assert!(res.headers().get("Access-Control-Allow-Origin").is_none());
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_echo_request_id() {
+    let mut node = ServerBuilder::new().start();
+
+    let uri = format!("http://localhost:{}/status", node.config.port);
+    let res = Request::get(&uri)
+        .header("X-Request-Id", "trace-123")
+        .body(())
+        .unwrap()
+        .send()
+        .unwrap();
+
+    assert_eq!(res.status().as_u16(), 200);
+    assert_eq!(res.headers()["X-Request-Id"], "trace-123");
+    node.wait_for_log_message("[trace-123]");
+    assert_eq!(node.count_log_messages("GET /status 200"), 1);
+
+    // requests without an id are given one
+    let res = isahc::get(&uri).unwrap();
+    let request_id = res.headers()["X-Request-Id"].to_str().unwrap();
+    assert_eq!(request_id.len(), 16);
+    assert_ne!(request_id, "trace-123");
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_mine_pending_transactions_on_demand() {
+    // keep the miner waiting so only the requested block is mined
+    let node = ServerBuilder::new().transaction_waiting_ms(60000).start();
+    node.add_valid_block();
+
+    let transaction = Transaction {
+        sender: PERSON2.to_string(),
+        recipient: PERSON1.to_string(),
+        amount: 10,
+    };
+    let res = node.add_transaction(&transaction);
+    assert_eq!(res.status().as_u16(), 200);
+
+    let mut res = node.mine();
+    assert_eq!(res.status().as_u16(), 200);
+
+    let mined_block: Block = serde_json::from_str(&res.text().unwrap()).unwrap();
+    assert_eq!(mined_block.index, 2);
+    assert_eq!(mined_block.transactions.len(), 2);
+    assert_eq!(mined_block.transactions[1], transaction);
+
+    assert_eq!(node.get_last_block(), mined_block);
+    assert_eq!(node.get_pending_transactions().count, 0);
+    assert!(node.get_status().hashrate > 0.0);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_return_hash_of_submitted_transaction() {
+    let node = ServerBuilder::new().transaction_waiting_ms(60000).start();
+    node.add_valid_block();
+
+    let transaction = Transaction {
+        sender: PERSON2.to_string(),
+        recipient: PERSON1.to_string(),
+        amount: 10,
+    };
+    let mut res = node.add_transaction(&transaction);
+    assert_eq!(res.status().as_u16(), 200);
+
+    let body: serde_json::Value = serde_json::from_str(&res.text().unwrap()).unwrap();
+    let hash = body["hash"].as_str().unwrap().to_string();
+    assert!(hash.starts_with("0x"));
+
+    // the hash finds the transaction once it is mined
+    assert_eq!(node.get_transaction(&hash).status().as_u16(), 404);
+    node.mine();
+
+    let mut res = node.get_transaction(&hash);
+    assert_eq!(res.status().as_u16(), 200);
+    let confirmed: serde_json::Value = serde_json::from_str(&res.text().unwrap()).unwrap();
+    assert_eq!(confirmed["block_index"], 2);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_count_transaction_confirmations() {
+    let node = ServerBuilder::new().transaction_waiting_ms(60000).start();
+    node.add_valid_block();
+
+    let transaction = Transaction {
+        sender: PERSON2.to_string(),
+        recipient: PERSON1.to_string(),
+        amount: 10,
+    };
+    let mut res = node.add_transaction(&transaction);
+    let body: serde_json::Value = serde_json::from_str(&res.text().unwrap()).unwrap();
+    let hash = body["hash"].as_str().unwrap().to_string();
+    node.mine();
+
+    let mut res = node.get_transaction(&hash);
+    let confirmed: serde_json::Value = serde_json::from_str(&res.text().unwrap()).unwrap();
+    assert_eq!(confirmed["confirmations"], 1);
+    assert_eq!(confirmed["final"], false);
+
+    // each block on top of it adds a confirmation, until the default depth of 6 is reached
+    for confirmations in 2..=6 {
+        node.mine();
+
+        let mut res = node.get_transaction(&hash);
+        let confirmed: serde_json::Value = serde_json::from_str(&res.text().unwrap()).unwrap();
+        assert_eq!(confirmed["confirmations"], confirmations);
+        assert_eq!(confirmed["final"], confirmations == 6);
+    }
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_download_snapshot() {
+    let node = ServerBuilder::new().transaction_waiting_ms(60000).start();
+    node.add_valid_block();
+    let last_block = node.get_last_block();
+
+    let snapshot = node.get_snapshot();
+
+    assert_eq!(snapshot["tip_index"], 1);
+    assert_eq!(snapshot["tip_block"]["index"], 1);
+    assert!(snapshot.get("blocks").is_none());
+    assert_eq!(
+        serde_json::from_value::<BlockHash>(snapshot["tip_hash"].clone()).unwrap(),
+        last_block.hash
+    );
+    assert_eq!(
+        snapshot["account_balances"]["balances"][PERSON2],
+        BLOCK_SUBSIDY
+    );
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_answer_json_rpc_calls() {
+    let node = ServerBuilder::new().json_rpc().start();
+    node.add_valid_block();
+    let last_block = node.get_last_block();
+
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "getBlockByIndex",
+        "params": [1],
+        "id": 7
+    });
+    let mut res = node.call_rpc(&request);
+    assert_eq!(res.status().as_u16(), 200);
+
+    let body: serde_json::Value = serde_json::from_str(&res.text().unwrap()).unwrap();
+    assert_eq!(body["jsonrpc"], "2.0");
+    assert_eq!(body["id"], 7);
+    assert!(body.get("error").is_none());
+
+    let block: Block = serde_json::from_value(body["result"].clone()).unwrap();
+    assert_eq!(block, last_block);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_answer_unknown_json_rpc_methods_with_an_error() {
+    let node = ServerBuilder::new().json_rpc().start();
+
+    let request = serde_json::json!({
+        "jsonrpc": "2.0",
+        "method": "getEverything",
+        "id": "abc"
+    });
+    let mut res = node.call_rpc(&request);
+    assert_eq!(res.status().as_u16(), 200);
+
+    let body: serde_json::Value = serde_json::from_str(&res.text().unwrap()).unwrap();
+    assert_eq!(body["jsonrpc"], "2.0");
+    assert_eq!(body["id"], "abc");
+    assert_eq!(body["error"]["code"], -32601);
+    assert!(body.get("result").is_none());
+
+    // without the setting the endpoint is not served at all
+    let node = ServerBuilder::new().port(8001).start();
+    assert_eq!(node.call_rpc(&request).status().as_u16(), 404);
+}
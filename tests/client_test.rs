@@ -0,0 +1,92 @@
+#![cfg(feature = "client")]
+// integration tests only run on windows, so their imports and helpers are unused elsewhere
+#![cfg_attr(not(windows), allow(unused_imports, dead_code))]
+
+mod common;
+
+use std::str::FromStr;
+
+use common::{ServerBuilder, PERSON1, PERSON2};
+use rust_blockchain::{
+    client::{ClientError, NodeClient},
+    model::{Address, Block, Transaction, BLOCK_SUBSIDY},
+};
+use serial_test::serial;
+
+fn create_client(port: u16) -> NodeClient {
+    NodeClient::new(&format!("http://localhost:{}/", port)).unwrap()
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_client_reads_blocks_and_balances() {
+    let node = ServerBuilder::new().transaction_waiting_ms(60000).start();
+    let client = create_client(node.config.port);
+
+    let blocks = client.get_blocks().unwrap();
+    assert_eq!(blocks.len(), 1);
+
+    let genesis_block = client.get_last_block().unwrap();
+    assert_eq!(genesis_block.index, 0);
+
+    let person1 = Address::from_str(PERSON1).unwrap();
+    let person2 = Address::from_str(PERSON2).unwrap();
+    assert_eq!(client.get_balance(&person1).unwrap(), 0);
+
+    let coinbase = Transaction {
+        sender: Address::default(),
+        recipient: person1.clone(),
+        amount: BLOCK_SUBSIDY,
+    };
+    let block = Block::new(1, 0, genesis_block.hash, vec![coinbase]);
+    client.submit_block(&block).unwrap();
+
+    assert_eq!(client.get_last_block().unwrap().index, 1);
+    assert_eq!(client.get_balance(&person1).unwrap(), BLOCK_SUBSIDY);
+
+    let transaction = Transaction {
+        sender: person1,
+        recipient: person2,
+        amount: 10,
+    };
+    let hash = client.submit_transaction(&transaction).unwrap();
+    assert_eq!(hash, transaction.hash());
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_client_reads_numbers_as_strings() {
+    let node = ServerBuilder::new().numbers_as_strings().start();
+    let client = create_client(node.config.port);
+
+    let genesis_block = client.get_last_block().unwrap();
+    assert_eq!(genesis_block.index, 0);
+
+    let person1 = Address::from_str(PERSON1).unwrap();
+    assert_eq!(client.get_balance(&person1).unwrap(), 0);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_client_reports_rejections() {
+    let node = ServerBuilder::new().start();
+    let client = create_client(node.config.port);
+
+    let transaction = Transaction {
+        sender: Address::from_str(PERSON1).unwrap(),
+        recipient: Address::from_str(PERSON2).unwrap(),
+        amount: 10,
+    };
+    let result = client.submit_transaction(&transaction);
+
+    match result {
+        Err(ClientError::BadStatus(status, message)) => {
+            assert_eq!(status, 400);
+            assert_eq!(message, "Insufficient funds");
+        }
+        _ => panic!("expected the transaction to be rejected"),
+    }
+}
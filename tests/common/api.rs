@@ -1,6 +1,11 @@
+use std::{net::TcpStream, time::Duration};
+
+use chrono::Utc;
+use crypto::{digest::Digest, sha2::Sha256};
 use ethereum_types::U256;
 use isahc::{Body, ReadResponseExt, Request, Response};
 use serde::{Deserialize, Serialize};
+use tungstenite::{stream::MaybeTlsStream, WebSocket};
 
 use super::Server;
 
@@ -15,25 +20,130 @@ pub struct Transaction {
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Block {
+    pub version: u32,
     pub index: u64,
     pub timestamp: u64,
+    pub difficulty: u32,
     pub nonce: u64,
     pub previous_hash: BlockHash,
     pub hash: BlockHash,
     pub transactions: Vec<Transaction>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PendingTransactions {
+    pub count: usize,
+    pub transactions: Vec<Transaction>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct RejectedBlock {
+    pub block: Block,
+    pub reason: String,
+    pub source: String,
+    pub rejected_at: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BlockSummary {
+    pub height: u64,
+    pub short_hash: String,
+    pub timestamp: u64,
+    pub transaction_count: usize,
+    pub miner: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct PeerInfo {
+    pub address: String,
+    pub reachable: bool,
+    pub last_seen: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Status {
+    pub height: u64,
+    pub difficulty: u32,
+    pub peer_count: usize,
+    pub mempool_size: usize,
+    pub stalled: bool,
+    pub hashrate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct ErrorResponse {
+    pub error: String,
+    pub message: String,
+}
+
 pub const PERSON1: &str = "f780b958227ff0bf5795ede8f9f7eaac67e7e06666b043a400026cbd421ce28e";
 pub const PERSON2: &str = "51df097c03c0a6e64e54a6fce90cb6968adebd85955917ed438e3d3c05f2f00f";
 
 pub const BLOCK_SUBSIDY: u64 = 100;
 
+pub type BlockSocket = WebSocket<MaybeTlsStream<TcpStream>>;
+
+// Reads the next block pushed by the node, failing if none arrives in time
+pub fn read_pushed_block(socket: &mut BlockSocket) -> Block {
+    loop {
+        let message = socket.read().unwrap();
+
+        if message.is_text() {
+            return serde_json::from_str(message.to_text().unwrap()).unwrap();
+        }
+    }
+}
+
+pub fn read_error(response: &mut Response<Body>) -> ErrorResponse {
+    serde_json::from_str(&response.text().unwrap()).unwrap()
+}
+
+pub fn current_timestamp() -> u64 {
+    Utc::now().timestamp_millis() as u64
+}
+
+// Hashes bytes like the node does, to check its block hashes independently
+pub fn sha256(bytes: &[u8]) -> BlockHash {
+    let mut byte_hash = <[u8; 32]>::default();
+    let mut hasher = Sha256::new();
+
+    hasher.input(bytes);
+    hasher.result(&mut byte_hash);
+
+    BlockHash::from(byte_hash)
+}
+
 pub trait Api {
     fn get_blocks(&self) -> Vec<Block>;
+    fn get_blocks_range(&self, from: usize, limit: usize) -> Vec<Block>;
+    fn get_blocks_bytes(&self) -> Vec<u8>;
     fn get_last_block(&self) -> Block;
+    fn get_recent_blocks(&self, n: usize) -> Vec<BlockSummary>;
+    fn get_block_by_index(&self, index: u64) -> Response<Body>;
+    fn get_block_by_hash(&self, hash: &BlockHash) -> Response<Body>;
+    fn get_raw_block(&self, index: u64) -> Response<Body>;
+    fn get_blocks_after_hash(&self, hash: &BlockHash) -> Response<Body>;
     fn add_block(&self, block: &Block) -> Response<Body>;
     fn add_valid_block(&self) -> Response<Body>;
+    fn add_valid_block_with_request_id(&self, request_id: &str) -> Response<Body>;
     fn add_transaction(&self, transaction: &Transaction) -> Response<Body>;
+    fn mine(&self) -> Response<Body>;
+    fn add_transaction_batch(&self, transactions: &serde_json::Value) -> serde_json::Value;
+    fn get_pending_transactions(&self) -> PendingTransactions;
+    fn get_transaction(&self, hash: &str) -> Response<Body>;
+    fn get_status(&self) -> Status;
+    fn get_snapshot(&self) -> serde_json::Value;
+    fn verify_chain(&self) -> serde_json::Value;
+    fn get_difficulty(&self) -> serde_json::Value;
+    fn get_explorer_block(&self, index: u64) -> Response<Body>;
+    fn get_peers(&self) -> Vec<PeerInfo>;
+    fn add_peer(&self, address: &str) -> Response<Body>;
+    fn get_rejected_blocks(&self) -> Vec<RejectedBlock>;
+    fn get_metrics(&self) -> String;
+    fn calculate_hash(&self, block: &Block) -> BlockHash;
+    fn subscribe_blocks(&self) -> BlockSocket;
+    fn call_rpc(&self, request: &serde_json::Value) -> Response<Body>;
 }
 
 impl Api for Server {
@@ -49,29 +159,85 @@ impl Api for Server {
         blocks
     }
 
+    fn get_blocks_range(&self, from: usize, limit: usize) -> Vec<Block> {
+        let uri = format!(
+            "{}/blocks?from={}&limit={}",
+            get_base_url(self),
+            from,
+            limit
+        );
+        let mut response = isahc::get(uri).unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+
+        let raw_body = response.text().unwrap();
+
+        serde_json::from_str(&raw_body).unwrap()
+    }
+
+    fn get_blocks_bytes(&self) -> Vec<u8> {
+        let uri = format!("{}/blocks", get_base_url(self));
+        let mut response = isahc::get(uri).unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+
+        response.bytes().unwrap()
+    }
+
     fn get_last_block(&self) -> Block {
         self.get_blocks().last().unwrap().to_owned()
     }
 
+    fn get_recent_blocks(&self, n: usize) -> Vec<BlockSummary> {
+        let uri = format!("{}/blocks/recent?n={}", get_base_url(self), n);
+        let mut response = isahc::get(uri).unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+
+        let raw_body = response.text().unwrap();
+
+        serde_json::from_str(&raw_body).unwrap()
+    }
+
+    fn get_block_by_index(&self, index: u64) -> Response<Body> {
+        let uri = format!("{}/blocks/{}", get_base_url(self), index);
+
+        isahc::get(uri).unwrap()
+    }
+
+    fn get_block_by_hash(&self, hash: &BlockHash) -> Response<Body> {
+        let uri = format!("{}/blocks/hash/{:#x}", get_base_url(self), hash);
+
+        isahc::get(uri).unwrap()
+    }
+
+    fn get_raw_block(&self, index: u64) -> Response<Body> {
+        let uri = format!("{}/blocks/{}/raw", get_base_url(self), index);
+
+        isahc::get(uri).unwrap()
+    }
+
+    fn get_blocks_after_hash(&self, hash: &BlockHash) -> Response<Body> {
+        let uri = format!("{}/blocks/after/{:#x}", get_base_url(self), hash);
+
+        isahc::get(uri).unwrap()
+    }
+
     fn add_valid_block(&self) -> Response<Body> {
-        let last_block = self.get_last_block();
+        self.add_block(&create_valid_block(&self.get_last_block()))
+    }
 
-        let coinbase = Transaction {
-            sender: PERSON1.to_string(),
-            recipient: PERSON2.to_string(),
-            amount: BLOCK_SUBSIDY,
-        };
+    fn add_valid_block_with_request_id(&self, request_id: &str) -> Response<Body> {
+        let uri = format!("{}/blocks", get_base_url(self));
+        let body = serde_json::to_string(&create_valid_block(&self.get_last_block())).unwrap();
 
-        let valid_block = Block {
-            index: last_block.index + 1,
-            timestamp: 0,
-            nonce: 0,
-            previous_hash: last_block.hash,
-            hash: BlockHash::default(),
-            transactions: vec![coinbase],
-        };
+        let request = Request::post(uri)
+            .header("Content-Type", "application/json")
+            .header("X-Request-Id", request_id)
+            .body(body)
+            .unwrap();
 
-        self.add_block(&valid_block)
+        isahc::send(request).unwrap()
     }
 
     fn add_block(&self, block: &Block) -> Response<Body> {
@@ -87,10 +253,189 @@ impl Api for Server {
 
         post_request(uri, body)
     }
+
+    fn mine(&self) -> Response<Body> {
+        let uri = format!("{}/mine", get_base_url(self));
+
+        post_request(uri, String::new())
+    }
+
+    fn add_transaction_batch(&self, transactions: &serde_json::Value) -> serde_json::Value {
+        let uri = format!("{}/transactions/batch", get_base_url(self));
+        let mut response = post_request(uri, transactions.to_string());
+
+        assert_eq!(response.status().as_u16(), 200);
+
+        serde_json::from_str(&response.text().unwrap()).unwrap()
+    }
+
+    fn get_pending_transactions(&self) -> PendingTransactions {
+        let uri = format!("{}/transactions/pending", get_base_url(self));
+        let mut response = isahc::get(uri).unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+
+        let raw_body = response.text().unwrap();
+
+        serde_json::from_str(&raw_body).unwrap()
+    }
+
+    fn get_transaction(&self, hash: &str) -> Response<Body> {
+        let uri = format!("{}/transactions/{}", get_base_url(self), hash);
+
+        isahc::get(uri).unwrap()
+    }
+
+    fn get_status(&self) -> Status {
+        let uri = format!("{}/status", get_base_url(self));
+        let mut response = isahc::get(uri).unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+
+        let raw_body = response.text().unwrap();
+
+        serde_json::from_str(&raw_body).unwrap()
+    }
+
+    fn get_snapshot(&self) -> serde_json::Value {
+        let uri = format!("{}/snapshot", get_base_url(self));
+        let mut response = isahc::get(uri).unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+
+        serde_json::from_str(&response.text().unwrap()).unwrap()
+    }
+
+    fn verify_chain(&self) -> serde_json::Value {
+        let uri = format!("{}/chain/verify", get_base_url(self));
+        let mut response = isahc::get(uri).unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+
+        serde_json::from_str(&response.text().unwrap()).unwrap()
+    }
+
+    fn get_explorer_block(&self, index: u64) -> Response<Body> {
+        let uri = format!("{}/explorer/block/{}", get_base_url(self), index);
+
+        isahc::get(uri).unwrap()
+    }
+
+    fn get_difficulty(&self) -> serde_json::Value {
+        let uri = format!("{}/difficulty", get_base_url(self));
+        let mut response = isahc::get(uri).unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+
+        serde_json::from_str(&response.text().unwrap()).unwrap()
+    }
+
+    fn get_peers(&self) -> Vec<PeerInfo> {
+        let uri = format!("{}/peers", get_base_url(self));
+        let mut response = isahc::get(uri).unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+
+        serde_json::from_str(&response.text().unwrap()).unwrap()
+    }
+
+    fn add_peer(&self, address: &str) -> Response<Body> {
+        let uri = format!("{}/peers", get_base_url(self));
+        let body = serde_json::json!({ "address": address });
+
+        post_request(uri, body.to_string())
+    }
+
+    fn get_rejected_blocks(&self) -> Vec<RejectedBlock> {
+        let uri = format!("{}/admin/rejected-blocks", get_base_url(self));
+        let mut response = isahc::get(uri).unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+
+        let raw_body = response.text().unwrap();
+
+        serde_json::from_str(&raw_body).unwrap()
+    }
+
+    fn get_metrics(&self) -> String {
+        let uri = format!("{}/metrics", get_base_url(self));
+        let mut response = isahc::get(uri).unwrap();
+
+        assert_eq!(response.status().as_u16(), 200);
+
+        response.text().unwrap()
+    }
+
+    fn calculate_hash(&self, block: &Block) -> BlockHash {
+        let uri = format!("{}/util/hash", get_base_url(self));
+        let body = serde_json::json!({
+            "index": block.index,
+            "timestamp": block.timestamp,
+            "difficulty": block.difficulty,
+            "nonce": block.nonce,
+            "previous_hash": block.previous_hash,
+            "transactions": block.transactions,
+        });
+
+        let mut response = post_request(uri, body.to_string());
+
+        assert_eq!(response.status().as_u16(), 200);
+
+        let raw_body = response.text().unwrap();
+        let result: serde_json::Value = serde_json::from_str(&raw_body).unwrap();
+
+        serde_json::from_value(result["hash"].clone()).unwrap()
+    }
+
+    fn subscribe_blocks(&self) -> BlockSocket {
+        let uri = format!(
+            "ws://localhost:{}{}/ws",
+            self.config.port, self.config.api_base_path
+        );
+        let (socket, _) = tungstenite::connect(uri).unwrap();
+
+        if let MaybeTlsStream::Plain(stream) = socket.get_ref() {
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+        }
+
+        socket
+    }
+
+    fn call_rpc(&self, request: &serde_json::Value) -> Response<Body> {
+        let uri = format!("{}/rpc", get_base_url(self));
+
+        post_request(uri, request.to_string())
+    }
+}
+
+// Includes the base path the api is served under, if any
+pub fn get_base_url(server: &Server) -> String {
+    format!(
+        "http://localhost:{}{}",
+        server.config.port, server.config.api_base_path
+    )
 }
 
-fn get_base_url(server: &Server) -> String {
-    format!("http://localhost:{}", server.config.port)
+// Next block on top of the given one, paying the subsidy so it is valid
+fn create_valid_block(last_block: &Block) -> Block {
+    let coinbase = Transaction {
+        sender: PERSON1.to_string(),
+        recipient: PERSON2.to_string(),
+        amount: BLOCK_SUBSIDY,
+    };
+
+    Block {
+        version: 2,
+        index: last_block.index + 1,
+        timestamp: current_timestamp(),
+        difficulty: 0,
+        nonce: 0,
+        previous_hash: last_block.hash,
+        hash: BlockHash::default(),
+        transactions: vec![coinbase],
+    }
 }
 
 fn post_request(uri: String, body: String) -> Response<Body> {
@@ -9,7 +9,7 @@ use std::{
 use assert_cmd::cargo::cargo_bin;
 use tasklist::kill;
 
-pub const MINER_ADDRESS: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+pub const MINER_ADDRESS: &str = "3c8b6a8e1f0f4f6c2b7d8e9a0c1d2e3f405162738495a6b7c8d9eafb0c1d2e3f";
 
 pub struct Config {
     pub port: u16,
@@ -20,6 +20,12 @@ pub struct Config {
     pub difficulty: u32,
     pub transaction_waiting_ms: u64,
     pub miner_address: String,
+    pub tls: bool,
+    pub cors_allowed_origins: String,
+    pub max_blocks_per_minute: u32,
+    pub api_base_path: String,
+    pub json_rpc: bool,
+    pub numbers_as_strings: bool,
 }
 
 pub struct ServerBuilder {
@@ -38,6 +44,12 @@ impl ServerBuilder {
             max_blocks: 0,
             max_nonce: 0,
             miner_address: MINER_ADDRESS.to_string(),
+            tls: false,
+            cors_allowed_origins: String::new(),
+            max_blocks_per_minute: 120,
+            api_base_path: String::new(),
+            json_rpc: false,
+            numbers_as_strings: false,
         };
 
         ServerBuilder { config }
@@ -49,6 +61,12 @@ impl ServerBuilder {
         self
     }
 
+    pub fn transaction_waiting_ms(mut self, transaction_waiting_ms: u64) -> ServerBuilder {
+        self.config.transaction_waiting_ms = transaction_waiting_ms;
+
+        self
+    }
+
     pub fn port(mut self, port: u16) -> ServerBuilder {
         self.config.port = port;
 
@@ -62,6 +80,57 @@ impl ServerBuilder {
         self
     }
 
+    // Peer serving its api under a base path
+    pub fn peer_with_base_path(mut self, port: u16, base_path: &str) -> ServerBuilder {
+        let address = format!("http://localhost:{}{}", port, base_path);
+        self.config.peers.push(address);
+
+        self
+    }
+
+    // Serves the api over HTTPS with the self-signed certificate of the fixtures
+    pub fn tls(mut self) -> ServerBuilder {
+        self.config.tls = true;
+
+        self
+    }
+
+    pub fn cors_allowed_origins(mut self, origins: &str) -> ServerBuilder {
+        self.config.cors_allowed_origins = origins.to_string();
+
+        self
+    }
+
+    pub fn max_blocks_per_minute(mut self, max_blocks_per_minute: u32) -> ServerBuilder {
+        self.config.max_blocks_per_minute = max_blocks_per_minute;
+
+        self
+    }
+
+    pub fn api_base_path(mut self, api_base_path: &str) -> ServerBuilder {
+        self.config.api_base_path = api_base_path.to_string();
+
+        self
+    }
+
+    pub fn peer_sync_ms(mut self, peer_sync_ms: u64) -> ServerBuilder {
+        self.config.peer_sync_ms = peer_sync_ms;
+
+        self
+    }
+
+    pub fn json_rpc(mut self) -> ServerBuilder {
+        self.config.json_rpc = true;
+
+        self
+    }
+
+    pub fn numbers_as_strings(mut self) -> ServerBuilder {
+        self.config.numbers_as_strings = true;
+
+        self
+    }
+
     pub fn start(self) -> Server {
         Server::new(self.config)
     }
@@ -78,6 +147,15 @@ pub struct Server {
 #[allow(dead_code)]
 impl Server {
     fn start_process(config: &Config) -> Child {
+        let fixtures = format!("{}/tests/fixtures", env!("CARGO_MANIFEST_DIR"));
+        let (tls_cert_path, tls_key_path) = match config.tls {
+            true => (
+                format!("{}/cert.pem", fixtures),
+                format!("{}/key.pem", fixtures),
+            ),
+            false => (String::new(), String::new()),
+        };
+
         Command::new(cargo_bin("rust_blockchain"))
             .env("PORT", config.port.to_string())
             .env("PEERS", config.peers.join(","))
@@ -87,7 +165,22 @@ impl Server {
                 config.transaction_waiting_ms.to_string(),
             )
             .env("PEER_SYNC_MS", config.peer_sync_ms.to_string())
-            .env("MINER_ADDRESS", config.miner_address.to_string())
+            .env("MINER_ADDRESS", &config.miner_address)
+            // tests spend the rewards of the blocks they add right away
+            .env("COINBASE_MATURITY", "0")
+            .env("TLS_CERT_PATH", tls_cert_path)
+            .env("TLS_KEY_PATH", tls_key_path)
+            .env("CORS_ALLOWED_ORIGINS", &config.cors_allowed_origins)
+            .env("API_BASE_PATH", &config.api_base_path)
+            .env("JSON_RPC_ENABLED", config.json_rpc.to_string())
+            .env(
+                "API_NUMBERS_AS_STRINGS",
+                config.numbers_as_strings.to_string(),
+            )
+            .env(
+                "MAX_BLOCKS_PER_MINUTE",
+                config.max_blocks_per_minute.to_string(),
+            )
             .stdout(Stdio::piped())
             .stderr(Stdio::piped())
             .spawn()
@@ -130,7 +223,13 @@ impl Server {
         false
     }
 
-    fn wait_for_log_message(&mut self, message: &str) {
+    pub fn count_log_messages(&self, message: &str) -> usize {
+        let lines = self.output.lock().unwrap();
+
+        lines.iter().filter(|line| line.contains(message)).count()
+    }
+
+    pub fn wait_for_log_message(&mut self, message: &str) {
         let wait_time = Duration::from_millis(50);
         let max_wait_time = Duration::from_millis(500);
 
@@ -1,5 +1,13 @@
+// integration tests only run on windows, so their imports are unused elsewhere
+#![cfg_attr(not(windows), allow(unused_imports))]
+
 mod common;
 
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
 use common::{Api, ServerBuilder};
 use serial_test::serial;
 
@@ -24,6 +32,25 @@ fn test_should_receive_new_valid_blocks() {
     assert_eq!(last_follower_block, last_leader_block);
 }
 
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_sync_with_peer_under_base_path() {
+    let leader_node = ServerBuilder::new()
+        .port(8000)
+        .api_base_path("/node1")
+        .start();
+    let mut follower_node = ServerBuilder::new()
+        .port(8001)
+        .peer_with_base_path(8000, "/node1")
+        .start();
+
+    leader_node.add_valid_block();
+
+    follower_node.wait_for_peer_sync();
+    assert_eq!(follower_node.get_last_block(), leader_node.get_last_block());
+}
+
 #[test]
 #[serial]
 #[cfg(windows)]
@@ -62,3 +89,69 @@ fn test_should_send_new_blocks() {
     let last_follower_block = follower_node.get_last_block();
     assert_eq!(last_follower_block, last_leader_block);
 }
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_broadcast_posted_blocks() {
+    let mut follower_node = ServerBuilder::new().port(8000).start();
+    // the sync runs once at startup, so only the broadcast can send the block in time
+    let leader_node = ServerBuilder::new()
+        .port(8001)
+        .peer(8000)
+        .peer_sync_ms(60000)
+        .start();
+
+    leader_node.add_valid_block_with_request_id("trace-broadcast");
+
+    follower_node.wait_to_receive_block_in_api();
+    assert_eq!(follower_node.get_last_block(), leader_node.get_last_block());
+
+    // the follower logs the id of the request the leader received the block with
+    follower_node.wait_for_log_message("POST /blocks 200");
+    assert_eq!(follower_node.count_log_messages("[trace-broadcast]"), 1);
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_report_unreachable_peer() {
+    // nothing listens on the peer port
+    let node = ServerBuilder::new().port(8001).peer(8000).start();
+
+    let start = Instant::now();
+    let mut peers = node.get_peers();
+
+    while peers[0].last_error.is_none() && start.elapsed() < Duration::from_secs(5) {
+        thread::sleep(Duration::from_millis(100));
+        peers = node.get_peers();
+    }
+
+    assert_eq!(peers.len(), 1);
+    assert_eq!(peers[0].address, "http://localhost:8000");
+    assert!(!peers[0].reachable);
+    assert_eq!(peers[0].last_seen, None);
+    assert_eq!(peers[0].last_error, Some("Peer is unreachable".to_string()));
+}
+
+#[test]
+#[serial]
+#[cfg(windows)]
+fn test_should_add_peer_at_runtime() {
+    let node = ServerBuilder::new().port(8001).start();
+    assert!(node.get_peers().is_empty());
+
+    let res = node.add_peer("http://localhost:8000");
+    assert_eq!(res.status().as_u16(), 200);
+
+    let res = node.add_peer("http://localhost:8000");
+    assert_eq!(res.status().as_u16(), 409);
+
+    let res = node.add_peer("localhost:8002");
+    assert_eq!(res.status().as_u16(), 400);
+
+    let peers = node.get_peers();
+    assert_eq!(peers.len(), 1);
+    assert_eq!(peers[0].address, "http://localhost:8000");
+    assert_eq!(node.get_status().peer_count, 1);
+}